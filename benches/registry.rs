@@ -0,0 +1,128 @@
+//! Benchmarks of the registry parsing, cache update and instance selection on synthetic
+//! registries of 100, 1k and 10k instances, run with `cargo bench --features test-util`
+//!
+//! Budget: `cache_update`, a whole registry refresh of 10k instances, parsing and grouping
+//! by app included, takes less than 50 ms of one core, under 0.2 % of the 30 s refresh
+//! interval. The registry it fetches again is unchanged, the common case of a refresh. The
+//! budget is asserted by `cargo test --release -- --ignored refresh_budget`.
+
+extern crate criterion;
+extern crate eureka_client;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use eureka_client::test_util::{synthetic_applications, ReplayApi};
+use eureka_client::{BaseConfig, Codec, EurekaClient, JsonCodec, XmlCodec};
+
+/// Registry sizes as (apps, instances per app)
+const SIZES: &[(usize, usize)] = &[(10, 10), (100, 10), (1000, 10)];
+
+fn codecs() -> Vec<(&'static str, Arc<dyn Codec>)> {
+    vec![("xml", Arc::new(XmlCodec)), ("json", Arc::new(JsonCodec))]
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for &(apps, instances) in SIZES {
+        let size = format!("{}x{}", apps, instances);
+        group.throughput(Throughput::Elements((apps * instances) as u64));
+        for (name, codec) in codecs() {
+            let payload = codec
+                .encode_applications(&synthetic_applications(apps, instances))
+                .unwrap();
+            group.bench_with_input(BenchmarkId::new(name, &size), &payload, |b, payload| {
+                b.iter(|| codec.decode_applications(payload).unwrap())
+            });
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}-stream", name), &size),
+                &payload,
+                |b, payload| {
+                    b.iter(|| {
+                        codec
+                            .stream_instances(Box::new(Cursor::new(payload.clone())))
+                            .unwrap()
+                            .count()
+                    })
+                },
+            );
+            // The same registry again, every instance reused from the cache
+            let mut known = HashMap::new();
+            for app in codec.decode_applications(&payload).unwrap().applications {
+                known.insert(app.name, app.instances.into_iter().map(Arc::new).collect());
+            }
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}-reusing", name), &size),
+                &payload,
+                |b, payload| {
+                    b.iter(|| {
+                        codec
+                            .stream_instances_reusing(
+                                Box::new(Cursor::new(payload.clone())),
+                                &known,
+                            )
+                            .unwrap()
+                            .count()
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+/// Client reading the registry recorded in a temporary directory
+fn replay_client(name: &str, codec: Arc<dyn Codec>, apps: usize, instances: usize) -> EurekaClient {
+    let dir = env::temp_dir().join(format!(
+        "eureka-bench-{}-{}-{}x{}",
+        std::process::id(),
+        name,
+        apps,
+        instances
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let payload = codec
+        .encode_applications(&synthetic_applications(apps, instances))
+        .unwrap();
+    fs::write(dir.join(format!("apps.{}", name)), payload).unwrap();
+
+    let mut config = BaseConfig::default();
+    config.eureka.register_with_eureka = false;
+    EurekaClient::with_api(config, Arc::new(ReplayApi::new(&dir).with_codec(codec)))
+}
+
+fn cache_update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cache_update");
+    for &(apps, instances) in SIZES {
+        let size = format!("{}x{}", apps, instances);
+        group.throughput(Throughput::Elements((apps * instances) as u64));
+        for (name, codec) in codecs() {
+            let client = replay_client(name, codec, apps, instances);
+            group.bench_function(BenchmarkId::new(name, &size), |b| {
+                b.iter(|| client.refresh_registry().unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn selection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("selection");
+    for &(apps, instances) in SIZES {
+        let client = replay_client("xml", Arc::new(XmlCodec), apps, instances);
+        client.refresh_registry().unwrap();
+        let app = format!("APP-{:04}", apps / 2);
+        group.bench_function(
+            BenchmarkId::new("resolve", format!("{}x{}", apps, instances)),
+            |b| b.iter(|| client.resolve(&app).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, cache_update, selection);
+criterion_main!(benches);
@@ -0,0 +1,89 @@
+//! Registration of an `actix-web` server while it runs, behind the `actix` feature
+
+use std::future::Future;
+use std::io;
+use std::net::TcpListener;
+use std::sync::Arc;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::rt::task::spawn_blocking;
+use actix_web::{web, App, Error, HttpResponse, HttpServer};
+
+use crate::{BaseConfig, EurekaClient};
+
+/// Adds the Spring Boot actuator style `/info` and `/health` endpoints of `client`, e.g.
+/// `App::new().configure(actuator(client))`
+///
+/// `/health` answers 503 while the client can't reach eureka.
+pub fn actuator(client: Arc<EurekaClient>) -> impl Fn(&mut web::ServiceConfig) + Clone {
+    move |config| {
+        config
+            .app_data(web::Data::from(Arc::clone(&client)))
+            .route("/info", web::get().to(info))
+            .route("/health", web::get().to(health));
+    }
+}
+
+async fn info(client: web::Data<EurekaClient>) -> HttpResponse {
+    HttpResponse::Ok().json(client.actuator_info())
+}
+
+async fn health(client: web::Data<EurekaClient>) -> HttpResponse {
+    let report = client.health();
+    let status = if report.eureka_reachable {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    HttpResponse::build(status).json(report.to_actuator())
+}
+
+/// Runs the `app` built for each worker on `listener`, registered with eureka while it runs
+///
+/// The instance registers with the port of `listener` before the server starts, `app` gets
+/// the client to add to its data or to pass to [`actuator`]. Once `shutdown` completes the
+/// instance is deregistered, so that peers stop calling it, then the server stops gracefully.
+pub async fn serve_actix<F, T, B, S>(
+    mut config: BaseConfig,
+    listener: TcpListener,
+    app: F,
+    shutdown: S,
+) -> io::Result<()>
+where
+    F: Fn(Arc<EurekaClient>) -> App<T> + Send + Clone + 'static,
+    T: ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<B>,
+            Error = Error,
+            InitError = (),
+        > + 'static,
+    B: MessageBody + 'static,
+    S: Future<Output = ()> + 'static,
+{
+    config.instance.port.value = listener.local_addr()?.port();
+    config.instance.port.enabled = true;
+    // Registration blocks until eureka answers
+    let client = spawn_blocking(move || {
+        let client = Arc::new(EurekaClient::new(config));
+        client.start();
+        client
+    })
+    .await
+    .map_err(io::Error::other)?;
+
+    let app_client = Arc::clone(&client);
+    let server = HttpServer::new(move || app(Arc::clone(&app_client)))
+        .disable_signals()
+        .listen(listener)?
+        .run();
+    let handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown.await;
+        let _ = spawn_blocking(move || client.deregister()).await;
+        handle.stop(true).await;
+    });
+    server.await
+}
@@ -0,0 +1,176 @@
+//! Addresses of the instances the client calls, see [`crate::EurekaClient::resolve`]
+
+use std::collections::HashMap;
+
+use crate::host_port;
+use crate::rest::structures::Instance;
+
+/// Instance of an app with the address to call it on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedInstance {
+    pub app: String,
+    /// Instance id, the host name when the instance has none
+    pub instance_id: String,
+    pub host_name: String,
+    pub ip_addr: String,
+    pub port: u16,
+    /// Whether `port` is the secure port
+    pub secure: bool,
+    /// `https` for the secure port, `http` otherwise
+    pub scheme: &'static str,
+    pub zone: Option<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl ResolvedInstance {
+    /// Address of `instance` on the port it enables, the secure one when both are enabled
+    /// and `prefer_secure`, e.g. when the client uses ssl
+    ///
+    /// An instance enabling neither port is called on the preferred one.
+    pub(crate) fn new(instance: &Instance, prefer_secure: bool) -> Self {
+        let secure = match (instance.port.enabled, instance.secure_port.enabled) {
+            (true, true) | (false, false) => prefer_secure,
+            (plain, _) => !plain,
+        };
+        ResolvedInstance::on_port(instance, secure)
+    }
+
+    /// Address of `instance` on its secure port when `secure`, on its port otherwise,
+    /// whether the instance enables it or not
+    pub(crate) fn on_port(instance: &Instance, secure: bool) -> Self {
+        let port = if secure {
+            instance.secure_port.value
+        } else {
+            instance.port.value
+        };
+        ResolvedInstance {
+            app: instance.app.clone(),
+            instance_id: instance
+                .instance_id
+                .clone()
+                .unwrap_or_else(|| instance.host_name.clone()),
+            host_name: instance.host_name.clone(),
+            ip_addr: instance.ip_addr.clone(),
+            port,
+            secure,
+            scheme: if secure { "https" } else { "http" },
+            zone: instance.zone().map(str::to_string),
+            metadata: instance
+                .metadata
+                .as_ref()
+                .map(|metadata| metadata.map.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Address of `app` on `host` rather than on an instance in the registry, e.g. a DNS name
+    /// of the app, whose instance id is `host:port`
+    pub(crate) fn on_host(app: &str, host: &str, port: u16, secure: bool) -> Self {
+        ResolvedInstance {
+            app: app.to_string(),
+            instance_id: host_port(host, port),
+            host_name: host.to_string(),
+            ip_addr: host.to_string(),
+            port,
+            secure,
+            scheme: if secure { "https" } else { "http" },
+            zone: None,
+            metadata: HashMap::new(),
+        }
+    }
+
+    /// `ip:port`, with IPv6 literals in brackets
+    pub fn address(&self) -> String {
+        host_port(&self.ip_addr, self.port)
+    }
+
+    /// `scheme://ip:port`
+    pub fn base_url(&self) -> String {
+        format!("{}://{}", self.scheme, self.address())
+    }
+
+    /// Url of `path` on the instance
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url(), path.trim_start_matches('/'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::structures::{PortData, SecurePort};
+    use crate::test_util::{instance, MockEurekaServer};
+    use crate::{BaseConfig, EurekaClient};
+
+    #[test]
+    fn test_resolved_instance() {
+        let mut instance = Instance {
+            app: "ORDERS".to_string(),
+            ip_addr: "fd00::1".to_string(),
+            port: PortData::new(8080, true),
+            secure_port: SecurePort::new(8443, true),
+            ..Instance::default()
+        };
+        instance.set_metadata_value("zone", "zone-a");
+
+        let resolved = ResolvedInstance::new(&instance, false);
+        assert_eq!(resolved.instance_id, "localhost");
+        assert_eq!(resolved.address(), "[fd00::1]:8080");
+        assert_eq!(resolved.url("/orders/1"), "http://[fd00::1]:8080/orders/1");
+        assert_eq!(resolved.zone.as_deref(), Some("zone-a"));
+        assert_eq!(
+            resolved.metadata.get("zone").map(String::as_str),
+            Some("zone-a")
+        );
+
+        let resolved = ResolvedInstance::new(&instance, true);
+        assert_eq!(resolved.base_url(), "https://[fd00::1]:8443");
+    }
+
+    #[test]
+    fn test_port_selection() {
+        let mut instance = Instance {
+            port: PortData::new(8080, false),
+            secure_port: SecurePort::new(8443, true),
+            ..Instance::default()
+        };
+        let resolved = ResolvedInstance::new(&instance, false);
+        assert!(resolved.secure);
+        assert_eq!(resolved.port, 8443);
+
+        instance.port.enabled = true;
+        instance.secure_port.enabled = false;
+        let resolved = ResolvedInstance::new(&instance, true);
+        assert_eq!(resolved.scheme, "http");
+        assert_eq!(resolved.port, 8080);
+
+        instance.port.enabled = false;
+        assert_eq!(ResolvedInstance::new(&instance, true).port, 8443);
+
+        let resolved = ResolvedInstance::on_host("ORDERS", "orders.service.internal", 80, false);
+        assert_eq!(
+            resolved.url("/orders"),
+            "http://orders.service.internal:80/orders"
+        );
+        assert_eq!(resolved.instance_id, "orders.service.internal:80");
+    }
+
+    #[test]
+    fn test_resolve_app_case_insensitively() {
+        let server = MockEurekaServer::start().unwrap();
+        server.register(instance("ORDERS").ip_addr("10.0.0.1").up().build());
+        let mut config = BaseConfig::default();
+        config.eureka = server.config();
+        config.eureka.register_with_eureka = false;
+        let client = EurekaClient::new(config);
+        client.refresh_registry().unwrap();
+
+        // As resolve_all and the discovery do
+        let resolved = client.resolve("orders").unwrap();
+        assert_eq!(resolved.app, "ORDERS");
+        assert_eq!(resolved.ip_addr, "10.0.0.1");
+        assert_eq!(client.resolve_all("orders").len(), 1);
+        assert!(client.resolve("billing").is_none());
+    }
+}
@@ -0,0 +1,207 @@
+//! Settings of the requests to other apps, see [`EurekaConfig::apps`](crate::EurekaConfig::apps)
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+
+use crate::EurekaError;
+
+/// Settings of the requests [`make_request`](crate::EurekaClient::make_request) and `call`
+/// send to an app
+///
+/// The requests go through the HTTP client shared with the eureka requests, but for the apps
+/// with a `timeout`, which reqwest only sets per client.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppClientConfig {
+    /// Request timeout in milli-seconds, 0 for the 30 s of the shared client
+    pub timeout: usize,
+    /// Path the request paths are relative to, e.g. `/api/v2`
+    pub base_path: String,
+    /// Headers sent with every request, unless the request sets them
+    pub headers: HashMap<String, String>,
+    /// Call the app over https on its secure port when true, over http on its port when
+    /// false, whichever ports the instances enable. By default on the port they enable
+    pub secure: Option<bool>,
+    /// `host:port` of the instances to call instead of the ones in the registry, e.g. a
+    /// local build of the app or the instances traffic is pinned to during an incident.
+    /// Called over https when `secure` is true
+    pub endpoints: Vec<String>,
+}
+
+impl AppClientConfig {
+    /// `path` under the base path
+    pub(crate) fn path(&self, path: &str) -> String {
+        let base_path = self.base_path.trim_matches('/');
+        let path = path.trim_start_matches('/');
+        if base_path.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}/{}", base_path, path)
+        }
+    }
+
+    /// `headers`, checking that they are valid HTTP headers
+    pub(crate) fn default_headers(&self, app: &str) -> Result<HeaderMap, EurekaError> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let invalid = |e: &dyn std::error::Error| {
+                EurekaError::InvalidConfig("apps", format!("header {} of {}: {}", name, app, e))
+            };
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(&e))?;
+            let value = HeaderValue::from_str(value).map_err(|e| invalid(&e))?;
+            headers.insert(name, value);
+        }
+        Ok(headers)
+    }
+
+    /// `endpoints` as host and port, checking that they have a port
+    pub(crate) fn parse_endpoints(&self, app: &str) -> Result<Vec<(String, u16)>, EurekaError> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| {
+                parse_endpoint(endpoint).ok_or_else(|| {
+                    let message = format!("endpoint {} of {}: expected host:port", endpoint, app);
+                    EurekaError::InvalidConfig("apps", message)
+                })
+            })
+            .collect()
+    }
+
+    /// Client of the requests to the app, when it needs its own timeout
+    fn client(&self) -> Result<Option<Client>, EurekaError> {
+        if self.timeout == 0 {
+            return Ok(None);
+        }
+        Client::builder()
+            .timeout(Duration::from_millis(self.timeout as u64))
+            .build()
+            .map(Some)
+            .map_err(EurekaError::network)
+    }
+}
+
+/// Host and port of `host:port`, the host of `[ipv6]:port` without its brackets
+fn parse_endpoint(endpoint: &str) -> Option<(String, u16)> {
+    let (host, port) = endpoint.trim().rsplit_once(':')?;
+    let port = port.parse().ok()?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        None
+    } else {
+        Some((host.to_string(), port))
+    }
+}
+
+/// HTTP clients of the requests to the apps, the apps with a timeout having their own
+#[derive(Debug)]
+pub(crate) struct AppClients {
+    shared: Client,
+    /// Clients by upper case app name
+    apps: HashMap<String, Client>,
+    /// Default headers by upper case app name
+    headers: HashMap<String, HeaderMap>,
+}
+
+impl AppClients {
+    /// Clients of `apps` sharing `shared`, the apps with invalid settings using it as is
+    pub(crate) fn new(apps: &HashMap<String, AppClientConfig>, shared: Client) -> Self {
+        let mut clients = HashMap::new();
+        let mut headers = HashMap::new();
+        for (app, config) in apps {
+            match config.client() {
+                Ok(Some(client)) => {
+                    clients.insert(app.to_uppercase(), client);
+                }
+                Ok(None) => {}
+                Err(e) => error!("Failed to create the client of {}: {}", app, e),
+            }
+            match config.default_headers(app) {
+                Ok(defaults) if !defaults.is_empty() => {
+                    headers.insert(app.to_uppercase(), defaults);
+                }
+                Ok(_) => {}
+                Err(e) => error!("Ignoring the headers of {}: {}", app, e),
+            }
+        }
+        AppClients {
+            shared,
+            apps: clients,
+            headers,
+        }
+    }
+
+    /// Client of the requests to `app`, matched case-insensitively
+    pub(crate) fn get(&self, app: &str) -> &Client {
+        self.apps.get(&app.to_uppercase()).unwrap_or(&self.shared)
+    }
+
+    /// Client shared by the eureka requests and the requests to the apps
+    pub(crate) fn shared(&self) -> &Client {
+        &self.shared
+    }
+
+    /// Adds the default headers of `app` that `headers` doesn't set
+    pub(crate) fn add_default_headers(&self, app: &str, headers: &mut HeaderMap) {
+        if let Some(defaults) = self.headers.get(&app.to_uppercase()) {
+            for (name, value) in defaults {
+                if !headers.contains_key(name) {
+                    headers.insert(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_client_config() {
+        let mut config = AppClientConfig {
+            base_path: "/reports/".to_string(),
+            ..AppClientConfig::default()
+        };
+        assert_eq!(config.path("/daily"), "reports/daily");
+        assert_eq!(AppClientConfig::default().path("/daily"), "daily");
+
+        config
+            .headers
+            .insert("X-Tenant".to_string(), "acme".to_string());
+        let headers = config.default_headers("REPORTS").unwrap();
+        assert_eq!(headers["x-tenant"], "acme");
+
+        let mut apps = HashMap::new();
+        apps.insert("reports".to_string(), config.clone());
+        let clients = AppClients::new(&apps, Client::new());
+        assert!(clients.apps.is_empty());
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("1"));
+        clients.add_default_headers("REPORTS", &mut headers);
+        assert_eq!(headers["x-tenant"], "acme");
+        headers.insert("x-tenant", HeaderValue::from_static("other"));
+        clients.add_default_headers("Reports", &mut headers);
+        assert_eq!(headers["x-tenant"], "other");
+        config.timeout = 500;
+        apps.insert("reports".to_string(), config.clone());
+        assert!(AppClients::new(&apps, Client::new())
+            .apps
+            .contains_key("REPORTS"));
+
+        config
+            .headers
+            .insert("X Tenant".to_string(), "acme".to_string());
+        assert!(config.default_headers("REPORTS").is_err());
+
+        config.endpoints = vec!["localhost:8080".to_string(), "[::1]:8081".to_string()];
+        assert_eq!(
+            config.parse_endpoints("REPORTS").unwrap(),
+            vec![("localhost".to_string(), 8080), ("::1".to_string(), 8081)]
+        );
+        config.endpoints.push("localhost".to_string());
+        assert!(config.parse_endpoints("REPORTS").is_err());
+    }
+}
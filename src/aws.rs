@@ -1,56 +1,161 @@
 //! AWS support
 
 use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 use reqwest::{Client, Response};
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 
+use crate::rest::structures::{
+    AmazonMetaDataType, AppMetaDataType, DataCenterInfo, DcNameType, Instance,
+};
+use crate::EurekaError;
+
+/// Header carrying the IMDSv2 session token
+const TOKEN_HEADER: &str = "X-aws-ec2-metadata-token";
+const TOKEN_TTL_HEADER: &str = "X-aws-ec2-metadata-token-ttl-seconds";
+/// Lifetime requested for the IMDSv2 session token, in seconds
+const TOKEN_TTL_SECS: &str = "21600";
+/// Time given to the metadata service to answer when detecting EC2
+pub(crate) const DETECT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Client of the EC2 instance metadata service
+///
+/// Uses IMDSv2 session tokens, falling back to IMDSv1 when no token can be obtained.
 #[derive(Debug, Clone)]
 pub struct AwsMetadata {
     client: Client,
     host: String,
 }
 
+impl Default for AwsMetadata {
+    fn default() -> Self {
+        AwsMetadata::with_host(AwsMetadata::METADATA_ENDPOINT)
+    }
+}
+
 impl AwsMetadata {
     pub const METADATA_ENDPOINT: &'static str = "169.254.169.254";
+    /// Data center info class expected by the eureka server for Amazon instances
+    pub const AMAZON_INFO_CLASS: &'static str = "com.netflix.appinfo.AmazonInfo";
 
     pub fn new(config: &HashMap<String, Value>) -> Self {
+        AwsMetadata::with_host(
+            config
+                .get("host")
+                .and_then(Value::as_str)
+                .unwrap_or(AwsMetadata::METADATA_ENDPOINT),
+        )
+    }
+
+    pub fn with_host(host: &str) -> Self {
         AwsMetadata {
             client: Client::new(),
-            host: config
-                .get("host")
-                .map(|host| host.as_str().unwrap().to_string())
-                .unwrap_or_else(|| String::from("169.254.169.254")),
+            host: host.to_string(),
+        }
+    }
+
+    /// Gives up on metadata requests after `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        match Client::builder().timeout(timeout).build() {
+            Ok(client) => self.client = client,
+            Err(e) => error!("Failed to set the metadata request timeout: {}", e),
         }
+        self
     }
 
-    fn fetch_metadata(&self) -> HashMap<&'static str, String> {
-        let mut results = HashMap::with_capacity(11);
-        results.insert("ami-id", self.lookup_metadata_key("ami-id"));
-        results.insert("instance-id", self.lookup_metadata_key("instance-id"));
-        results.insert("instance-type", self.lookup_metadata_key("instance-type"));
-        results.insert("local-ipv4", self.lookup_metadata_key("local-ipv4"));
-        results.insert("local-hostname", self.lookup_metadata_key("local-hostname"));
+    /// Whether the metadata service answers, i.e. this runs on EC2
+    pub fn is_available(&self) -> bool {
+        let token = self.fetch_token();
+        self.get(token.as_ref().map(String::as_str), "meta-data/instance-id")
+            .is_ok()
+    }
+
+    /// Reason of the imminent termination of this instance, if any
+    pub fn termination_notice(&self) -> Option<String> {
+        let token = self.fetch_token();
+        let token = token.as_ref().map(String::as_str);
+        // 404 until a spot interruption is scheduled
+        if let Ok(mut response) = self.get(token, "meta-data/spot/instance-action") {
+            let action = response.text().unwrap_or_default();
+            return Some(format!("spot interruption {}", action));
+        }
+        let lifecycle_state = self
+            .get(token, "meta-data/autoscaling/target-lifecycle-state")
+            .and_then(|mut response| response.text())
+            .unwrap_or_default();
+        if lifecycle_state.trim() == "Terminated" {
+            return Some("auto scaling termination".to_string());
+        }
+        None
+    }
+
+    /// Data center info of this EC2 instance, to register with
+    pub fn data_center_info(&self) -> DataCenterInfo {
+        DataCenterInfo {
+            class: Some(AwsMetadata::AMAZON_INFO_CLASS.to_string()),
+            name: DcNameType::Amazon,
+            metadata: Some(self.amazon_metadata()),
+        }
+    }
+
+    /// Metadata of this EC2 instance, keys missing from the metadata service are left empty
+    pub fn amazon_metadata(&self) -> AmazonMetaDataType {
+        let mut metadata = self.fetch_metadata();
+        let mut take = |key: &str| metadata.remove(key).unwrap_or_default();
+        AmazonMetaDataType {
+            ami_launch_index: take("ami-launch-index"),
+            local_hostname: take("local-hostname"),
+            availability_zone: take("availability-zone"),
+            instance_id: take("instance-id"),
+            public_ipv4: take("public-ipv4"),
+            public_hostname: take("public-hostname"),
+            ami_manifest_patch: take("ami-manifest-path"),
+            local_ipv4: take("local-ipv4"),
+            hostname: take("hostname"),
+            ami_id: take("ami-id"),
+            instance_type: take("instance-type"),
+        }
+    }
+
+    /// Metadata keys of this EC2 instance, without the ones the metadata service lacks
+    pub fn fetch_metadata(&self) -> HashMap<&'static str, String> {
+        let token = self.fetch_token();
+        let token = token.as_ref().map(String::as_str);
+        let mut results = HashMap::with_capacity(14);
+        for &key in &[
+            "ami-id",
+            "ami-launch-index",
+            "ami-manifest-path",
+            "instance-id",
+            "instance-type",
+            "local-ipv4",
+            "local-hostname",
+            "hostname",
+            "public-hostname",
+            "public-ipv4",
+            "mac",
+        ] {
+            results.insert(key, self.lookup_metadata_key(token, key));
+        }
         results.insert(
             "availability-zone",
-            self.lookup_metadata_key("placement/availability-zone"),
+            self.lookup_metadata_key(token, "placement/availability-zone"),
         );
-        results.insert(
-            "public-hostname",
-            self.lookup_metadata_key("public-hostname"),
-        );
-        results.insert("public-ipv4", self.lookup_metadata_key("public-ipv4"));
-        results.insert("mac", self.lookup_metadata_key("mac"));
         results.insert(
             "accountId",
-            self.lookup_instance_identity()
+            self.lookup_instance_identity(token)
                 .and_then(|i| i["accountId"].as_str().map(|id| id.to_owned())),
         );
-        let mac = results["mac"].clone().unwrap();
-        results.insert(
-            "vpc-id",
-            self.lookup_metadata_key(&format!("network/interfaces/macs/{}/vpc-id", mac)),
-        );
+        let vpc_id = results["mac"].clone().and_then(|mac| {
+            self.lookup_metadata_key(token, &format!("network/interfaces/macs/{}/vpc-id", mac))
+        });
+        results.insert("vpc-id", vpc_id);
         debug!("Found Instance AWS Metadata: {:?}", results);
         results
             .into_iter()
@@ -62,12 +167,35 @@ impl AwsMetadata {
             })
     }
 
-    fn lookup_metadata_key(&self, key: &str) -> Option<String> {
+    /// IMDSv2 session token, `None` when the metadata service only supports IMDSv1
+    fn fetch_token(&self) -> Option<String> {
         let mut response = self
             .client
-            .get(&format!("http://{}/latest/meta-data/{}", self.host, key))
+            .put(&format!("http://{}/latest/api/token", self.host))
+            .header(TOKEN_TTL_HEADER, TOKEN_TTL_SECS)
             .send()
             .and_then(Response::error_for_status)
+            .map_err(|e| {
+                debug!("No IMDSv2 token, falling back to IMDSv1: {}", e);
+                e
+            })
+            .ok()?;
+        response.text().ok()
+    }
+
+    fn get(&self, token: Option<&str>, path: &str) -> reqwest::Result<Response> {
+        let mut request = self
+            .client
+            .get(&format!("http://{}/latest/{}", self.host, path));
+        if let Some(token) = token {
+            request = request.header(TOKEN_HEADER, token);
+        }
+        request.send().and_then(Response::error_for_status)
+    }
+
+    fn lookup_metadata_key(&self, token: Option<&str>, key: &str) -> Option<String> {
+        let mut response = self
+            .get(token, &format!("meta-data/{}", key))
             .map_err(|e| {
                 error!("Error requesting metadata key: {}", e);
                 e
@@ -76,15 +204,9 @@ impl AwsMetadata {
         response.text().ok()
     }
 
-    fn lookup_instance_identity(&self) -> Option<HashMap<String, Value>> {
+    fn lookup_instance_identity(&self, token: Option<&str>) -> Option<HashMap<String, Value>> {
         let mut response = self
-            .client
-            .get(&format!(
-                "http://{}/latest/dynamic/instance-identity/document",
-                self.host
-            ))
-            .send()
-            .and_then(Response::error_for_status)
+            .get(token, "dynamic/instance-identity/document")
             .map_err(|e| {
                 error!("Error requesting instance identity document: {}", e);
                 e
@@ -93,3 +215,319 @@ impl AwsMetadata {
         response.json().ok()
     }
 }
+
+impl DataCenterInfo {
+    /// Amazon data center info when running on EC2, `MyOwn` otherwise
+    pub fn detect() -> Self {
+        let aws = AwsMetadata::default().with_timeout(DETECT_TIMEOUT);
+        if aws.is_available() {
+            info!("EC2 metadata service found, registering as Amazon");
+            aws.data_center_info()
+        } else {
+            debug!("No EC2 metadata service, registering as MyOwn");
+            DataCenterInfo::default()
+        }
+    }
+}
+
+/// EC2 address an Amazon instance registers with
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AdvertisePolicy {
+    /// Private IPv4 and host name, for callers within the VPC
+    AdvertisePrivateIp,
+    /// Public IPv4 as both `ipAddr` and `hostName`, for peered or internet-facing setups
+    AdvertisePublicIp,
+    /// Public host name and IPv4
+    AdvertisePublicHostname,
+}
+
+impl Default for AdvertisePolicy {
+    fn default() -> Self {
+        AdvertisePolicy::AdvertisePrivateIp
+    }
+}
+
+impl AdvertisePolicy {
+    /// Sets the `ipAddr` and `hostName` of an Amazon instance from its EC2 metadata
+    ///
+    /// Public policies fall back to the private address when the instance has no public one.
+    pub fn apply(self, instance: &mut Instance) {
+        let metadata = match &instance.data_center_info.metadata {
+            Some(metadata) if instance.data_center_info.name == DcNameType::Amazon => metadata,
+            _ => return,
+        };
+        let has_public_ip = !metadata.public_ipv4.is_empty();
+        let (ip_addr, host_name) = match self {
+            AdvertisePolicy::AdvertisePublicIp if has_public_ip => {
+                (metadata.public_ipv4.clone(), metadata.public_ipv4.clone())
+            }
+            AdvertisePolicy::AdvertisePublicHostname
+                if has_public_ip && !metadata.public_hostname.is_empty() =>
+            {
+                (
+                    metadata.public_ipv4.clone(),
+                    metadata.public_hostname.clone(),
+                )
+            }
+            _ => (metadata.local_ipv4.clone(), metadata.local_hostname.clone()),
+        };
+        if !ip_addr.is_empty() {
+            instance.ip_addr = ip_addr;
+        }
+        if !host_name.is_empty() {
+            instance.host_name = host_name;
+        }
+    }
+}
+
+/// Watches the EC2 metadata for a spot interruption or an auto scaling termination
+#[derive(Debug)]
+pub struct TerminationWatcher {
+    aws: AwsMetadata,
+    is_running: Arc<AtomicBool>,
+}
+
+impl TerminationWatcher {
+    /// Interval between two polls of the metadata, as advised by AWS
+    pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+    pub fn new(aws: AwsMetadata) -> Self {
+        TerminationWatcher {
+            aws,
+            is_running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Polls the metadata in the background, calling `on_termination` once on a termination notice
+    pub fn start<F>(&self, on_termination: F)
+    where
+        F: FnOnce(String) + Send + 'static,
+    {
+        self.is_running.store(true, Ordering::Relaxed);
+
+        let is_running = Arc::clone(&self.is_running);
+        let aws = self.aws.clone();
+        thread::spawn(move || {
+            while is_running.load(Ordering::Relaxed) {
+                if let Some(reason) = aws.termination_notice() {
+                    warn!("Instance termination notice: {}", reason);
+                    on_termination(reason);
+                    return;
+                }
+                thread::sleep(TerminationWatcher::POLL_INTERVAL);
+            }
+        });
+    }
+}
+
+impl Drop for TerminationWatcher {
+    fn drop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Client of the ECS task metadata endpoint (v4), for containers on ECS and Fargate
+#[derive(Debug, Clone)]
+pub struct EcsMetadata {
+    client: Client,
+    uri: String,
+}
+
+/// Identity and network of the running ECS task
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EcsTaskInfo {
+    pub cluster: String,
+    pub task_arn: String,
+    pub family: String,
+    pub revision: String,
+    pub availability_zone: Option<String>,
+    pub container_name: String,
+    pub ip_addr: Option<String>,
+    pub private_dns_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EcsContainer {
+    name: String,
+    #[serde(default)]
+    networks: Vec<EcsNetwork>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EcsNetwork {
+    #[serde(rename = "IPv4Addresses", default)]
+    ipv4_addresses: Vec<String>,
+    #[serde(rename = "PrivateDNSName")]
+    private_dns_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EcsTask {
+    cluster: String,
+    #[serde(rename = "TaskARN")]
+    task_arn: String,
+    family: String,
+    revision: String,
+    availability_zone: Option<String>,
+}
+
+impl EcsMetadata {
+    pub const URI_ENV: &'static str = "ECS_CONTAINER_METADATA_URI_V4";
+
+    pub const CLUSTER: &'static str = "ecs.cluster";
+    pub const TASK_ARN: &'static str = "ecs.task-arn";
+    pub const TASK_DEFINITION: &'static str = "ecs.task-definition";
+
+    pub fn new(uri: &str) -> Self {
+        EcsMetadata {
+            client: Client::new(),
+            uri: uri.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Metadata endpoint of `ECS_CONTAINER_METADATA_URI_V4`, `None` outside of ECS
+    pub fn from_env() -> Option<Self> {
+        env::var(EcsMetadata::URI_ENV)
+            .ok()
+            .map(|uri| EcsMetadata::new(&uri))
+    }
+
+    pub fn fetch(&self) -> Result<EcsTaskInfo, EurekaError> {
+        let container: EcsContainer = self.get_json(&self.uri)?;
+        let task: EcsTask = self.get_json(&format!("{}/task", self.uri))?;
+        let network = container.networks.into_iter().next();
+        let info = EcsTaskInfo {
+            cluster: task.cluster,
+            task_arn: task.task_arn,
+            family: task.family,
+            revision: task.revision,
+            availability_zone: task.availability_zone,
+            container_name: container.name,
+            ip_addr: network
+                .as_ref()
+                .and_then(|network| network.ipv4_addresses.first().cloned()),
+            private_dns_name: network.and_then(|network| network.private_dns_name),
+        };
+        debug!("Found ECS task metadata: {:?}", info);
+        Ok(info)
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, EurekaError> {
+        self.client
+            .get(url)
+            .send()
+            .and_then(Response::error_for_status)
+            .and_then(|mut response| response.json())
+            .map_err(EurekaError::network)
+    }
+}
+
+impl EcsTaskInfo {
+    /// Id of the task, the last segment of its ARN
+    pub fn task_id(&self) -> &str {
+        self.task_arn.rsplit('/').next().unwrap_or(&self.task_arn)
+    }
+
+    /// Fills the address, zone and identity of `instance` with the ones of the task
+    ///
+    /// The instance id is only set when the instance has none.
+    pub fn apply_to(&self, instance: &mut Instance) {
+        if let Some(ip_addr) = &self.ip_addr {
+            instance.ip_addr = ip_addr.clone();
+            instance.host_name = self
+                .private_dns_name
+                .clone()
+                .unwrap_or_else(|| ip_addr.clone());
+        }
+        if instance.instance_id.is_none() {
+            instance.instance_id = Some(format!("{}:{}", self.task_id(), self.container_name));
+        }
+        if let Some(zone) = &self.availability_zone {
+            instance.set_metadata_value(AppMetaDataType::ZONE, zone);
+        }
+        instance.set_metadata_value(EcsMetadata::CLUSTER, &self.cluster);
+        instance.set_metadata_value(EcsMetadata::TASK_ARN, &self.task_arn);
+        instance.set_metadata_value(
+            EcsMetadata::TASK_DEFINITION,
+            &format!("{}:{}", self.family, self.revision),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advertise_policy() {
+        let mut instance = Instance::default();
+        instance.data_center_info = DataCenterInfo {
+            class: Some(AwsMetadata::AMAZON_INFO_CLASS.to_string()),
+            name: DcNameType::Amazon,
+            metadata: Some(AmazonMetaDataType {
+                local_ipv4: "10.0.0.1".to_string(),
+                local_hostname: "ip-10-0-0-1.ec2.internal".to_string(),
+                public_ipv4: "54.0.0.1".to_string(),
+                public_hostname: "ec2-54-0-0-1.compute-1.amazonaws.com".to_string(),
+                ..AmazonMetaDataType::default()
+            }),
+        };
+
+        AdvertisePolicy::AdvertisePublicIp.apply(&mut instance);
+        assert_eq!(instance.ip_addr, "54.0.0.1");
+        assert_eq!(instance.host_name, "54.0.0.1");
+
+        AdvertisePolicy::AdvertisePublicHostname.apply(&mut instance);
+        assert_eq!(instance.host_name, "ec2-54-0-0-1.compute-1.amazonaws.com");
+
+        AdvertisePolicy::AdvertisePrivateIp.apply(&mut instance);
+        assert_eq!(instance.ip_addr, "10.0.0.1");
+        assert_eq!(instance.host_name, "ip-10-0-0-1.ec2.internal");
+
+        // No public address, keep the private one
+        instance
+            .data_center_info
+            .metadata
+            .as_mut()
+            .unwrap()
+            .public_ipv4 = String::new();
+        AdvertisePolicy::AdvertisePublicIp.apply(&mut instance);
+        assert_eq!(instance.ip_addr, "10.0.0.1");
+    }
+
+    #[test]
+    fn test_apply_ecs_task_info() {
+        let info = EcsTaskInfo {
+            cluster: "default".to_string(),
+            task_arn:
+                "arn:aws:ecs:us-west-2:111122223333:task/default/158d1c8083dd49d6b527399fd6414f5c"
+                    .to_string(),
+            family: "curltest".to_string(),
+            revision: "26".to_string(),
+            availability_zone: Some("us-west-2d".to_string()),
+            container_name: "curl".to_string(),
+            ip_addr: Some("10.0.2.106".to_string()),
+            private_dns_name: Some("ip-10-0-2-106.us-west-2.compute.internal".to_string()),
+        };
+        let mut instance = Instance::default();
+        info.apply_to(&mut instance);
+        assert_eq!(instance.ip_addr, "10.0.2.106");
+        assert_eq!(
+            instance.host_name,
+            "ip-10-0-2-106.us-west-2.compute.internal"
+        );
+        assert_eq!(
+            instance.instance_id.as_deref(),
+            Some("158d1c8083dd49d6b527399fd6414f5c:curl")
+        );
+        assert_eq!(instance.zone(), Some("us-west-2d"));
+        assert_eq!(
+            instance.metadata_value(EcsMetadata::TASK_DEFINITION),
+            Some("curltest:26")
+        );
+    }
+}
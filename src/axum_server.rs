@@ -0,0 +1,45 @@
+//! Registration of an `axum` server while it serves, behind the `axum` feature
+
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+
+use axum::Router;
+use tokio::net::TcpListener;
+use tokio::task::spawn_blocking;
+
+use crate::{BaseConfig, EurekaClient};
+
+/// Serves `router` on `listener`, registered with eureka while it serves
+///
+/// The instance registers with the port of `listener` before the server starts. The
+/// client is the state of `router`, handlers get it with `State<Arc<EurekaClient>>`.
+/// The instance is deregistered once the server shut down gracefully on `shutdown`.
+pub async fn serve_axum<F>(
+    mut config: BaseConfig,
+    listener: TcpListener,
+    router: Router<Arc<EurekaClient>>,
+    shutdown: F,
+) -> io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    config.instance.port.value = listener.local_addr()?.port();
+    config.instance.port.enabled = true;
+    // Registration blocks until eureka answers
+    let client = spawn_blocking(move || {
+        let client = Arc::new(EurekaClient::new(config));
+        client.start();
+        client
+    })
+    .await
+    .map_err(io::Error::other)?;
+
+    let result = axum::serve(listener, router.with_state(Arc::clone(&client)))
+        .with_graceful_shutdown(shutdown)
+        .await;
+    spawn_blocking(move || client.deregister())
+        .await
+        .map_err(io::Error::other)?;
+    result
+}
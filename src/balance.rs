@@ -0,0 +1,281 @@
+//! `tower` service discovery and load balancing over the instances of an app, behind the
+//! `tower` feature
+//!
+//! The instances are read from a [`ServiceDiscovery`], the registry cache of the client by
+//! default, polled with a `tokio` timer, so they have to be polled within a `tokio` runtime.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::fmt;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::{ready, Stream};
+use tokio::time::{interval, Interval, MissedTickBehavior};
+use tower::balance::p2c::Balance;
+use tower::discover::Change;
+use tower::load::{CompleteOnResponse, PendingRequestsDiscover};
+use tower::{BoxError, Layer, Service};
+
+use crate::discovery::ServiceDiscovery;
+use crate::rest::structures::Instance;
+use crate::EurekaClient;
+
+/// [`Discover`](tower::discover::Discover) of the UP instances of an app
+///
+/// Instances are keyed by instance id, or host name without one, and turned into services by
+/// `make_service`. The instances are compared with the known ones on every refresh.
+pub struct EurekaDiscover<F, S> {
+    discovery: Arc<dyn ServiceDiscovery>,
+    app: String,
+    make_service: F,
+    known: HashSet<String>,
+    changes: VecDeque<Change<String, S>>,
+    refresh_interval: Duration,
+    // Created on the first poll, timers need the runtime
+    refresh: Option<Interval>,
+}
+
+// Nothing is pinned in place, the services are only moved out of the queue
+impl<F, S> Unpin for EurekaDiscover<F, S> {}
+
+impl<F, S> fmt::Debug for EurekaDiscover<F, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EurekaDiscover")
+            .field("app", &self.app)
+            .field("known", &self.known)
+            .finish()
+    }
+}
+
+impl<F, S> EurekaDiscover<F, S>
+where
+    F: FnMut(&Instance) -> S,
+{
+    /// Discovers the UP instances of `app` in `discovery`, checked every `refresh_interval`
+    pub fn new(
+        discovery: Arc<dyn ServiceDiscovery>,
+        app: &str,
+        make_service: F,
+        refresh_interval: Duration,
+    ) -> Self {
+        EurekaDiscover {
+            discovery,
+            app: app.to_string(),
+            make_service,
+            known: HashSet::new(),
+            changes: VecDeque::new(),
+            refresh_interval,
+            refresh: None,
+        }
+    }
+
+    /// Queues the instances gone from the cache and the new ones
+    fn refresh_changes(&mut self) {
+        let up_instances = self.discovery.instances(&self.app);
+        let instances: HashMap<&str, &Instance> = up_instances
+            .iter()
+            .map(|instance| (instance_key(instance), instance))
+            .collect();
+        let removed: Vec<String> = self
+            .known
+            .iter()
+            .filter(|key| !instances.contains_key(key.as_str()))
+            .cloned()
+            .collect();
+        for key in removed {
+            self.known.remove(&key);
+            self.changes.push_back(Change::Remove(key));
+        }
+        for (key, instance) in instances {
+            if self.known.insert(key.to_string()) {
+                let service = (self.make_service)(instance);
+                self.changes
+                    .push_back(Change::Insert(key.to_string(), service));
+            }
+        }
+    }
+}
+
+impl<F, S> Stream for EurekaDiscover<F, S>
+where
+    F: FnMut(&Instance) -> S,
+{
+    type Item = Result<Change<String, S>, Infallible>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(change) = this.changes.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+            let refresh_interval = this.refresh_interval;
+            let refresh = this.refresh.get_or_insert_with(|| {
+                let mut refresh = interval(refresh_interval);
+                refresh.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                refresh
+            });
+            // The first tick is immediate
+            ready!(refresh.poll_tick(cx));
+            this.refresh_changes();
+        }
+    }
+}
+
+/// Layer balancing the requests over the UP instances of an app, see
+/// [`EurekaClient::balance`]
+///
+/// The wrapped "service" is the `FnMut(&Instance) -> S` making the service of an instance.
+/// Requests go to the least loaded of two random instances, by number of pending requests.
+pub struct EurekaBalance<Req> {
+    discovery: Arc<dyn ServiceDiscovery>,
+    app: String,
+    refresh_interval: Duration,
+    _req: PhantomData<fn(Req)>,
+}
+
+impl<Req> Clone for EurekaBalance<Req> {
+    fn clone(&self) -> Self {
+        EurekaBalance {
+            discovery: Arc::clone(&self.discovery),
+            app: self.app.clone(),
+            refresh_interval: self.refresh_interval,
+            _req: PhantomData,
+        }
+    }
+}
+
+impl<Req> fmt::Debug for EurekaBalance<Req> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EurekaBalance")
+            .field("app", &self.app)
+            .field("refresh_interval", &self.refresh_interval)
+            .finish()
+    }
+}
+
+impl<Req> EurekaBalance<Req> {
+    /// Balances over the UP instances of `app` in `discovery`, checked every 30 seconds
+    pub fn new(discovery: Arc<dyn ServiceDiscovery>, app: &str) -> Self {
+        EurekaBalance {
+            discovery,
+            app: app.to_string(),
+            refresh_interval: Duration::from_secs(30),
+            _req: PhantomData,
+        }
+    }
+
+    /// Compares the registry cache with the known instances every `refresh_interval`
+    pub fn with_refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_interval = refresh_interval;
+        self
+    }
+}
+
+impl<F, S, Req> Layer<F> for EurekaBalance<Req>
+where
+    F: FnMut(&Instance) -> S,
+    S: Service<Req>,
+    S::Error: Into<BoxError>,
+{
+    type Service = Balance<PendingRequestsDiscover<EurekaDiscover<F, S>>, Req>;
+
+    fn layer(&self, make_service: F) -> Self::Service {
+        let discover = EurekaDiscover::new(
+            Arc::clone(&self.discovery),
+            &self.app,
+            make_service,
+            self.refresh_interval,
+        );
+        Balance::new(PendingRequestsDiscover::new(
+            discover,
+            CompleteOnResponse::default(),
+        ))
+    }
+}
+
+impl EurekaClient {
+    /// Discovers the UP instances of `app`, each turned into a service by `make_service`
+    ///
+    /// The registry cache is checked for changes every `registry_fetch_interval`.
+    pub fn discover<F, S>(&self, app: &str, make_service: F) -> EurekaDiscover<F, S>
+    where
+        F: FnMut(&Instance) -> S,
+    {
+        EurekaDiscover::new(
+            self.discovery(),
+            app,
+            make_service,
+            self.registry_fetch_interval(),
+        )
+    }
+
+    /// Layer balancing requests of type `Req` over the UP instances of `app`
+    pub fn balance<Req>(&self, app: &str) -> EurekaBalance<Req> {
+        EurekaBalance::new(self.discovery(), app)
+            .with_refresh_interval(self.registry_fetch_interval())
+    }
+
+    fn registry_fetch_interval(&self) -> Duration {
+        Duration::from_millis(self.config.eureka.registry_fetch_interval.max(1) as u64)
+    }
+}
+
+fn instance_key(instance: &Instance) -> &str {
+    instance
+        .instance_id
+        .as_deref()
+        .unwrap_or(&instance.host_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::RegistryDiscovery;
+    use crate::registry::AppCache;
+    use crate::test_util::instance;
+    use std::future::poll_fn;
+
+    async fn next_change<F>(discover: &mut EurekaDiscover<F, String>) -> String
+    where
+        F: FnMut(&Instance) -> String,
+    {
+        match poll_fn(|cx| Pin::new(&mut *discover).poll_next(cx)).await {
+            Some(Ok(Change::Insert(key, service))) => format!("+{} {}", key, service),
+            Some(Ok(Change::Remove(key))) => format!("-{}", key),
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_discover() {
+        let mut apps = HashMap::new();
+        apps.insert(
+            "APP".to_string(),
+            vec![
+                Arc::new(instance("APP").id("a").up().build()),
+                Arc::new(instance("APP").id("b").down().build()),
+            ],
+        );
+        let app_cache = Arc::new(AppCache::new(apps));
+        let mut discover = EurekaDiscover::new(
+            Arc::new(RegistryDiscovery::new(
+                Arc::clone(&app_cache),
+                Duration::from_secs(30),
+            )),
+            "APP",
+            |instance: &Instance| format!("{}:{}", instance.app, instance.status),
+            Duration::from_secs(30),
+        );
+        assert_eq!(next_change(&mut discover).await, "+a APP:UP");
+
+        let instances = vec![Arc::new(instance("APP").id("b").up().build())];
+        app_cache.update(|apps| apps.insert("APP".to_string(), instances));
+        // Picked up on the next refresh
+        assert_eq!(next_change(&mut discover).await, "-a");
+        assert_eq!(next_change(&mut discover).await, "+b APP:UP");
+    }
+}
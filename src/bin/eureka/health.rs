@@ -0,0 +1,195 @@
+//! `eureka health [APP]`, calling the health check url of the instances to find the ones
+//! whose eureka status is stale
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use eureka_client::{EurekaApi, EurekaError, EurekaRestClient, Instance, StatusType};
+use reqwest::Client;
+
+use crate::output::{instance_id, Output};
+
+/// Health check of an instance
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Check {
+    app: String,
+    instance_id: String,
+    /// Status reported by eureka
+    status: String,
+    url: Option<String>,
+    /// Status code of the health check response
+    code: Option<u16>,
+    /// Reason the health check url couldn't be reached
+    error: Option<String>,
+    latency_ms: Option<u128>,
+    verdict: Verdict,
+}
+
+/// Eureka status cross-referenced with the health check
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Verdict {
+    /// The health check agrees with the eureka status
+    Ok,
+    /// UP in eureka but the health check fails, e.g. a registration outliving its process
+    Stale,
+    /// Not UP in eureka but the health check succeeds
+    Healthy,
+    /// The instance has no health check url
+    Unchecked,
+}
+
+impl Verdict {
+    fn of(status: StatusType, healthy: Option<bool>) -> Verdict {
+        match (status, healthy) {
+            (_, None) => Verdict::Unchecked,
+            (StatusType::Up, Some(false)) => Verdict::Stale,
+            (StatusType::Up, Some(true)) => Verdict::Ok,
+            (_, Some(true)) => Verdict::Healthy,
+            (_, Some(false)) => Verdict::Ok,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Verdict::Ok => "ok",
+            Verdict::Stale => "STALE",
+            Verdict::Healthy => "healthy",
+            Verdict::Unchecked => "-",
+        }
+    }
+}
+
+/// Health check url of `instance`, the secure one when it has no plain one
+fn health_url(instance: &Instance) -> Option<String> {
+    if !instance.health_check_url.is_empty() {
+        Some(instance.health_check_url.clone())
+    } else {
+        instance.secure_health_check_url().map(str::to_string)
+    }
+}
+
+fn check(http: &Client, instance: &Instance) -> Check {
+    let url = health_url(instance);
+    let mut check = Check {
+        app: instance.app.clone(),
+        instance_id: instance_id(instance).to_string(),
+        status: instance.status.to_string(),
+        url: url.clone(),
+        code: None,
+        error: None,
+        latency_ms: None,
+        verdict: Verdict::Unchecked,
+    };
+    let url = match url {
+        Some(url) => url,
+        None => return check,
+    };
+    let start = Instant::now();
+    let healthy = match http.get(&url).send() {
+        Ok(resp) => {
+            check.code = Some(resp.status().as_u16());
+            resp.status().is_success()
+        }
+        Err(e) => {
+            check.error = Some(e.to_string());
+            false
+        }
+    };
+    check.latency_ms = Some(start.elapsed().as_millis());
+    check.verdict = Verdict::of(instance.status, Some(healthy));
+    check
+}
+
+/// Checks the instances of `app`, or of all apps, `concurrency` at a time
+pub fn health(
+    client: &EurekaRestClient,
+    output: &Output,
+    app: Option<&str>,
+    concurrency: usize,
+    timeout: Duration,
+) -> Result<(), EurekaError> {
+    let mut instances = match app {
+        Some(app) => client.get_instances_by_app(app)?,
+        None => client.get_all_instances()?,
+    };
+    instances.sort_by(|a, b| (&a.app, instance_id(a)).cmp(&(&b.app, instance_id(b))));
+    let http = Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(EurekaError::Network)?;
+
+    let next = AtomicUsize::new(0);
+    let checks = Mutex::new(vec![None; instances.len()]);
+    thread::scope(|scope| {
+        for _ in 0..concurrency.min(instances.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let instance = match instances.get(index) {
+                    Some(instance) => instance,
+                    None => break,
+                };
+                let check = check(&http, instance);
+                checks.lock().unwrap()[index] = Some(check);
+            });
+        }
+    });
+    let checks: Vec<Check> = checks.into_inner().unwrap().into_iter().flatten().collect();
+
+    if output.is_json() {
+        return output.json(&checks);
+    }
+    let rows: Vec<Vec<String>> = checks
+        .iter()
+        .map(|check| {
+            let health = match (check.code, &check.error) {
+                (Some(code), _) => code.to_string(),
+                (None, Some(error)) => error.clone(),
+                (None, None) => "-".to_string(),
+            };
+            let latency = check
+                .latency_ms
+                .map_or("-".to_string(), |latency| format!("{} ms", latency));
+            vec![
+                check.app.clone(),
+                check.instance_id.clone(),
+                check.status.clone(),
+                health,
+                latency,
+                check.verdict.name().to_string(),
+            ]
+        })
+        .collect();
+    output.table(
+        &["APP", "ID", "STATUS", "HEALTH", "LATENCY", "VERDICT"],
+        &rows,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verdict() {
+        assert_eq!(Verdict::of(StatusType::Up, Some(true)), Verdict::Ok);
+        assert_eq!(Verdict::of(StatusType::Up, Some(false)), Verdict::Stale);
+        assert_eq!(
+            Verdict::of(StatusType::OutOfService, Some(true)),
+            Verdict::Healthy
+        );
+        assert_eq!(Verdict::of(StatusType::Down, Some(false)), Verdict::Ok);
+        assert_eq!(Verdict::of(StatusType::Up, None), Verdict::Unchecked);
+
+        let instance = Instance::default();
+        assert_eq!(health_url(&instance), None);
+        let http = Client::new();
+        let check = check(&http, &instance);
+        assert_eq!(check.verdict, Verdict::Unchecked);
+        assert_eq!(check.code, None);
+    }
+}
@@ -0,0 +1,133 @@
+//! `eureka apps`, `eureka instances APP` and `eureka instance APP ID`
+
+use std::collections::BTreeMap;
+
+use eureka_client::{EurekaApi, EurekaError, EurekaRestClient, Instance, StatusType};
+
+use crate::output::{instance_id, instance_row, Output, INSTANCE_HEADER};
+
+/// Apps of the registry, with their instances by status
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AppSummary {
+    app: String,
+    instances: usize,
+    up: usize,
+    down: usize,
+    other: usize,
+}
+
+pub fn apps(client: &EurekaRestClient, output: &Output) -> Result<(), EurekaError> {
+    let mut apps: BTreeMap<String, AppSummary> = BTreeMap::new();
+    for instance in client.get_all_instances()? {
+        let summary = apps
+            .entry(instance.app.clone())
+            .or_insert_with(|| AppSummary {
+                app: instance.app.clone(),
+                ..AppSummary::default()
+            });
+        summary.instances += 1;
+        match instance.status {
+            StatusType::Up => summary.up += 1,
+            StatusType::Down => summary.down += 1,
+            _ => summary.other += 1,
+        }
+    }
+    let apps: Vec<AppSummary> = apps.into_iter().map(|(_, summary)| summary).collect();
+    if output.is_json() {
+        return output.json(&apps);
+    }
+    let rows: Vec<Vec<String>> = apps
+        .iter()
+        .map(|summary| {
+            vec![
+                summary.app.clone(),
+                summary.instances.to_string(),
+                summary.up.to_string(),
+                summary.down.to_string(),
+                summary.other.to_string(),
+            ]
+        })
+        .collect();
+    output.table(&["APP", "INSTANCES", "UP", "DOWN", "OTHER"], &rows);
+    Ok(())
+}
+
+pub fn instances(client: &EurekaRestClient, output: &Output, app: &str) -> Result<(), EurekaError> {
+    let mut instances = client.get_instances_by_app(app)?;
+    instances.sort_by(|a, b| instance_id(a).cmp(instance_id(b)));
+    if output.is_json() {
+        return output.json(&instances);
+    }
+    let rows: Vec<Vec<String>> = instances.iter().map(instance_row).collect();
+    output.table(INSTANCE_HEADER, &rows);
+    Ok(())
+}
+
+pub fn instance(
+    client: &EurekaRestClient,
+    output: &Output,
+    app: &str,
+    id: &str,
+) -> Result<(), EurekaError> {
+    let instance = client.get_instance_by_app_and_instance(app, id)?;
+    if output.is_json() {
+        return output.json(&instance);
+    }
+    output.table(&["FIELD", "VALUE"], &details(&instance));
+    Ok(())
+}
+
+/// Field and value rows of `instance`, metadata keys sorted
+fn details(instance: &Instance) -> Vec<Vec<String>> {
+    let mut rows = vec![
+        vec!["app".to_string(), instance.app.clone()],
+        vec!["instanceId".to_string(), instance_id(instance).to_string()],
+        vec!["hostName".to_string(), instance.host_name.clone()],
+        vec!["ipAddr".to_string(), instance.ip_addr.clone()],
+        vec!["status".to_string(), instance.status.to_string()],
+        vec![
+            "overriddenStatus".to_string(),
+            instance
+                .overridden_status
+                .as_ref()
+                .map_or("-".to_string(), ToString::to_string),
+        ],
+        vec![
+            "port".to_string(),
+            format!(
+                "{} (enabled: {})",
+                instance.port.value, instance.port.enabled
+            ),
+        ],
+        vec![
+            "securePort".to_string(),
+            format!(
+                "{} (enabled: {})",
+                instance.secure_port.value, instance.secure_port.enabled
+            ),
+        ],
+        vec!["vipAddress".to_string(), instance.vip_address.clone()],
+        vec![
+            "secureVipAddress".to_string(),
+            instance.secure_vip_address.clone(),
+        ],
+        vec!["homePageUrl".to_string(), instance.home_page_url.clone()],
+        vec![
+            "statusPageUrl".to_string(),
+            instance.status_page_url.clone(),
+        ],
+        vec![
+            "healthCheckUrl".to_string(),
+            instance.health_check_url.clone(),
+        ],
+    ];
+    if let Some(ref metadata) = instance.metadata {
+        let mut entries: Vec<_> = metadata.map.iter().collect();
+        entries.sort();
+        for (key, value) in entries {
+            rows.push(vec![format!("metadata.{}", key), value.clone()]);
+        }
+    }
+    rows
+}
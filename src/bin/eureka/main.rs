@@ -0,0 +1,159 @@
+//! Command line client of a eureka server, built with the `cli` feature, e.g.
+//! `eureka --url http://localhost:8761/eureka instances ORDERS`
+
+extern crate clap;
+extern crate ctrlc;
+extern crate eureka_client;
+extern crate reqwest;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+mod health;
+mod inspect;
+mod output;
+mod register;
+mod snapshot;
+mod status;
+mod watch;
+
+use std::process;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use eureka_client::{EurekaError, EurekaRestClient};
+
+use crate::output::Output;
+use crate::register::RegisterArgs;
+use crate::snapshot::Format;
+use crate::status::StatusArgs;
+
+/// Inspects and updates the registry of a eureka server
+#[derive(Debug, Parser)]
+#[command(name = "eureka", version)]
+struct Cli {
+    /// Service url of the eureka server
+    #[arg(
+        long,
+        global = true,
+        env = "EUREKA_URL",
+        default_value = "http://localhost:8761/eureka"
+    )]
+    url: String,
+    /// Print JSON instead of tables
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Lists the apps with their number of instances
+    Apps,
+    /// Lists the instances of an app
+    Instances { app: String },
+    /// Shows an instance of an app
+    Instance { app: String, id: String },
+    /// Registers an instance and sends its heartbeats until Ctrl-C
+    Register(RegisterArgs),
+    /// Deregisters an instance of an app
+    Deregister { app: String, id: String },
+    /// Writes the registry to stdout
+    Export {
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+    },
+    /// Registers the instances of a file written by `export`
+    Import {
+        file: String,
+        /// Service url of the eureka server the instances are registered with, default
+        /// `--url`
+        #[arg(long)]
+        to: Option<String>,
+        /// Format of the file, detected by default
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+        /// Sends the heartbeats of the instances until Ctrl-C, then deregisters them
+        #[arg(long)]
+        keep_alive: bool,
+    },
+    /// Overrides the status of an instance, or clears its override
+    Status {
+        app: String,
+        id: String,
+        #[command(flatten)]
+        args: StatusArgs,
+    },
+    /// Calls the health check url of the instances, of an app or of all apps, and tells
+    /// the ones UP in eureka but failing their health check
+    Health {
+        app: Option<String>,
+        /// Health checks sent at a time
+        #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u64).range(1..))]
+        concurrency: u64,
+        /// Seconds a health check may take
+        #[arg(long, default_value_t = 5)]
+        timeout: u64,
+    },
+    /// Prints the instances added, removed and changed, of an app or of all apps, until
+    /// Ctrl-C
+    Watch {
+        app: Option<String>,
+        /// Seconds between polls
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+    },
+}
+
+fn run(cli: Cli) -> Result<(), EurekaError> {
+    // The rate limit of the apps would throttle the imports and heartbeats of many instances
+    let client = EurekaRestClient::new(cli.url).without_rate_limit();
+    let output = Output::new(cli.json);
+    match cli.command {
+        Command::Apps => inspect::apps(&client, &output),
+        Command::Instances { app } => inspect::instances(&client, &output, &app),
+        Command::Instance { app, id } => inspect::instance(&client, &output, &app, &id),
+        Command::Register(args) => register::register(&client, &args),
+        Command::Deregister { app, id } => register::deregister(&client, &app, &id),
+        Command::Export { format } => snapshot::export(&client, format),
+        Command::Import {
+            file,
+            to,
+            format,
+            keep_alive,
+        } => {
+            let client = match to {
+                Some(to) => EurekaRestClient::new(to).without_rate_limit(),
+                None => client,
+            };
+            snapshot::import(&client, &file, format, keep_alive)
+        }
+        Command::Status { app, id, args } => status::status(&client, &app, &id, &args),
+        Command::Health {
+            app,
+            concurrency,
+            timeout,
+        } => health::health(
+            &client,
+            &output,
+            app.as_deref(),
+            concurrency as usize,
+            Duration::from_secs(timeout),
+        ),
+        Command::Watch { app, interval } => watch::watch(
+            &client,
+            &output,
+            app.as_deref(),
+            Duration::from_secs(interval),
+        ),
+    }
+}
+
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("eureka: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,108 @@
+//! Tables and JSON printed by the commands
+
+use eureka_client::{EurekaError, Instance, ParseError};
+use serde::Serialize;
+
+/// Output format picked with `--json`
+#[derive(Debug, Clone, Copy)]
+pub struct Output {
+    json: bool,
+}
+
+impl Output {
+    pub fn new(json: bool) -> Self {
+        Output { json }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.json
+    }
+
+    /// Prints `value` as pretty JSON
+    pub fn json<T: Serialize>(&self, value: &T) -> Result<(), EurekaError> {
+        let json = serde_json::to_string_pretty(value)
+            .map_err(|e| EurekaError::ParseError(ParseError::new(e.to_string())))?;
+        println!("{}", json);
+        Ok(())
+    }
+
+    /// Prints `value` as JSON on one line, e.g. one per event of a stream
+    pub fn json_line<T: Serialize>(&self, value: &T) -> Result<(), EurekaError> {
+        let json = serde_json::to_string(value)
+            .map_err(|e| EurekaError::ParseError(ParseError::new(e.to_string())))?;
+        println!("{}", json);
+        Ok(())
+    }
+
+    /// Prints `rows` under `header`, in aligned columns
+    pub fn table(&self, header: &[&str], rows: &[Vec<String>]) {
+        print!("{}", table(header, rows));
+    }
+}
+
+/// Instance id, the host name when the instance has none
+pub fn instance_id(instance: &Instance) -> &str {
+    instance
+        .instance_id
+        .as_deref()
+        .unwrap_or(&instance.host_name)
+}
+
+/// Instance columns of the tables
+pub const INSTANCE_HEADER: &[&str] = &["ID", "HOST", "IP", "PORT", "STATUS", "ZONE"];
+
+pub fn instance_row(instance: &Instance) -> Vec<String> {
+    let port = if instance.secure_port.enabled && !instance.port.enabled {
+        instance.secure_port.value
+    } else {
+        instance.port.value
+    };
+    vec![
+        instance_id(instance).to_string(),
+        instance.host_name.clone(),
+        instance.ip_addr.clone(),
+        port.to_string(),
+        instance.status.to_string(),
+        instance.zone().unwrap_or("-").to_string(),
+    ]
+}
+
+fn table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let header: Vec<String> = header.iter().map(|title| title.to_string()).collect();
+    let mut table = String::new();
+    for row in Some(&header).into_iter().chain(rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table() {
+        let rows = vec![
+            vec!["ORDERS".to_string(), "2".to_string()],
+            vec!["PAYMENT-GATEWAY".to_string(), "10".to_string()],
+        ];
+        assert_eq!(
+            table(&["APP", "INSTANCES"], &rows),
+            "APP              INSTANCES\n\
+             ORDERS           2\n\
+             PAYMENT-GATEWAY  10\n"
+        );
+    }
+}
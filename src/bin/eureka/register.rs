@@ -0,0 +1,175 @@
+//! `eureka register` and `eureka deregister`, for services which don't register themselves,
+//! e.g. databases or legacy apps
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use clap::Args;
+use eureka_client::{EurekaApi, EurekaError, EurekaRestClient, Instance, InstanceBuilder};
+use eureka_client::{LeaseInfo, StatusType};
+
+use crate::output::instance_id;
+
+#[derive(Debug, Args)]
+pub struct RegisterArgs {
+    /// App of the instance
+    #[arg(long)]
+    app: String,
+    /// Host name of the instance
+    #[arg(long)]
+    host: String,
+    /// Port of the instance
+    #[arg(long)]
+    port: u16,
+    /// Seconds eureka keeps the instance without heartbeat, heartbeats are sent every
+    /// third of it
+    #[arg(long, default_value_t = LeaseInfo::DEFAULT_DURATION_IN_SECS)]
+    ttl: u32,
+    /// Instance id, default `HOST:APP:PORT`
+    #[arg(long)]
+    id: Option<String>,
+    /// Ip address of the instance, default the host
+    #[arg(long)]
+    ip: Option<String>,
+    /// VIP address of the instance, default the app
+    #[arg(long)]
+    vip: Option<String>,
+    /// Health check url of the instance
+    #[arg(long)]
+    health_url: Option<String>,
+    /// Metadata of the instance, repeated
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_metadata)]
+    metadata: Vec<(String, String)>,
+}
+
+fn parse_metadata(entry: &str) -> Result<(String, String), String> {
+    match entry.find('=') {
+        Some(index) => Ok((entry[..index].to_string(), entry[index + 1..].to_string())),
+        None => Err(format!("expected KEY=VALUE, got `{}`", entry)),
+    }
+}
+
+/// UP instance registered by `args`
+fn instance(args: &RegisterArgs) -> Result<Instance, EurekaError> {
+    if args.ttl == 0 {
+        return Err(EurekaError::InvalidConfig(
+            "ttl",
+            "must be at least 1 s".to_string(),
+        ));
+    }
+    let mut builder = InstanceBuilder::new(&args.app)
+        .host_name(&args.host)
+        .ip_addr(args.ip.as_deref().unwrap_or(&args.host))
+        .port(args.port, true)
+        .status(StatusType::Up)
+        .lease_info(LeaseInfo {
+            renewal_interval_in_secs: Some((args.ttl / 3).max(1)),
+            duration_in_secs: Some(args.ttl),
+            ..LeaseInfo::default()
+        });
+    if let Some(ref id) = args.id {
+        builder = builder.instance_id(id);
+    }
+    if let Some(ref vip) = args.vip {
+        builder = builder.vip_address(vip);
+    }
+    if let Some(ref url) = args.health_url {
+        builder = builder.health_check_url(url);
+    }
+    for (key, value) in &args.metadata {
+        builder = builder.metadata(key, value);
+    }
+    builder.build()
+}
+
+/// Registers the instance, then sends its heartbeats until Ctrl-C and deregisters it
+pub fn register(client: &EurekaRestClient, args: &RegisterArgs) -> Result<(), EurekaError> {
+    let instance = instance(args)?;
+    let interval = Duration::from_secs(u64::from((args.ttl / 3).max(1)));
+    register_until_interrupted(client, &[instance], interval)
+}
+
+/// Registers `instances`, sends their heartbeats every `interval` until Ctrl-C, then
+/// deregisters them
+pub fn register_until_interrupted(
+    client: &EurekaRestClient,
+    instances: &[Instance],
+    interval: Duration,
+) -> Result<(), EurekaError> {
+    let (stop, stopped) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = stop.send(());
+    })
+    .map_err(|e| EurekaError::UnexpectedState(format!("Failed to handle Ctrl-C: {}", e)))?;
+
+    for instance in instances {
+        client.register(&instance.app, instance)?;
+        eprintln!("Registered {} {}", instance.app, instance_id(instance));
+    }
+    eprintln!("Heartbeat every {} s until Ctrl-C", interval.as_secs());
+    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+        for instance in instances {
+            let (app, id) = (&instance.app, instance_id(instance));
+            match client.send_heartbeat(app, id) {
+                Err(ref e) if e.is_not_found() => {
+                    eprintln!("eureka: {} {} was evicted, registering it again", app, id);
+                    if let Err(e) = client.register(app, instance) {
+                        eprintln!("eureka: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("eureka: {}", e),
+                Ok(()) => {}
+            }
+        }
+    }
+    for instance in instances {
+        deregister(client, &instance.app, instance_id(instance))?;
+    }
+    Ok(())
+}
+
+pub fn deregister(client: &EurekaRestClient, app: &str, id: &str) -> Result<(), EurekaError> {
+    client.deregister(app, id)?;
+    eprintln!("Deregistered {} {}", app, id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: RegisterArgs,
+    }
+
+    #[test]
+    fn test_instance() {
+        let cli = Cli::parse_from(vec![
+            "register",
+            "--app",
+            "POSTGRES",
+            "--host",
+            "db-1",
+            "--port",
+            "5432",
+            "--ttl",
+            "30",
+            "--metadata",
+            "role=primary",
+        ]);
+        let instance = instance(&cli.args).unwrap();
+        assert_eq!(instance.instance_id.as_deref(), Some("db-1:POSTGRES:5432"));
+        assert_eq!(instance.ip_addr, "db-1");
+        assert_eq!(instance.status, StatusType::Up);
+        let lease_info = instance.lease_info.as_ref().unwrap();
+        assert_eq!(lease_info.renewal_interval_in_secs, Some(10));
+        assert_eq!(lease_info.duration_in_secs, Some(30));
+        assert_eq!(instance.metadata_value("role"), Some("primary"));
+
+        assert!(parse_metadata("role").is_err());
+    }
+}
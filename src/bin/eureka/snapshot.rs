@@ -0,0 +1,126 @@
+//! `eureka export` and `eureka import FILE`, copying the registry of a server, e.g. to seed
+//! a test server or record the `apps` response of a `ReplayApi`
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use clap::ValueEnum;
+use eureka_client::{Application, Applications, Codec, EurekaApi, EurekaError};
+use eureka_client::{EurekaRestClient, Instance, JsonCodec, XmlCodec};
+
+use crate::output::instance_id;
+use crate::register::register_until_interrupted;
+
+/// Heartbeat interval of the instances imported with `--keep-alive`
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Json,
+    Xml,
+}
+
+impl Format {
+    /// Format of `document`, XML when it starts with a tag
+    fn detect(document: &[u8]) -> Format {
+        match document.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'<') => Format::Xml,
+            _ => Format::Json,
+        }
+    }
+
+    fn codec(self) -> &'static dyn Codec {
+        match self {
+            Format::Json => &JsonCodec,
+            Format::Xml => &XmlCodec,
+        }
+    }
+}
+
+/// Applications document of `instances`, apps and instances sorted
+fn snapshot(instances: Vec<Instance>) -> Applications {
+    let mut apps: BTreeMap<String, Vec<Instance>> = BTreeMap::new();
+    for instance in instances {
+        apps.entry(instance.app.clone()).or_default().push(instance);
+    }
+    Applications {
+        versions_delta: None,
+        apps_hashcode: None,
+        applications: apps
+            .into_iter()
+            .map(|(name, mut instances)| {
+                instances.sort_by(|a, b| instance_id(a).cmp(instance_id(b)));
+                Application { name, instances }
+            })
+            .collect(),
+    }
+}
+
+/// Writes the registry to stdout
+pub fn export(client: &EurekaRestClient, format: Format) -> Result<(), EurekaError> {
+    let apps = snapshot(client.get_all_instances()?);
+    let document = format.codec().encode_applications(&apps)?;
+    let mut stdout = io::stdout();
+    stdout
+        .write_all(&document)
+        .and_then(|_| stdout.write_all(b"\n"))
+        .map_err(EurekaError::Io)
+}
+
+/// Registers the instances of the document at `path`, and with `keep_alive` sends their
+/// heartbeats until Ctrl-C, otherwise eureka evicts them when their lease expires
+pub fn import(
+    client: &EurekaRestClient,
+    path: &str,
+    format: Option<Format>,
+    keep_alive: bool,
+) -> Result<(), EurekaError> {
+    let document = fs::read(path).map_err(EurekaError::Io)?;
+    let format = format.unwrap_or_else(|| Format::detect(&document));
+    let instances: Vec<Instance> = format
+        .codec()
+        .decode_applications(&document)?
+        .applications
+        .into_iter()
+        .flat_map(|app| app.instances)
+        .collect();
+    if keep_alive {
+        return register_until_interrupted(client, &instances, HEARTBEAT_INTERVAL);
+    }
+    for instance in &instances {
+        client.register(&instance.app, instance)?;
+    }
+    eprintln!("Registered {} instances", instances.len());
+    Ok(())
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+
+    use eureka_client::test_util::instance;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let apps = snapshot(vec![
+            instance("PAYMENTS").id("payments-1").build(),
+            instance("ORDERS").id("orders-2").build(),
+            instance("ORDERS").id("orders-1").build(),
+        ]);
+        let names: Vec<&str> = apps
+            .applications
+            .iter()
+            .map(|app| app.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["ORDERS", "PAYMENTS"]);
+        assert_eq!(instance_id(&apps.applications[0].instances[0]), "orders-1");
+
+        for format in [Format::Json, Format::Xml].iter().copied() {
+            let document = format.codec().encode_applications(&apps).unwrap();
+            assert_eq!(Format::detect(&document), format);
+            assert_eq!(format.codec().decode_applications(&document).unwrap(), apps);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! `eureka status APP ID`, setting or clearing the status override of an instance, e.g. to
+//! take it out of service
+
+use clap::Args;
+use eureka_client::{EurekaApi, EurekaError, EurekaRestClient, StatusType};
+
+#[derive(Debug, Args)]
+#[group(required = true, multiple = true)]
+pub struct StatusArgs {
+    /// Status overriding the one of the instance, or its status once the override is
+    /// cleared: UP, DOWN, STARTING or OUT_OF_SERVICE
+    #[arg(long, value_name = "STATUS", value_parser = parse_status)]
+    set: Option<StatusType>,
+    /// Removes the status override, the instance has the status given with `--set` or
+    /// UNKNOWN until it reports its own
+    #[arg(long)]
+    clear_override: bool,
+}
+
+fn parse_status(status: &str) -> Result<StatusType, String> {
+    match status {
+        "UP" | "DOWN" | "STARTING" | "OUT_OF_SERVICE" => status.parse(),
+        _ => Err(format!(
+            "expected UP, DOWN, STARTING or OUT_OF_SERVICE, got `{}`",
+            status
+        )),
+    }
+}
+
+pub fn status(
+    client: &EurekaRestClient,
+    app: &str,
+    id: &str,
+    args: &StatusArgs,
+) -> Result<(), EurekaError> {
+    match (args.clear_override, args.set) {
+        (true, status) => {
+            client.delete_status_override(app, id, status)?;
+            eprintln!("Cleared the status override of {} {}", app, id);
+        }
+        (false, Some(status)) => {
+            client.update_status(app, id, status)?;
+            eprintln!("Overrode the status of {} {} with {}", app, id, status);
+        }
+        (false, None) => unreachable!("clap requires --set or --clear-override"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: StatusArgs,
+    }
+
+    #[test]
+    fn test_args() {
+        let cli = Cli::parse_from(vec!["status", "--set", "OUT_OF_SERVICE"]);
+        assert_eq!(cli.args.set, Some(StatusType::OutOfService));
+        assert!(!cli.args.clear_override);
+
+        let cli = Cli::parse_from(vec!["status", "--clear-override", "--set", "UP"]);
+        assert_eq!(cli.args.set, Some(StatusType::Up));
+        assert!(cli.args.clear_override);
+
+        assert!(Cli::try_parse_from(vec!["status"]).is_err());
+        assert!(Cli::try_parse_from(vec!["status", "--set", "UNKNOWN"]).is_err());
+    }
+}
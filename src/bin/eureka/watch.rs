@@ -0,0 +1,141 @@
+//! `eureka watch [APP]`, printing the instances added, removed and changed between polls
+
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::thread;
+use std::time::Duration;
+
+use eureka_client::{diff_registry, Application, Applications, EurekaApi, EurekaError};
+use eureka_client::{EurekaRestClient, Instance, RegistryDiff};
+
+use crate::output::{instance_id, Output};
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Polls the instances of `app`, or of all apps, every `interval` until interrupted
+///
+/// The first poll lists the instances as added. Failed polls are reported and the next
+/// ones compared to the last successful one.
+pub fn watch(
+    client: &EurekaRestClient,
+    output: &Output,
+    app: Option<&str>,
+    interval: Duration,
+) -> Result<(), EurekaError> {
+    let color =
+        !output.is_json() && io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let mut before = registry(Vec::new());
+    loop {
+        let instances = match app {
+            Some(app) => client.get_instances_by_app(app),
+            None => client.get_all_instances(),
+        };
+        match instances {
+            Ok(instances) => {
+                let after = registry(instances);
+                let diff = diff_registry(&before, &after);
+                if output.is_json() {
+                    if !diff.is_empty() {
+                        output.json_line(&diff)?;
+                    }
+                } else {
+                    for line in lines(&diff, &after, color) {
+                        println!("{}", line);
+                    }
+                }
+                before = after;
+            }
+            Err(e) => eprintln!("eureka: {}", e),
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// Registry dump of `instances`, all in one application as [`diff_registry`] only compares
+/// the instances
+fn registry(instances: Vec<Instance>) -> Applications {
+    Applications {
+        versions_delta: None,
+        apps_hashcode: None,
+        applications: vec![Application {
+            name: String::new(),
+            instances,
+        }],
+    }
+}
+
+/// Lines of `diff`, the added instances with their status in `after`
+fn lines(diff: &RegistryDiff, after: &Applications, color: bool) -> Vec<String> {
+    let statuses: HashMap<String, String> = after
+        .applications
+        .iter()
+        .flat_map(|app| &app.instances)
+        .map(|instance| {
+            let key = format!("{}/{}", instance.app, instance_id(instance));
+            (key, instance.status.to_string())
+        })
+        .collect();
+    let paint = |code: &str, line: String| {
+        if color {
+            format!("{}{}{}", code, line, RESET)
+        } else {
+            line
+        }
+    };
+
+    let mut lines = Vec::new();
+    for instance in &diff.added {
+        let status = statuses.get(instance).map_or("", String::as_str);
+        lines.push(paint(GREEN, format!("+ {} {}", instance, status)));
+    }
+    for instance in &diff.removed {
+        lines.push(paint(RED, format!("- {}", instance)));
+    }
+    for change in &diff.changed {
+        lines.push(paint(
+            YELLOW,
+            format!("~ {}: {}", change.instance, change.changes.join(", ")),
+        ));
+    }
+    lines
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+
+    use eureka_client::test_util;
+    use eureka_client::StatusType;
+
+    fn instance(id: &str, status: StatusType) -> Instance {
+        test_util::instance("ORDERS").id(id).status(status).build()
+    }
+
+    #[test]
+    fn test_lines() {
+        let before = registry(vec![
+            instance("orders-1", StatusType::Up),
+            instance("orders-2", StatusType::Up),
+        ]);
+        let after = registry(vec![
+            instance("orders-2", StatusType::Down),
+            instance("orders-3", StatusType::Starting),
+        ]);
+        let diff = diff_registry(&before, &after);
+        assert_eq!(
+            lines(&diff, &after, false),
+            vec![
+                "+ ORDERS/orders-3 STARTING",
+                "- ORDERS/orders-1",
+                "~ ORDERS/orders-2: status: UP -> DOWN",
+            ]
+        );
+        assert_eq!(
+            lines(&diff, &after, true)[1],
+            "\x1b[31m- ORDERS/orders-1\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Clients of several independent eureka clusters, e.g. a legacy and a new one during a
+//! migration
+
+use rand::random;
+use reqwest::header::HeaderMap;
+use reqwest::{Method, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{EurekaClient, EurekaError, ResolvedInstance, StatusType};
+
+/// How a [`EurekaClientSet`] looks the apps up in the registries of its clusters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupPolicy {
+    /// The first cluster, in the order they were added, with an UP instance of the app
+    Priority,
+    /// The UP instances of the app in all the clusters, picked alike
+    Merge,
+}
+
+impl Default for LookupPolicy {
+    fn default() -> Self {
+        LookupPolicy::Priority
+    }
+}
+
+/// Cluster of a [`EurekaClientSet`]
+#[derive(Debug)]
+struct Cluster {
+    name: String,
+    client: Arc<EurekaClient>,
+}
+
+/// Clients of several eureka clusters, looking the apps up in their registries with a
+/// [`LookupPolicy`]
+///
+/// Each cluster has its own config: the instance registers with the clusters whose config
+/// has `register_with_eureka`, and the registries of the clusters with `fetch_registry` are
+/// looked up.
+///
+/// ```ignore
+/// let clients = EurekaClientSet::new(LookupPolicy::Priority)
+///     .with_cluster("new", EurekaClient::new(new_config))
+///     .with_cluster("legacy", EurekaClient::new(legacy_config));
+/// clients.start();
+/// let orders: Vec<Order> =
+///     clients.call("ORDERS", "/orders", Method::GET, &(), HeaderMap::new())?;
+/// ```
+#[derive(Debug, Default)]
+pub struct EurekaClientSet {
+    clusters: Vec<Cluster>,
+    policy: LookupPolicy,
+}
+
+impl EurekaClientSet {
+    pub fn new(policy: LookupPolicy) -> Self {
+        EurekaClientSet {
+            clusters: Vec::new(),
+            policy,
+        }
+    }
+
+    /// Adds the cluster `name` served by `client`, after the clusters already added
+    pub fn with_cluster(mut self, name: &str, client: EurekaClient) -> Self {
+        self.clusters.push(Cluster {
+            name: name.to_string(),
+            client: Arc::new(client),
+        });
+        self
+    }
+
+    pub fn policy(&self) -> LookupPolicy {
+        self.policy
+    }
+
+    /// Client of the cluster `name`
+    pub fn cluster(&self, name: &str) -> Option<&Arc<EurekaClient>> {
+        self.clusters
+            .iter()
+            .find(|cluster| cluster.name == name)
+            .map(|cluster| &cluster.client)
+    }
+
+    /// Names of the clusters, in lookup order
+    pub fn cluster_names(&self) -> Vec<&str> {
+        self.clusters
+            .iter()
+            .map(|cluster| cluster.name.as_str())
+            .collect()
+    }
+
+    /// Starts the clients of all the clusters
+    pub fn start(&self) {
+        for cluster in &self.clusters {
+            cluster.client.start();
+        }
+    }
+
+    /// Sets the status of the instance on the clusters it registers with
+    ///
+    /// Fails with the error of the first cluster which failed, after trying all of them.
+    pub fn set_status(&self, status: StatusType) -> Result<(), EurekaError> {
+        let mut result = Ok(());
+        for cluster in self.registering() {
+            if let Err(e) = cluster.client.set_status(status) {
+                error!("Failed to set the status on {}: {}", cluster.name, e);
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    /// Deregisters the instance from the clusters it registers with
+    pub fn deregister(&self) {
+        for cluster in self.registering() {
+            cluster.client.deregister();
+        }
+    }
+
+    fn registering(&self) -> impl Iterator<Item = &Cluster> {
+        self.clusters
+            .iter()
+            .filter(|cluster| cluster.client.instance.is_some())
+    }
+
+    /// UP instance of `app` picked as [`make_request`](Self::make_request) does
+    pub fn resolve(&self, app: &str) -> Option<ResolvedInstance> {
+        self.pick_cluster(app)?.resolve(app)
+    }
+
+    /// The UP instances of `app` in the cluster the policy picks, or in all clusters when
+    /// merging
+    pub fn resolve_all(&self, app: &str) -> Vec<ResolvedInstance> {
+        match self.policy {
+            LookupPolicy::Priority => self
+                .clusters
+                .iter()
+                .map(|cluster| cluster.client.resolve_all(app))
+                .find(|instances| !instances.is_empty())
+                .unwrap_or_default(),
+            LookupPolicy::Merge => self
+                .clusters
+                .iter()
+                .flat_map(|cluster| cluster.client.resolve_all(app))
+                .collect(),
+        }
+    }
+
+    /// Sends a request to an instance of `app`, see [`EurekaClient::make_request`]
+    pub fn make_request<V: Serialize>(
+        &self,
+        app: &str,
+        path: &str,
+        method: Method,
+        body: &V,
+        headers: HeaderMap,
+    ) -> Result<Response, EurekaError> {
+        self.client_of(app)?
+            .make_request(app, path, method, body, headers)
+    }
+
+    /// Calls an instance of `app`, see [`EurekaClient::call`]
+    pub fn call<V: Serialize, R: DeserializeOwned>(
+        &self,
+        app: &str,
+        path: &str,
+        method: Method,
+        body: &V,
+        headers: HeaderMap,
+    ) -> Result<R, EurekaError> {
+        self.client_of(app)?.call(app, path, method, body, headers)
+    }
+
+    /// Client of the cluster the request to `app` goes to, or why there is none
+    fn client_of(&self, app: &str) -> Result<&EurekaClient, EurekaError> {
+        match self.pick_cluster(app) {
+            Some(client) => Ok(client),
+            // Why the first cluster has no instance, e.g. its registry being stale
+            None => match self.clusters.first() {
+                Some(cluster) => match cluster.client.registry.find_instance(app) {
+                    Ok(_) => Ok(&*cluster.client),
+                    Err(e) => Err(e),
+                },
+                None => Err(EurekaError::AppNotFound(app.to_string())),
+            },
+        }
+    }
+
+    /// Cluster with an UP instance of `app`, the first one or, when merging, one picked with
+    /// the odds of its share of the instances
+    fn pick_cluster(&self, app: &str) -> Option<&EurekaClient> {
+        let counts: Vec<usize> = self
+            .clusters
+            .iter()
+            .map(|cluster| cluster.client.resolve_all(app).len())
+            .collect();
+        let index = match self.policy {
+            LookupPolicy::Priority => counts.iter().position(|&count| count > 0)?,
+            LookupPolicy::Merge => pick_weighted(&counts, random::<usize>())?,
+        };
+        Some(&*self.clusters[index].client)
+    }
+}
+
+/// Index whose odds are its share of the sum of `counts`, `None` when they are all 0
+fn pick_weighted(counts: &[usize], draw: usize) -> Option<usize> {
+    let total: usize = counts.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let mut draw = draw % total;
+    for (index, &count) in counts.iter().enumerate() {
+        if draw < count {
+            return Some(index);
+        }
+        draw -= count;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{instance, MockEurekaServer};
+    use crate::BaseConfig;
+
+    fn client(server: &MockEurekaServer) -> EurekaClient {
+        let mut config = BaseConfig::default();
+        config.eureka = server.config();
+        config.eureka.register_with_eureka = false;
+        let client = EurekaClient::new(config);
+        client.refresh_registry().unwrap();
+        client
+    }
+
+    #[test]
+    fn test_client_set() {
+        let legacy = MockEurekaServer::start().unwrap();
+        legacy.register(instance("ORDERS").ip_addr("10.0.0.1").up().build());
+        let new = MockEurekaServer::start().unwrap();
+        new.register(instance("ORDERS").ip_addr("10.1.0.1").up().build());
+        new.register(instance("BILLING").ip_addr("10.1.0.2").up().build());
+
+        let clients = EurekaClientSet::new(LookupPolicy::Priority)
+            .with_cluster("legacy", client(&legacy))
+            .with_cluster("new", client(&new));
+        assert_eq!(clients.cluster_names(), vec!["legacy", "new"]);
+        assert!(clients.cluster("new").is_some());
+        assert_eq!(clients.resolve("ORDERS").unwrap().ip_addr, "10.0.0.1");
+        assert_eq!(clients.resolve("BILLING").unwrap().ip_addr, "10.1.0.2");
+        assert_eq!(clients.resolve_all("ORDERS").len(), 1);
+        assert!(clients.resolve("MISSING").is_none());
+        let error = clients.client_of("MISSING").unwrap_err();
+        assert!(matches!(error, EurekaError::AppNotFound(_)));
+        assert!(clients.set_status(StatusType::Down).is_ok());
+
+        let clients = EurekaClientSet {
+            policy: LookupPolicy::Merge,
+            ..clients
+        };
+        assert_eq!(clients.resolve_all("ORDERS").len(), 2);
+        assert_eq!(pick_weighted(&[1, 0, 2], 0), Some(0));
+        assert_eq!(pick_weighted(&[1, 0, 2], 2), Some(2));
+        assert_eq!(pick_weighted(&[1, 0, 2], 4), Some(0));
+        assert_eq!(pick_weighted(&[0, 0], 1), None);
+    }
+}
@@ -0,0 +1,198 @@
+//! Time source of the heartbeats, registry refreshes, retries and rate limiter
+
+use std::fmt::Debug;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Clock and sleeps of the client, replaceable to test its timing without waiting
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Blocks the calling thread for `duration`
+    fn sleep(&self, duration: Duration);
+}
+
+/// Wall clock, sleeping with `thread::sleep`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Virtual clock, whose time only moves when [`advance`](Self::advance)d
+///
+/// Sleeping threads park until the time passed their deadline, so that a test steps the
+/// background loops of a client one sleep at a time:
+///
+/// ```ignore
+/// client.start();
+/// clock.wait_for_sleepers(2);
+/// clock.advance_to_next_wakeup();
+/// ```
+#[derive(Debug)]
+pub struct MockClock {
+    origin: Instant,
+    state: Mutex<MockState>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    elapsed: Duration,
+    /// Deadlines of the sleeping threads, the ones the time hasn't passed yet
+    deadlines: Vec<Duration>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock {
+            origin: Instant::now(),
+            state: Mutex::new(MockState::default()),
+            changed: Condvar::new(),
+        }
+    }
+}
+
+impl MockClock {
+    /// Moves the time forward by `duration`, waking the threads whose sleep it ends
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.lock();
+        state.elapsed += duration;
+        let elapsed = state.elapsed;
+        state.deadlines.retain(|&deadline| deadline > elapsed);
+        self.changed.notify_all();
+    }
+
+    /// Moves the time forward to the earliest deadline of the sleeping threads, returns
+    /// whether a thread was sleeping
+    pub fn advance_to_next_wakeup(&self) -> bool {
+        let state = self.lock();
+        let next = state.deadlines.iter().min().copied();
+        let elapsed = state.elapsed;
+        drop(state);
+        match next {
+            Some(next) => {
+                self.advance(next - elapsed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Virtual time elapsed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        self.lock().elapsed
+    }
+
+    /// Number of threads sleeping until the time is advanced
+    pub fn sleepers(&self) -> usize {
+        self.lock().deadlines.len()
+    }
+
+    /// Blocks until at least `count` threads sleep, e.g. until the background loops of a
+    /// client wait for their next round
+    pub fn wait_for_sleepers(&self, count: usize) {
+        let mut state = self.lock();
+        while state.deadlines.len() < count {
+            state = self
+                .changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.origin + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let mut state = self.lock();
+        let deadline = state.elapsed + duration;
+        if deadline <= state.elapsed {
+            return;
+        }
+        state.deadlines.push(deadline);
+        self.changed.notify_all();
+        while state.elapsed < deadline {
+            state = self
+                .changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+/// Clock shared by the parts of a client, replaceable once they are built
+#[derive(Debug, Clone)]
+pub(crate) struct SharedClock {
+    clock: Arc<RwLock<Arc<dyn Clock>>>,
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        SharedClock {
+            clock: Arc::new(RwLock::new(Arc::new(SystemClock))),
+        }
+    }
+}
+
+impl SharedClock {
+    pub(crate) fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.clock.write().unwrap() = clock;
+    }
+
+    pub(crate) fn now(&self) -> Instant {
+        self.clock.read().unwrap().now()
+    }
+
+    pub(crate) fn sleep(&self, duration: Duration) {
+        // Not holding the lock while sleeping
+        let clock = Arc::clone(&self.clock.read().unwrap());
+        clock.sleep(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let mock = Arc::new(MockClock::default());
+        let clock = SharedClock::default();
+        clock.set_clock(Arc::clone(&mock) as Arc<dyn Clock>);
+        let start = clock.now();
+        mock.advance(Duration::from_secs(15));
+        assert_eq!(clock.now() - start, Duration::from_secs(15));
+        assert!(!mock.advance_to_next_wakeup());
+
+        thread::scope(|scope| {
+            let sleeper = scope.spawn(|| {
+                clock.sleep(Duration::from_secs(30));
+                clock.now() - start
+            });
+            mock.wait_for_sleepers(1);
+            // Parked until the time passed its deadline
+            mock.advance(Duration::from_secs(10));
+            assert_eq!(mock.sleepers(), 1);
+            assert!(mock.advance_to_next_wakeup());
+            assert_eq!(sleeper.join().unwrap(), Duration::from_secs(45));
+        });
+        assert_eq!(mock.sleepers(), 0);
+        assert_eq!(mock.elapsed(), Duration::from_secs(45));
+    }
+}
@@ -0,0 +1,225 @@
+//! Spring Cloud Config client, fetching the config of the app from the config server
+//! discovered in the registry, behind the `spring-config` feature
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::{path_segment_encode, EurekaClient, EurekaError, ParseError};
+
+/// Config of the app fetched by a [`ConfigClient`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConfigClientConfig {
+    /// App of the config servers in the registry, default CONFIGSERVER
+    pub service_id: String,
+    /// Application name the config is looked up for
+    pub application: String,
+    /// Comma separated profiles, default `default`
+    pub profile: String,
+    /// Label, e.g. a git branch, the server default label when empty
+    pub label: String,
+    /// Config refresh interval in milli-seconds, 0 to disable, default 60,000
+    pub refresh_interval: usize,
+}
+
+impl Default for ConfigClientConfig {
+    fn default() -> Self {
+        ConfigClientConfig {
+            service_id: "CONFIGSERVER".to_string(),
+            application: String::new(),
+            profile: "default".to_string(),
+            label: String::new(),
+            refresh_interval: 60_000,
+        }
+    }
+}
+
+/// Environment answered by a Spring Cloud Config server
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Environment {
+    #[serde(default)]
+    property_sources: Vec<PropertySource>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct PropertySource {
+    #[serde(default)]
+    source: Map<String, Value>,
+}
+
+/// Fetches the property sources of an application from a Spring Cloud Config server into `T`
+///
+/// The dotted property names are nested, `server.port` being the `port` field of the
+/// `server` field of `T`. The first property source holding a property wins, as in Spring.
+#[derive(Debug)]
+pub struct ConfigClient<T> {
+    client: Arc<EurekaClient>,
+    config: ConfigClientConfig,
+    current: Arc<RwLock<Option<Arc<T>>>>,
+    is_running: Arc<AtomicBool>,
+}
+
+impl<T: DeserializeOwned + Send + Sync + 'static> ConfigClient<T> {
+    pub fn new(client: Arc<EurekaClient>, config: ConfigClientConfig) -> Self {
+        ConfigClient {
+            client,
+            config,
+            current: Arc::new(RwLock::new(None)),
+            is_running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Last config fetched, `None` until a fetch succeeded
+    pub fn get(&self) -> Option<Arc<T>> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Fetches the config from the config server and keeps it for [`get`](Self::get)
+    pub fn fetch(&self) -> Result<Arc<T>, EurekaError> {
+        fetch_config(&self.client, &self.config, &self.current)
+    }
+
+    /// Fetches the config, then refreshes it every `refresh_interval` until the client
+    /// is dropped
+    pub fn start(&self) -> Result<Arc<T>, EurekaError> {
+        let config = self.fetch()?;
+        if self.config.refresh_interval == 0 {
+            return Ok(config);
+        }
+        self.is_running.store(true, Ordering::Relaxed);
+
+        let is_running = Arc::clone(&self.is_running);
+        let client = Arc::clone(&self.client);
+        let config_client = self.config.clone();
+        let current = Arc::clone(&self.current);
+        let interval = Duration::from_millis(self.config.refresh_interval as u64);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if !is_running.load(Ordering::Relaxed) {
+                break;
+            }
+            if let Err(e) = fetch_config(&client, &config_client, &current) {
+                error!("Failed to refresh the config: {}", e);
+            }
+        });
+        Ok(config)
+    }
+}
+
+impl<T> Drop for ConfigClient<T> {
+    fn drop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn fetch_config<T: DeserializeOwned>(
+    client: &EurekaClient,
+    config: &ConfigClientConfig,
+    current: &RwLock<Option<Arc<T>>>,
+) -> Result<Arc<T>, EurekaError> {
+    let environment: Environment = client.call(
+        &config.service_id,
+        &config_path(config),
+        Method::GET,
+        &(),
+        HeaderMap::new(),
+    )?;
+    let properties = merge_property_sources(&environment.property_sources);
+    let value = serde_json::from_value(properties)
+        .map(Arc::new)
+        .map_err(|e| EurekaError::ParseError(ParseError::new(e.to_string())))?;
+    *current.write().unwrap() = Some(Arc::clone(&value));
+    Ok(value)
+}
+
+/// `/{application}/{profile}/{label}`, without the label when empty. Slashes in the label
+/// are sent as `(_)`, as the config server expects
+fn config_path(config: &ConfigClientConfig) -> String {
+    let mut path = format!(
+        "/{}/{}",
+        path_segment_encode(&config.application),
+        path_segment_encode(&config.profile)
+    );
+    if !config.label.is_empty() {
+        path.push('/');
+        path.push_str(&path_segment_encode(&config.label.replace('/', "(_)")));
+    }
+    path
+}
+
+/// Nests the dotted properties of the sources, the first source holding a property wins
+fn merge_property_sources(sources: &[PropertySource]) -> Value {
+    let mut merged = Map::new();
+    for source in sources.iter().rev() {
+        for (name, value) in &source.source {
+            insert_property(&mut merged, name, value.clone());
+        }
+    }
+    Value::Object(merged)
+}
+
+fn insert_property(properties: &mut Map<String, Value>, name: &str, value: Value) {
+    match name.find('.') {
+        Some(dot) => {
+            let entry = properties
+                .entry(name[..dot].to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !entry.is_object() {
+                *entry = Value::Object(Map::new());
+            }
+            if let Value::Object(ref mut nested) = *entry {
+                insert_property(nested, &name[dot + 1..], value);
+            }
+        }
+        None => {
+            properties.insert(name.to_string(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_property_sources() {
+        let environment: Environment = serde_json::from_value(serde_json::json!({
+            "name": "orders",
+            "profiles": ["prod"],
+            "propertySources": [
+                { "name": "orders-prod.yml", "source": { "server.port": 8081 } },
+                {
+                    "name": "application.yml",
+                    "source": { "server.port": 8080, "server.host": "localhost", "debug": false }
+                }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            merge_property_sources(&environment.property_sources),
+            serde_json::json!({
+                "server": { "port": 8081, "host": "localhost" },
+                "debug": false
+            })
+        );
+    }
+
+    #[test]
+    fn test_config_path() {
+        let mut config = ConfigClientConfig {
+            application: "orders".to_string(),
+            ..ConfigClientConfig::default()
+        };
+        assert_eq!(config_path(&config), "/orders/default");
+        config.label = "feature/x".to_string();
+        assert_eq!(config_path(&config), "/orders/default/feature(_)x");
+    }
+}
@@ -0,0 +1,156 @@
+//! `hyper` connector dialing the instances of `http://APP.eureka/` uris, behind the `hyper`
+//! feature
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::Uri;
+use hyper_util::client::legacy::connect::HttpConnector;
+use rand::seq::SliceRandom;
+use tower_service::Service;
+
+use crate::registry::{missing_instance, up_instances, AppCache};
+use crate::rest::structures::Instance;
+use crate::{host_port, EurekaClient, EurekaError};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Connector resolving the `APP.eureka` hosts to a random UP instance of `APP` before
+/// handing the connection to the wrapped connector
+///
+/// The instance port is the secure one for `https` uris. Other hosts are connected to
+/// unchanged, so it can replace the connector of a hyper or tonic client.
+#[derive(Debug, Clone)]
+pub struct EurekaConnector<C = HttpConnector> {
+    inner: C,
+    app_cache: Arc<AppCache>,
+}
+
+impl<C> EurekaConnector<C> {
+    pub const HOST_SUFFIX: &'static str = ".eureka";
+
+    /// Connects through `inner` instead, e.g. a TLS connector
+    pub fn with_connector<D>(self, inner: D) -> EurekaConnector<D> {
+        EurekaConnector {
+            inner,
+            app_cache: self.app_cache,
+        }
+    }
+
+    /// Address of an UP instance when `uri` names an app, `uri` itself otherwise
+    fn resolve(&self, uri: Uri) -> Result<Uri, EurekaError> {
+        let app = match uri.host() {
+            Some(host) if host.ends_with(Self::HOST_SUFFIX) => {
+                &host[..host.len() - Self::HOST_SUFFIX.len()]
+            }
+            _ => return Ok(uri),
+        };
+        let ssl = uri.scheme_str() == Some("https");
+        let instance = up_instances(&self.app_cache, app)
+            .choose(&mut rand::thread_rng())
+            .cloned()
+            .ok_or_else(|| missing_instance(&self.app_cache, app))?;
+        let port = if ssl {
+            instance.secure_port.value
+        } else {
+            instance.port.value
+        };
+        let address = format!(
+            "{}://{}/",
+            uri.scheme_str().unwrap_or("http"),
+            host_port(&instance.ip_addr, port)
+        );
+        address.parse().map_err(|e: http::uri::InvalidUri| {
+            EurekaError::UnexpectedState(format!("Invalid instance address {}: {}", address, e))
+        })
+    }
+}
+
+impl<C> Service<Uri> for EurekaConnector<C>
+where
+    C: Service<Uri>,
+    C::Error: Into<BoxError>,
+    C::Future: Send + 'static,
+{
+    type Response = C::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        match self.resolve(uri) {
+            Ok(uri) => {
+                let connecting = self.inner.call(uri);
+                Box::pin(async move { connecting.await.map_err(Into::into) })
+            }
+            Err(e) => Box::pin(async move { Err(e.into()) }),
+        }
+    }
+}
+
+impl EurekaClient {
+    /// Connector dialing the instances of the apps named by `APP.eureka` hosts
+    pub fn connector(&self) -> EurekaConnector {
+        EurekaConnector {
+            inner: HttpConnector::new(),
+            app_cache: self.registry.app_cache(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::structures::{PortData, SecurePort, StatusType};
+
+    #[test]
+    fn test_resolve() {
+        let mut instance = Instance::default();
+        instance.app = "MY-SERVICE".to_string();
+        instance.status = StatusType::Up;
+        instance.ip_addr = "10.0.0.1".to_string();
+        instance.port = PortData::new(8080, true);
+        instance.secure_port = SecurePort::new(8443, true);
+        let mut apps = HashMap::new();
+        apps.insert("MY-SERVICE".to_string(), vec![Arc::new(instance)]);
+        let connector = EurekaConnector {
+            inner: (),
+            app_cache: Arc::new(AppCache::new(apps)),
+        };
+
+        let resolve = |uri: &str| {
+            connector
+                .resolve(uri.parse().unwrap())
+                .map(|uri| uri.to_string())
+        };
+        assert_eq!(
+            resolve("http://my-service.eureka/api").unwrap(),
+            "http://10.0.0.1:8080/"
+        );
+        assert_eq!(
+            resolve("https://my-service.eureka/").unwrap(),
+            "https://10.0.0.1:8443/"
+        );
+        assert_eq!(
+            resolve("http://example.com/api").unwrap(),
+            "http://example.com/api"
+        );
+        assert!(resolve("http://other.eureka/").is_err());
+    }
+
+    #[test]
+    fn test_hyper_connect() {
+        fn assert_connect<C: hyper_util::client::legacy::connect::Connect>(_: &C) {}
+        let connector = EurekaConnector {
+            inner: HttpConnector::new(),
+            app_cache: Arc::new(AppCache::default()),
+        };
+        assert_connect(&connector);
+    }
+}
@@ -0,0 +1,338 @@
+//! Data center detection for AWS, GCE and Azure
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use reqwest::{Client, Response};
+use serde_json::Value;
+
+use crate::aws::{AwsMetadata, DETECT_TIMEOUT};
+use crate::rest::structures::{AppMetaDataType, DataCenterInfo, Instance};
+use crate::EurekaError;
+
+/// Data center info and instance metadata found by a [`DataCenterProvider`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DataCenter {
+    pub info: DataCenterInfo,
+    /// Instance metadata to register with, e.g. the `zone`
+    pub metadata: HashMap<String, String>,
+}
+
+impl DataCenter {
+    /// Sets the data center info of `instance` and adds the metadata it lacks
+    pub fn apply_to(&self, instance: &mut Instance) {
+        instance.data_center_info = self.info.clone();
+        for (key, value) in &self.metadata {
+            if instance.metadata_value(key).is_none() {
+                instance.set_metadata_value(key, value);
+            }
+        }
+    }
+}
+
+/// Source of the data center info of the instance
+pub trait DataCenterProvider: Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether this runs in the data center of the provider
+    fn is_available(&self) -> bool;
+
+    fn data_center(&self) -> Result<DataCenter, EurekaError>;
+}
+
+/// Data center the instance registers in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DataCenterKind {
+    /// No metadata lookup, the data center info of the instance config is used
+    MyOwn,
+    /// The first of AWS, GCE and Azure whose metadata service answers
+    Auto,
+    Aws,
+    Gce,
+    Azure,
+}
+
+impl Default for DataCenterKind {
+    fn default() -> Self {
+        DataCenterKind::MyOwn
+    }
+}
+
+impl DataCenterKind {
+    /// Provider of the data center, `None` for `MyOwn` or when none is detected
+    pub fn provider(self) -> Option<Box<dyn DataCenterProvider>> {
+        match self {
+            DataCenterKind::MyOwn => None,
+            DataCenterKind::Auto => detect_provider(),
+            DataCenterKind::Aws => Some(Box::new(AwsMetadata::default())),
+            DataCenterKind::Gce => Some(Box::new(GceMetadata::default())),
+            DataCenterKind::Azure => Some(Box::new(AzureMetadata::default())),
+        }
+    }
+}
+
+/// First provider whose metadata service answers
+pub fn detect_provider() -> Option<Box<dyn DataCenterProvider>> {
+    let providers: Vec<Box<dyn DataCenterProvider>> = vec![
+        Box::new(AwsMetadata::default().with_timeout(DETECT_TIMEOUT)),
+        Box::new(GceMetadata::default().with_timeout(DETECT_TIMEOUT)),
+        Box::new(AzureMetadata::default().with_timeout(DETECT_TIMEOUT)),
+    ];
+    let provider = providers
+        .into_iter()
+        .find(|provider| provider.is_available());
+    match &provider {
+        Some(provider) => info!("Detected the {} data center", provider.name()),
+        None => debug!("No data center metadata service found"),
+    }
+    provider
+}
+
+impl DataCenterProvider for AwsMetadata {
+    fn name(&self) -> &'static str {
+        "aws"
+    }
+
+    fn is_available(&self) -> bool {
+        AwsMetadata::is_available(self)
+    }
+
+    fn data_center(&self) -> Result<DataCenter, EurekaError> {
+        let info = self.data_center_info();
+        let mut metadata = HashMap::new();
+        if let Some(aws) = &info.metadata {
+            if !aws.availability_zone.is_empty() {
+                metadata.insert(
+                    AppMetaDataType::ZONE.to_string(),
+                    aws.availability_zone.clone(),
+                );
+            }
+        }
+        Ok(DataCenter { info, metadata })
+    }
+}
+
+/// Client of the GCE metadata server
+#[derive(Debug, Clone)]
+pub struct GceMetadata {
+    client: Client,
+    host: String,
+}
+
+impl Default for GceMetadata {
+    fn default() -> Self {
+        GceMetadata::with_host(GceMetadata::METADATA_ENDPOINT)
+    }
+}
+
+impl GceMetadata {
+    pub const METADATA_ENDPOINT: &'static str = "metadata.google.internal";
+    pub const INSTANCE_ID: &'static str = "gce.instance-id";
+    pub const PROJECT_ID: &'static str = "gce.project-id";
+    pub const MACHINE_TYPE: &'static str = "gce.machine-type";
+
+    pub fn with_host(host: &str) -> Self {
+        GceMetadata {
+            client: Client::new(),
+            host: host.to_string(),
+        }
+    }
+
+    /// Gives up on metadata requests after `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        match Client::builder().timeout(timeout).build() {
+            Ok(client) => self.client = client,
+            Err(e) => error!("Failed to set the metadata request timeout: {}", e),
+        }
+        self
+    }
+
+    fn get(&self, path: &str) -> Result<String, EurekaError> {
+        self.client
+            .get(&format!("http://{}/computeMetadata/v1/{}", self.host, path))
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .and_then(Response::error_for_status)
+            .and_then(|mut response| response.text())
+            .map_err(EurekaError::network)
+    }
+}
+
+impl DataCenterProvider for GceMetadata {
+    fn name(&self) -> &'static str {
+        "gce"
+    }
+
+    fn is_available(&self) -> bool {
+        self.get("instance/id").is_ok()
+    }
+
+    fn data_center(&self) -> Result<DataCenter, EurekaError> {
+        let mut metadata = HashMap::new();
+        // Zone and machine type are resource paths, e.g. projects/123/zones/us-central1-a
+        metadata.insert(
+            AppMetaDataType::ZONE.to_string(),
+            last_segment(&self.get("instance/zone")?),
+        );
+        metadata.insert(
+            GceMetadata::MACHINE_TYPE.to_string(),
+            last_segment(&self.get("instance/machine-type")?),
+        );
+        metadata.insert(
+            GceMetadata::INSTANCE_ID.to_string(),
+            self.get("instance/id")?,
+        );
+        metadata.insert(
+            GceMetadata::PROJECT_ID.to_string(),
+            self.get("project/project-id")?,
+        );
+        Ok(DataCenter {
+            info: DataCenterInfo::default(),
+            metadata,
+        })
+    }
+}
+
+/// Client of the Azure instance metadata service
+#[derive(Debug, Clone)]
+pub struct AzureMetadata {
+    client: Client,
+    host: String,
+}
+
+impl Default for AzureMetadata {
+    fn default() -> Self {
+        AzureMetadata::with_host(AzureMetadata::METADATA_ENDPOINT)
+    }
+}
+
+impl AzureMetadata {
+    pub const METADATA_ENDPOINT: &'static str = "169.254.169.254";
+    pub const API_VERSION: &'static str = "2021-02-01";
+    pub const VM_ID: &'static str = "azure.vm-id";
+    pub const VM_SIZE: &'static str = "azure.vm-size";
+    pub const SUBSCRIPTION_ID: &'static str = "azure.subscription-id";
+    pub const RESOURCE_GROUP: &'static str = "azure.resource-group";
+
+    pub fn with_host(host: &str) -> Self {
+        AzureMetadata {
+            client: Client::new(),
+            host: host.to_string(),
+        }
+    }
+
+    /// Gives up on metadata requests after `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        match Client::builder().timeout(timeout).build() {
+            Ok(client) => self.client = client,
+            Err(e) => error!("Failed to set the metadata request timeout: {}", e),
+        }
+        self
+    }
+
+    fn compute(&self) -> Result<Value, EurekaError> {
+        self.client
+            .get(&format!(
+                "http://{}/metadata/instance/compute?api-version={}",
+                self.host,
+                AzureMetadata::API_VERSION
+            ))
+            .header("Metadata", "true")
+            .send()
+            .and_then(Response::error_for_status)
+            .and_then(|mut response| response.json())
+            .map_err(EurekaError::network)
+    }
+}
+
+impl DataCenterProvider for AzureMetadata {
+    fn name(&self) -> &'static str {
+        "azure"
+    }
+
+    fn is_available(&self) -> bool {
+        self.compute().is_ok()
+    }
+
+    fn data_center(&self) -> Result<DataCenter, EurekaError> {
+        Ok(azure_data_center(&self.compute()?))
+    }
+}
+
+fn azure_data_center(compute: &Value) -> DataCenter {
+    let field = |name: &str| compute[name].as_str().unwrap_or_default().to_string();
+    let mut metadata = HashMap::new();
+    // Availability zones are numbered within a location, e.g. eastus-1
+    let location = field("location");
+    let zone = match field("zone") {
+        ref zone if zone.is_empty() => location,
+        zone => format!("{}-{}", location, zone),
+    };
+    for (key, value) in &[
+        (AppMetaDataType::ZONE, zone),
+        (AzureMetadata::VM_ID, field("vmId")),
+        (AzureMetadata::VM_SIZE, field("vmSize")),
+        (AzureMetadata::SUBSCRIPTION_ID, field("subscriptionId")),
+        (AzureMetadata::RESOURCE_GROUP, field("resourceGroupName")),
+    ] {
+        if !value.is_empty() {
+            metadata.insert(key.to_string(), value.clone());
+        }
+    }
+    DataCenter {
+        info: DataCenterInfo::default(),
+        metadata,
+    }
+}
+
+fn last_segment(path: &str) -> String {
+    path.trim()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gce_last_segment() {
+        assert_eq!(
+            last_segment("projects/123456/zones/us-central1-a"),
+            "us-central1-a"
+        );
+        assert_eq!(last_segment("n1-standard-1"), "n1-standard-1");
+    }
+
+    #[test]
+    fn test_azure_data_center() {
+        let compute = serde_json::json!({
+            "location": "eastus",
+            "zone": "2",
+            "vmId": "02aab8a4-74ef-476e-8182-f6d2ba4166a6",
+            "vmSize": "Standard_D2s_v3",
+            "subscriptionId": "8d10da13-8125-4ba9-a717-bf7490507b3d",
+            "resourceGroupName": "eureka",
+        });
+        let data_center = azure_data_center(&compute);
+        assert_eq!(data_center.info, DataCenterInfo::default());
+        assert_eq!(data_center.metadata[AppMetaDataType::ZONE], "eastus-2");
+        assert_eq!(
+            data_center.metadata[AzureMetadata::VM_SIZE],
+            "Standard_D2s_v3"
+        );
+
+        let mut instance = Instance::default();
+        instance.set_metadata_value(AppMetaDataType::ZONE, "configured");
+        data_center.apply_to(&mut instance);
+        assert_eq!(instance.zone(), Some("configured"));
+        assert_eq!(
+            instance.metadata_value(AzureMetadata::RESOURCE_GROUP),
+            Some("eureka")
+        );
+    }
+}
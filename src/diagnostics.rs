@@ -0,0 +1,85 @@
+//! Internal events of the client, sent to the `log` crate unless another sink is set
+
+use std::fmt::{self, Debug};
+use std::sync::{Arc, RwLock};
+
+pub use log::Level;
+
+/// Receiver of the internal events of the client (registration, heartbeats, registry fetches)
+///
+/// Implement it to route these events to another logging or metrics pipeline.
+pub trait DiagnosticsSink: Debug + Send + Sync {
+    fn emit(&self, level: Level, args: fmt::Arguments<'_>);
+}
+
+/// Sends the events to the `log` crate, with the `eureka_client` target
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogSink;
+
+impl DiagnosticsSink for LogSink {
+    fn emit(&self, level: Level, args: fmt::Arguments<'_>) {
+        log!(target: "eureka_client", level, "{}", args);
+    }
+}
+
+/// Sink shared by the parts of a client, replaceable once they are built
+#[derive(Debug, Clone)]
+pub(crate) struct Diagnostics {
+    sink: Arc<RwLock<Arc<dyn DiagnosticsSink>>>,
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Diagnostics {
+            sink: Arc::new(RwLock::new(Arc::new(LogSink))),
+        }
+    }
+}
+
+impl Diagnostics {
+    pub(crate) fn set_sink(&self, sink: Arc<dyn DiagnosticsSink>) {
+        *self.sink.write().unwrap() = sink;
+    }
+
+    pub(crate) fn emit(&self, level: Level, args: fmt::Arguments<'_>) {
+        let sink = Arc::clone(&self.sink.read().unwrap());
+        sink.emit(level, args);
+    }
+}
+
+/// `diag!(diagnostics, Warn, "format", args...)` emits an event to a [`Diagnostics`]
+macro_rules! diag {
+    ($diagnostics:expr, $level:ident, $($arg:tt)+) => {
+        $diagnostics.emit($crate::diagnostics::Level::$level, format_args!($($arg)+))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct RecordingSink {
+        events: Mutex<Vec<(Level, String)>>,
+    }
+
+    impl DiagnosticsSink for RecordingSink {
+        fn emit(&self, level: Level, args: fmt::Arguments<'_>) {
+            self.events.lock().unwrap().push((level, args.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_set_sink() {
+        let diagnostics = Diagnostics::default();
+        let shared = diagnostics.clone();
+        let sink = Arc::new(RecordingSink::default());
+        diagnostics.set_sink(Arc::clone(&sink) as Arc<dyn DiagnosticsSink>);
+        diag!(shared, Warn, "heartbeat {}", "dropped");
+        assert_eq!(
+            *sink.events.lock().unwrap(),
+            vec![(Level::Warn, "heartbeat dropped".to_string())]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Service discovery backends, Eureka being the one of [`EurekaClient`]
+
+use std::fmt::Debug;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::registry::{up_instances, AppCache};
+use crate::rest::structures::Instance;
+use crate::EurekaClient;
+
+/// Source of the instances of the apps, e.g. to write the adapters and the app code against
+/// another registry than Eureka
+pub trait ServiceDiscovery: Debug + Send + Sync {
+    /// UP instances of `app`, matched case-insensitively
+    fn instances(&self, app: &str) -> Vec<Instance>;
+
+    /// Sends the UP instances of `app` to `sender` now and whenever they change, until the
+    /// receiver is dropped
+    fn subscribe(&self, app: &str, sender: Sender<Vec<Instance>>);
+}
+
+/// Instances of the registry cache of a client
+#[derive(Debug, Clone)]
+pub(crate) struct RegistryDiscovery {
+    app_cache: Arc<AppCache>,
+    refresh_interval: Duration,
+}
+
+impl RegistryDiscovery {
+    /// Discovery of `app_cache`, compared every `refresh_interval` for the subscriptions
+    pub(crate) fn new(app_cache: Arc<AppCache>, refresh_interval: Duration) -> Self {
+        RegistryDiscovery {
+            app_cache,
+            refresh_interval,
+        }
+    }
+}
+
+impl ServiceDiscovery for RegistryDiscovery {
+    fn instances(&self, app: &str) -> Vec<Instance> {
+        up_instances(&self.app_cache, app)
+            .iter()
+            .map(|instance| Instance::clone(instance))
+            .collect()
+    }
+
+    fn subscribe(&self, app: &str, sender: Sender<Vec<Instance>>) {
+        let discovery = self.clone();
+        let app = app.to_string();
+        thread::spawn(move || {
+            let mut known = None;
+            loop {
+                let instances = discovery.instances(&app);
+                if known.as_ref() != Some(&instances) {
+                    if sender.send(instances.clone()).is_err() {
+                        break;
+                    }
+                    known = Some(instances);
+                }
+                thread::sleep(discovery.refresh_interval);
+            }
+        });
+    }
+}
+
+impl EurekaClient {
+    /// Discovery of the cached registry, e.g. for adapters written against [`ServiceDiscovery`]
+    ///
+    /// Subscriptions compare the registry cache every `registry_fetch_interval`.
+    pub fn discovery(&self) -> Arc<dyn ServiceDiscovery> {
+        Arc::new(self.registry_discovery())
+    }
+
+    pub(crate) fn registry_discovery(&self) -> RegistryDiscovery {
+        RegistryDiscovery::new(
+            self.registry.app_cache(),
+            Duration::from_millis(self.config.eureka.registry_fetch_interval.max(1) as u64),
+        )
+    }
+}
+
+impl ServiceDiscovery for EurekaClient {
+    fn instances(&self, app: &str) -> Vec<Instance> {
+        self.registry_discovery().instances(app)
+    }
+
+    fn subscribe(&self, app: &str, sender: Sender<Vec<Instance>>) {
+        self.registry_discovery().subscribe(app, sender)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_subscribe() {
+        let instance = test_util::instance("APP").up().build();
+        let app_cache = Arc::new(AppCache::default());
+        let discovery = RegistryDiscovery::new(Arc::clone(&app_cache), Duration::from_millis(1));
+
+        let (tx, rx) = channel();
+        discovery.subscribe("app", tx);
+        assert!(rx.recv().unwrap().is_empty());
+        let instances = vec![Arc::new(instance.clone())];
+        app_cache.update(|apps| apps.insert("APP".to_string(), instances));
+        assert_eq!(rx.recv().unwrap(), vec![instance]);
+    }
+}
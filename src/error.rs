@@ -0,0 +1,451 @@
+//! Errors of the client, classified by [`EurekaError::is_retryable`] and
+//! [`EurekaError::is_not_found`]
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::fmt::Display;
+use std::io;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::{ReqwestError, StatusCode};
+
+/// Request of the client an error happened in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Register,
+    Deregister,
+    Heartbeat,
+    /// Registry queries
+    Fetch,
+    /// Status, metadata and auto scaling group updates
+    Update,
+    /// Requests to the instances of an app, e.g. `EurekaClient::call`
+    Call,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Operation::Register => "register",
+            Operation::Deregister => "deregister",
+            Operation::Heartbeat => "heartbeat",
+            Operation::Fetch => "fetch",
+            Operation::Update => "update",
+            Operation::Call => "call",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Characters of the document kept around a parse error
+const SNIPPET_LEN: usize = 60;
+
+/// Document the client couldn't read or write, located in the document when possible
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// Path of the element holding the error, e.g. `applications/application/instance/port`
+    pub path: Option<String>,
+    /// Byte offset of the error in the document
+    pub offset: Option<usize>,
+    /// Line of the error, starting at 1
+    pub line: Option<usize>,
+    /// Column of the error, starting at 1
+    pub column: Option<usize>,
+    /// Line of the document around the error, truncated
+    pub snippet: Option<String>,
+}
+
+impl ParseError {
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        ParseError {
+            message: message.into(),
+            ..ParseError::default()
+        }
+    }
+
+    /// Locates the error at `line` and `column` of `document`
+    pub(crate) fn at(mut self, document: &str, line: usize, column: usize) -> Self {
+        let line_start: usize = document
+            .split_inclusive('\n')
+            .take(line.saturating_sub(1))
+            .map(str::len)
+            .sum();
+        let text = document[line_start..].lines().next().unwrap_or("");
+        let column_start = text
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(text.len(), |(index, _)| index);
+        let chars: Vec<char> = text.chars().collect();
+        let center = text[..column_start].chars().count();
+        let from = center.saturating_sub(SNIPPET_LEN / 2);
+        let to = (from + SNIPPET_LEN).min(chars.len());
+        let mut snippet: String = chars[from..to].iter().collect();
+        if from > 0 {
+            snippet.insert_str(0, "...");
+        }
+        if to < chars.len() {
+            snippet.push_str("...");
+        }
+
+        self.offset = Some(line_start + column_start);
+        self.line = Some(line);
+        self.column = Some(column);
+        self.snippet = Some(snippet.trim().to_string());
+        self
+    }
+
+    /// Locates the error at the byte `offset` of `document`
+    pub(crate) fn at_offset(self, document: &str, offset: usize) -> Self {
+        let before = &document[..offset.min(document.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        let column = before[line_start..].chars().count() + 1;
+        self.at(document, line, column)
+    }
+
+    pub(crate) fn with_path(mut self, path: String) -> Self {
+        self.path = Some(path);
+        self
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, " at line {}, column {}", line, column)?;
+        }
+        if let Some(ref path) = self.path {
+            write!(f, " in {}", path)?;
+        }
+        if let Some(ref snippet) = self.snippet {
+            write!(f, ": `{}`", snippet)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Error)]
+pub enum EurekaError {
+    #[error("{0}")]
+    Network(#[source] ReqwestError),
+    /// No connection to the server could be opened in time
+    #[error("Connect timed out: {0}")]
+    ConnectTimeout(#[source] ReqwestError),
+    /// The server accepted the connection but its response didn't arrive in time
+    #[error("Read timed out: {0}")]
+    ReadTimeout(#[source] ReqwestError),
+    /// Host name which couldn't be resolved, e.g. of a eureka server or an instance
+    #[error("DNS resolution failed: {0}")]
+    Dns(String),
+    #[error("{}", .0.canonical_reason().unwrap_or("Unknown Status Code"))]
+    Request(StatusCode),
+    #[error("Request to {url} failed with {status}: {body}")]
+    RequestDetailed {
+        status: StatusCode,
+        url: String,
+        body: String,
+    },
+    #[error("{0}")]
+    UnexpectedState(String),
+    #[error("{0}")]
+    ParseError(#[from] ParseError),
+    #[error("{0}")]
+    Io(#[source] std::io::Error),
+    #[error("Eureka request rate limit exceeded")]
+    RateLimited,
+    /// The registry has no app of this name
+    #[error("App {0} is not in the registry")]
+    AppNotFound(String),
+    /// No UP instance in the registry has this VIP address
+    #[error("No UP instance has the VIP address {0}")]
+    VipNotFound(String),
+    /// The app is in the registry but none of its `instances` is UP
+    #[error("App {app} has no UP instance out of {instances}")]
+    NoUpInstance { app: String, instances: usize },
+    /// The app is missing from a registry cache which wasn't refreshed for longer than
+    /// expected, `age` being `None` when it was never fetched
+    #[error("App {app} is not in the registry cache, {}", refreshed(.age))]
+    StaleRegistry { app: String, age: Option<Duration> },
+    #[error("Invalid {0}: {1}")]
+    InvalidConfig(&'static str, String),
+    /// `source` failed `operation` on `url`, see [`EurekaError::root`]
+    #[error("{operation} {url} failed: {source}")]
+    Failed {
+        operation: Operation,
+        url: String,
+        #[source]
+        source: Box<EurekaError>,
+    },
+}
+
+impl EurekaError {
+    /// Error of a request reqwest failed to send, telling the timeouts and DNS failures apart
+    /// by the I/O error which caused it
+    pub(crate) fn network(e: ReqwestError) -> Self {
+        match connect_failure(&e) {
+            Some(ConnectFailure::TimedOut) => EurekaError::ConnectTimeout(e),
+            Some(ConnectFailure::Unresolved) => EurekaError::Dns(e.to_string()),
+            _ if e.is_timeout() => EurekaError::ReadTimeout(e),
+            _ => EurekaError::Network(e),
+        }
+    }
+
+    /// Adds the operation and url the error happened on, unless it already has them
+    pub(crate) fn context(self, operation: Operation, url: &str) -> Self {
+        match self {
+            EurekaError::Failed { .. } => self,
+            source => EurekaError::Failed {
+                operation,
+                url: url.to_string(),
+                source: Box::new(source),
+            },
+        }
+    }
+
+    /// The error without its operation context
+    pub fn root(&self) -> &EurekaError {
+        match self {
+            EurekaError::Failed { source, .. } => source.root(),
+            error => error,
+        }
+    }
+
+    pub fn operation(&self) -> Option<Operation> {
+        match self {
+            EurekaError::Failed { operation, .. } => Some(*operation),
+            _ => None,
+        }
+    }
+
+    /// Url of the failed request
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            EurekaError::Failed { url, .. } | EurekaError::RequestDetailed { url, .. } => Some(url),
+            _ => None,
+        }
+    }
+
+    /// Status of the error response
+    pub fn status(&self) -> Option<StatusCode> {
+        match self.root() {
+            EurekaError::Request(status) | EurekaError::RequestDetailed { status, .. } => {
+                Some(*status)
+            }
+            EurekaError::Network(e) => e.status(),
+            _ => None,
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        match self.root() {
+            EurekaError::ConnectTimeout(_) | EurekaError::ReadTimeout(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the server couldn't be reached or didn't answer, as opposed to an error
+    /// response, e.g. to quarantine the servers which keep failing
+    pub fn is_connection_failure(&self) -> bool {
+        match self.root() {
+            EurekaError::Network(_)
+            | EurekaError::ConnectTimeout(_)
+            | EurekaError::ReadTimeout(_)
+            | EurekaError::Dns(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the request couldn't reach the server, which refused the connection, didn't
+    /// accept it in time or whose name didn't resolve, so that it was never sent
+    pub fn is_unreachable(&self) -> bool {
+        match self.root() {
+            EurekaError::ConnectTimeout(_) | EurekaError::Dns(_) => true,
+            EurekaError::Network(e) => connect_failure(e).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Whether sending the request again later may succeed: connection failures, I/O
+    /// errors, 5xx and 429 responses, requests dropped by the rate limiter and apps without
+    /// UP instance or missing from a stale registry cache
+    pub fn is_retryable(&self) -> bool {
+        if self.is_connection_failure() {
+            return true;
+        }
+        match self.root() {
+            EurekaError::Io(_)
+            | EurekaError::RateLimited
+            | EurekaError::NoUpInstance { .. }
+            | EurekaError::StaleRegistry { .. } => true,
+            EurekaError::Request(status) | EurekaError::RequestDetailed { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the app or instance is unknown, e.g. a heartbeat of an instance eureka
+    /// evicted
+    pub fn is_not_found(&self) -> bool {
+        self.status() == Some(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Why no connection to the server could be opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConnectFailure {
+    TimedOut,
+    Refused,
+    Unresolved,
+}
+
+impl ConnectFailure {
+    /// Failure of the connector which returned `e`
+    fn of(e: &io::Error) -> Option<ConnectFailure> {
+        match e.kind() {
+            io::ErrorKind::TimedOut => Some(ConnectFailure::TimedOut),
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkUnreachable => Some(ConnectFailure::Refused),
+            // Unlike the socket's, the resolver's errors carry a message but no OS error code
+            _ if e.raw_os_error().is_none() && e.get_ref().is_some() => {
+                Some(ConnectFailure::Unresolved)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Why reqwest couldn't connect, `None` when the request failed once connected
+fn connect_failure(e: &ReqwestError) -> Option<ConnectFailure> {
+    let inner: &(dyn StdError + 'static) = e.get_ref()?;
+    // reqwest holds its own timeouts and the I/O errors of responses directly, the
+    // connector's errors come wrapped by hyper
+    if inner.is::<io::Error>() {
+        return None;
+    }
+    io_source(inner).and_then(ConnectFailure::of)
+}
+
+/// First I/O error of the source chain of `e`
+fn io_source<'a>(e: &'a (dyn StdError + 'static)) -> Option<&'a io::Error> {
+    let mut next = Some(e);
+    while let Some(error) = next {
+        if let Some(io) = error.downcast_ref::<io::Error>() {
+            return Some(io);
+        }
+        // reqwest's `source` skips the hyper error it wraps
+        next = match error.downcast_ref::<ReqwestError>() {
+            Some(e) => e.get_ref().map(|inner| inner as &(dyn StdError + 'static)),
+            None => error.source(),
+        };
+    }
+    None
+}
+
+fn refreshed(age: &Option<Duration>) -> String {
+    match age {
+        Some(age) => format!("refreshed {} s ago", age.as_secs()),
+        None => "never fetched".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_classification() {
+        let error = EurekaError::RequestDetailed {
+            status: StatusCode::NOT_FOUND,
+            url: "http://eureka/apps/APP/1".to_string(),
+            body: String::new(),
+        }
+        .context(Operation::Heartbeat, "http://eureka/apps/APP/1");
+        assert_eq!(error.operation(), Some(Operation::Heartbeat));
+        assert_eq!(error.url(), Some("http://eureka/apps/APP/1"));
+        assert!(error.is_not_found());
+        assert!(!error.is_retryable());
+        assert!(error
+            .to_string()
+            .starts_with("heartbeat http://eureka/apps/APP/1 failed"));
+
+        let error = EurekaError::Request(StatusCode::SERVICE_UNAVAILABLE)
+            .context(Operation::Fetch, "/apps")
+            .context(Operation::Call, "/ignored");
+        assert_eq!(error.operation(), Some(Operation::Fetch));
+        assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(error.is_retryable());
+        assert!(EurekaError::RateLimited.is_retryable());
+        assert!(!EurekaError::ParseError(ParseError::new("malformed")).is_retryable());
+
+        let error = EurekaError::Dns("failed to lookup address information".to_string())
+            .context(Operation::Register, "http://eureka/apps/APP");
+        assert!(error.is_connection_failure());
+        assert!(error.is_retryable());
+        assert!(error.is_unreachable());
+        assert!(!error.is_timeout());
+        assert!(!EurekaError::Request(StatusCode::BAD_GATEWAY).is_unreachable());
+    }
+
+    #[test]
+    fn test_network_classification() {
+        // Nothing listens on the port of a dropped listener
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let error =
+            EurekaError::network(reqwest::get(&format!("http://127.0.0.1:{}/", port)).unwrap_err());
+        assert!(matches!(error, EurekaError::Network(_)), "{:?}", error);
+        assert!(error.is_unreachable());
+
+        // The connection of a listener which never accepts it is open, but left unanswered
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let error = EurekaError::network(client.get(&url).send().unwrap_err());
+        assert!(matches!(error, EurekaError::ReadTimeout(_)), "{:?}", error);
+        assert!(error.is_timeout());
+        assert!(!error.is_unreachable());
+
+        let unresolved = io::Error::new(io::ErrorKind::Other, "failed to lookup address");
+        assert_eq!(
+            ConnectFailure::of(&unresolved),
+            Some(ConnectFailure::Unresolved)
+        );
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert_eq!(ConnectFailure::of(&refused), Some(ConnectFailure::Refused));
+        assert_eq!(
+            ConnectFailure::of(&io::Error::from(io::ErrorKind::ConnectionReset)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_error_at() {
+        let document = "<instance>\n  <port>80</prot>\n</instance>";
+        let error = ParseError::new("tag mismatch")
+            .at(document, 2, 17)
+            .with_path("instance/port".to_string());
+        assert_eq!(error.offset, Some(27));
+        assert_eq!(error.snippet.as_deref(), Some("<port>80</prot>"));
+        assert_eq!(
+            error.to_string(),
+            "tag mismatch at line 2, column 17 in instance/port: `<port>80</prot>`"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Lifecycle events of the client, for audit trails of the registrations and status changes
+
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::rest::structures::StatusType;
+
+/// Lifecycle event of the client, see
+/// [`EurekaClient::with_event_sender`](crate::EurekaClient::with_event_sender)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClientEvent {
+    /// Milli-seconds since the unix epoch
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// What happened, serialized with a `type` field naming the variant
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum EventKind {
+    /// The instance was registered, or registered again after its lease expired
+    #[serde(rename_all = "camelCase")]
+    Registered { app: String, instance_id: String },
+    #[serde(rename_all = "camelCase")]
+    Deregistered { app: String, instance_id: String },
+    /// The status of the instance was changed on the eureka servers
+    #[serde(rename_all = "camelCase")]
+    StatusChange {
+        app: String,
+        instance_id: String,
+        status: StatusType,
+    },
+    /// A eureka server was quarantined after `failures` failures in a row
+    #[serde(rename_all = "camelCase")]
+    EndpointQuarantined {
+        service_url: String,
+        failures: usize,
+    },
+    /// The registry was fetched, `changed_apps` naming the apps whose instances it changed,
+    /// sorted
+    #[serde(rename_all = "camelCase")]
+    CacheRefreshed {
+        applications: usize,
+        instances: usize,
+        changed_apps: Vec<String>,
+    },
+    /// The other UP instances of the app of the instance changed, `peers` being their ids
+    #[serde(rename_all = "camelCase")]
+    PeersChanged { app: String, peers: Vec<String> },
+}
+
+/// Channel shared by the parts of a client, events are dropped until a sender is set
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Events {
+    sender: Arc<Mutex<Option<Sender<ClientEvent>>>>,
+}
+
+impl Events {
+    pub(crate) fn set_sender(&self, sender: Sender<ClientEvent>) {
+        *self.sender.lock().unwrap() = Some(sender);
+    }
+
+    /// Sends `kind` stamped with the current time, the sender is dropped once the
+    /// receiver hangs up
+    pub(crate) fn emit(&self, kind: EventKind) {
+        let mut sender = self.sender.lock().unwrap();
+        if let Some(ref tx) = *sender {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as u64);
+            if tx.send(ClientEvent { timestamp, kind }).is_err() {
+                *sender = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_emit() {
+        let events = Events::default();
+        // No receiver yet, nothing to send to
+        events.emit(EventKind::CacheRefreshed {
+            applications: 1,
+            instances: 2,
+            changed_apps: vec!["APP".to_string()],
+        });
+
+        let (tx, rx) = channel();
+        events.clone().set_sender(tx);
+        events.emit(EventKind::StatusChange {
+            app: "APP".to_string(),
+            instance_id: "host:app:8080".to_string(),
+            status: StatusType::OutOfService,
+        });
+        let event = rx.try_recv().unwrap();
+        assert!(rx.try_recv().is_err());
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "timestamp": event.timestamp,
+                "type": "StatusChange",
+                "app": "APP",
+                "instanceId": "host:app:8080",
+                "status": "OUT_OF_SERVICE",
+            })
+        );
+
+        drop(rx);
+        events.emit(EventKind::Deregistered {
+            app: "APP".to_string(),
+            instance_id: "host:app:8080".to_string(),
+        });
+        assert!(events.sender.lock().unwrap().is_none());
+    }
+}
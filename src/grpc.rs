@@ -0,0 +1,131 @@
+//! `tonic` channels balancing over the instances of an app, behind the `tonic` feature
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tokio::time::{interval, MissedTickBehavior};
+use tonic::transport::channel::Change;
+use tonic::transport::{Channel, Endpoint};
+
+use crate::registry::up_instances;
+use crate::rest::structures::Instance;
+use crate::{host_port, EurekaClient};
+
+impl EurekaClient {
+    /// gRPC channel balancing over the UP instances of `app`
+    ///
+    /// Instances with an enabled secure port are dialed with `https` on it. The endpoints
+    /// follow the registry cache, compared every `registry_fetch_interval` by a task spawned
+    /// on the `tokio` runtime until the channel is dropped.
+    pub fn grpc_channel(&self, app: &str) -> Channel {
+        self.grpc_channel_with(app, |endpoint| endpoint)
+    }
+
+    /// Same as [`grpc_channel`](EurekaClient::grpc_channel), with `configure` applied to the
+    /// endpoint of each instance, e.g. to set timeouts or TLS
+    pub fn grpc_channel_with<F>(&self, app: &str, configure: F) -> Channel
+    where
+        F: Fn(Endpoint) -> Endpoint + Send + 'static,
+    {
+        let (channel, changes) = Channel::balance_channel(16);
+        let app_cache = self.registry.app_cache();
+        let app = app.to_string();
+        let refresh_interval =
+            Duration::from_millis(self.config.eureka.registry_fetch_interval.max(1) as u64);
+        tokio::spawn(async move {
+            let mut known = HashSet::new();
+            let mut refresh = interval(refresh_interval);
+            refresh.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            while !changes.is_closed() {
+                refresh.tick().await;
+                let uris = up_instances(&app_cache, &app)
+                    .iter()
+                    .map(|instance| instance_uri(instance))
+                    .collect();
+                for change in endpoint_changes(&mut known, uris) {
+                    let change = match change {
+                        Change::Insert(uri, _) => match Endpoint::from_shared(uri.clone()) {
+                            Ok(endpoint) => Change::Insert(uri, configure(endpoint)),
+                            Err(e) => {
+                                warn!("Invalid gRPC endpoint {} of {}: {}", uri, app, e);
+                                continue;
+                            }
+                        },
+                        Change::Remove(uri) => Change::Remove(uri),
+                    };
+                    if changes.send(change).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        channel
+    }
+}
+
+/// `https` uri of the secure port when enabled, `http` one of the port otherwise
+fn instance_uri(instance: &Instance) -> String {
+    if instance.secure_port.enabled {
+        format!(
+            "https://{}",
+            host_port(&instance.ip_addr, instance.secure_port.value)
+        )
+    } else {
+        format!(
+            "http://{}",
+            host_port(&instance.ip_addr, instance.port.value)
+        )
+    }
+}
+
+/// Removals of the `known` uris gone from `uris` and insertions of the new ones
+fn endpoint_changes(known: &mut HashSet<String>, uris: HashSet<String>) -> Vec<Change<String, ()>> {
+    let mut changes: Vec<_> = known
+        .difference(&uris)
+        .map(|uri| Change::Remove(uri.clone()))
+        .collect();
+    changes.extend(
+        uris.difference(known)
+            .map(|uri| Change::Insert(uri.clone(), ())),
+    );
+    *known = uris;
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::structures::{PortData, SecurePort};
+
+    #[test]
+    fn test_endpoint_changes() {
+        let mut instance = Instance::default();
+        instance.ip_addr = "10.0.0.1".to_string();
+        instance.port = PortData::new(8080, true);
+        assert_eq!(instance_uri(&instance), "http://10.0.0.1:8080");
+        instance.secure_port = SecurePort::new(8443, true);
+        assert_eq!(instance_uri(&instance), "https://10.0.0.1:8443");
+
+        let uris = |uris: &[&str]| uris.iter().map(|uri| uri.to_string()).collect();
+        let describe = |changes: Vec<Change<String, ()>>| -> Vec<String> {
+            changes
+                .into_iter()
+                .map(|change| match change {
+                    Change::Insert(uri, _) => format!("+{}", uri),
+                    Change::Remove(uri) => format!("-{}", uri),
+                })
+                .collect()
+        };
+        let mut known = HashSet::new();
+        assert_eq!(
+            describe(endpoint_changes(&mut known, uris(&["http://a:80"]))),
+            vec!["+http://a:80"]
+        );
+        assert!(endpoint_changes(&mut known, uris(&["http://a:80"])).is_empty());
+        assert_eq!(
+            describe(endpoint_changes(&mut known, uris(&["http://b:80"]))),
+            vec!["-http://a:80", "+http://b:80"]
+        );
+    }
+}
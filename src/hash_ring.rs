@@ -0,0 +1,151 @@
+//! Consistent hashing of keys to the instances of an app, see
+//! [`crate::EurekaClient::hash_ring`]
+
+use crate::address::ResolvedInstance;
+
+/// Consistent hash ring of the UP instances of an app, mapping keys to the instance owning
+/// them, e.g. the cache node of a key or the consumer of a partition
+///
+/// Each instance is placed on the ring `virtual_nodes` times. Adding or removing an
+/// instance only moves the keys it gains or loses, and the clients building a ring of the
+/// same instances map the keys alike.
+#[derive(Debug, Clone)]
+pub struct HashRing {
+    /// Instances sorted by instance id
+    instances: Vec<ResolvedInstance>,
+    /// Hash of each virtual node and the index of its instance, sorted by hash
+    points: Vec<(u64, usize)>,
+    virtual_nodes: usize,
+}
+
+impl HashRing {
+    /// Virtual nodes per instance giving each instance a share of the keys within a few
+    /// percents
+    pub const DEFAULT_VIRTUAL_NODES: usize = 160;
+
+    /// Ring of `instances`, each placed `virtual_nodes` times
+    pub fn new(mut instances: Vec<ResolvedInstance>, virtual_nodes: usize) -> Self {
+        instances.sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+        instances.dedup_by(|a, b| a.instance_id == b.instance_id);
+        let mut points = Vec::with_capacity(instances.len() * virtual_nodes);
+        for (index, instance) in instances.iter().enumerate() {
+            for node in 0..virtual_nodes {
+                let point = format!("{}#{}", instance.instance_id, node);
+                points.push((hash(point.as_bytes()), index));
+            }
+        }
+        points.sort();
+        HashRing {
+            instances,
+            points,
+            virtual_nodes,
+        }
+    }
+
+    /// Instance owning `key`, the first one clockwise of its hash
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<&ResolvedInstance> {
+        self.get_n(key, 1).into_iter().next()
+    }
+
+    /// Up to `n` distinct instances clockwise of the hash of `key`, its owner first, e.g.
+    /// the replicas of a key
+    pub fn get_n<K: AsRef<[u8]>>(&self, key: K, n: usize) -> Vec<&ResolvedInstance> {
+        let n = n.min(self.instances.len());
+        if n == 0 {
+            return Vec::new();
+        }
+        let mut owners: Vec<usize> = Vec::with_capacity(n);
+        let hash = hash(key.as_ref());
+        let start = self.points.partition_point(|&(point, _)| point < hash);
+        let clockwise = self.points[start..].iter().chain(&self.points[..start]);
+        for &(_, index) in clockwise {
+            if !owners.contains(&index) {
+                owners.push(index);
+                if owners.len() == n {
+                    break;
+                }
+            }
+        }
+        owners
+            .into_iter()
+            .map(|index| &self.instances[index])
+            .collect()
+    }
+
+    /// The instances of the ring, sorted by instance id
+    pub fn instances(&self) -> &[ResolvedInstance] {
+        &self.instances
+    }
+
+    pub fn virtual_nodes(&self) -> usize {
+        self.virtual_nodes
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+}
+
+/// 64-bit FNV-1a of `bytes` with the finalizer of MurmurHash3 spreading its bits, stable
+/// across processes and versions unlike the hasher of the standard library
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::instance;
+    use std::collections::HashMap;
+
+    fn ring_of(ids: &[&str]) -> HashRing {
+        let instances = ids
+            .iter()
+            .map(|id| ResolvedInstance::new(&instance("CACHE").id(id).up().build(), false))
+            .collect();
+        HashRing::new(instances, HashRing::DEFAULT_VIRTUAL_NODES)
+    }
+
+    #[test]
+    fn test_hash_ring() {
+        assert_eq!(hash(b""), hash(b""));
+        assert_ne!(hash(b"key-1"), hash(b"key-2"));
+
+        let ring = ring_of(&["cache-3", "cache-1", "cache-2"]);
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.instances()[0].instance_id, "cache-1");
+        let replicas = ring.get_n("user:42", 5);
+        assert_eq!(replicas.len(), 3);
+        assert_eq!(replicas[0], ring.get("user:42").unwrap());
+        assert_ne!(replicas[0].instance_id, replicas[1].instance_id);
+
+        // Removing an instance only moves its own keys
+        let smaller = ring_of(&["cache-1", "cache-3"]);
+        let mut owned = HashMap::new();
+        for key in 0..1000 {
+            let key = format!("key-{}", key);
+            let owner = &ring.get(&key).unwrap().instance_id;
+            *owned.entry(owner.clone()).or_insert(0) += 1;
+            if owner != "cache-2" {
+                assert_eq!(&smaller.get(&key).unwrap().instance_id, owner);
+            }
+        }
+        assert_eq!(owned.len(), 3);
+        assert!(owned.values().all(|&count| count > 200), "{:?}", owned);
+
+        assert!(ring_of(&[]).get("key").is_none());
+    }
+}
@@ -0,0 +1,273 @@
+//! Health of the eureka client, for readiness and liveness probes
+
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+/// Health of the eureka client, see [`EurekaClient::health`](crate::EurekaClient::health)
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    /// Whether the instance is registered, false when `register_with_eureka` is off
+    pub registered: bool,
+    /// Milli-seconds since the last successful heartbeat
+    pub last_heartbeat_age: Option<u64>,
+    /// Milli-seconds since the last successful registry fetch
+    pub registry_age: Option<u64>,
+    /// Whether the last heartbeat or registry fetch reached a eureka server
+    pub eureka_reachable: bool,
+    /// Service urls of the eureka servers quarantined after repeated failures
+    pub quarantined_endpoints: Vec<String>,
+    /// Error of the last heartbeat, cleared by a successful one
+    pub last_heartbeat_error: Option<String>,
+    /// Error of the last registry fetch, cleared by a successful one
+    pub last_registry_error: Option<String>,
+    /// Whether a thread panicked while holding the registry cache, which is still served
+    pub registry_degraded: bool,
+}
+
+impl HealthReport {
+    /// Spring Boot actuator status, `UP` when the last heartbeat or registry fetch reached
+    /// a eureka server
+    pub fn status(&self) -> &'static str {
+        if self.eureka_reachable {
+            "UP"
+        } else {
+            "DOWN"
+        }
+    }
+
+    /// Body of a Spring Boot actuator `/health` endpoint, the report is the `eureka` component
+    pub fn to_actuator(&self) -> Value {
+        json!({
+            "status": self.status(),
+            "components": {
+                "eureka": {
+                    "status": self.status(),
+                    "details": self,
+                },
+            },
+        })
+    }
+}
+
+/// Round-trip times of the last runs of an operation, in milli-seconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyPercentiles {
+    /// Number of runs measured, at most the last 128
+    pub samples: usize,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl LatencyPercentiles {
+    fn from_samples(samples: &VecDeque<u64>) -> Self {
+        let mut sorted: Vec<u64> = samples.iter().cloned().collect();
+        sorted.sort_unstable();
+        // Nearest rank
+        let percentile = |p: usize| match sorted.len() {
+            0 => 0,
+            len => sorted[(p * len + 99) / 100 - 1],
+        };
+        LatencyPercentiles {
+            samples: sorted.len(),
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: sorted.last().cloned().unwrap_or(0),
+        }
+    }
+}
+
+/// Heartbeat and registry fetch latencies, see
+/// [`EurekaClient::latency_stats`](crate::EurekaClient::latency_stats)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyStats {
+    pub heartbeat: LatencyPercentiles,
+    pub registry_fetch: LatencyPercentiles,
+}
+
+/// Receiver of the round-trip time of each run of an operation, e.g. a metrics exporter
+pub(crate) trait LatencyObserver: Debug + Send + Sync {
+    fn observe(&self, elapsed: Duration, success: bool);
+}
+
+/// Outcome and latency of the last runs of a periodic eureka operation
+#[derive(Debug, Default)]
+pub(crate) struct OperationStatus {
+    last_success: Option<Instant>,
+    last_error: Option<String>,
+    latencies: VecDeque<u64>,
+    observer: Option<Arc<dyn LatencyObserver>>,
+}
+
+impl OperationStatus {
+    const LATENCY_SAMPLES: usize = 128;
+
+    /// Records the outcome of a run which took `elapsed`
+    pub(crate) fn record<T, E: Display>(&mut self, result: &Result<T, E>, elapsed: Duration) {
+        if self.latencies.len() == OperationStatus::LATENCY_SAMPLES {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(elapsed.as_millis() as u64);
+        if let Some(ref observer) = self.observer {
+            observer.observe(elapsed, result.is_ok());
+        }
+        match result {
+            Ok(_) => {
+                self.last_success = Some(Instant::now());
+                self.last_error = None;
+            }
+            Err(e) => self.last_error = Some(e.to_string()),
+        }
+    }
+
+    pub(crate) fn set_observer(&mut self, observer: Arc<dyn LatencyObserver>) {
+        self.observer = Some(observer);
+    }
+
+    /// Milli-seconds since the last success
+    pub(crate) fn age(&self) -> Option<u64> {
+        self.last_success
+            .map(|instant| instant.elapsed().as_millis() as u64)
+    }
+
+    pub(crate) fn last_error(&self) -> Option<String> {
+        self.last_error.clone()
+    }
+
+    pub(crate) fn latency(&self) -> LatencyPercentiles {
+        LatencyPercentiles::from_samples(&self.latencies)
+    }
+
+    /// Whether the last run succeeded
+    pub(crate) fn is_ok(&self) -> bool {
+        self.last_success.is_some() && self.last_error.is_none()
+    }
+}
+
+/// Error of a past eureka operation, see
+/// [`EurekaClient::recent_errors`](crate::EurekaClient::recent_errors)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientError {
+    /// Milli-seconds since the unix epoch
+    pub timestamp: u64,
+    /// Operation that failed, e.g. `heartbeat`, `fetch_registry` or `make_request`
+    pub operation: &'static str,
+    pub message: String,
+}
+
+/// Ring buffer of the last errors of the client
+#[derive(Debug)]
+pub(crate) struct ErrorLog {
+    capacity: usize,
+    errors: Mutex<VecDeque<ClientError>>,
+    total: AtomicUsize,
+}
+
+impl Default for ErrorLog {
+    fn default() -> Self {
+        ErrorLog::new(ErrorLog::DEFAULT_CAPACITY)
+    }
+}
+
+impl ErrorLog {
+    pub(crate) const DEFAULT_CAPACITY: usize = 32;
+
+    pub(crate) fn new(capacity: usize) -> Self {
+        ErrorLog {
+            capacity,
+            errors: Mutex::new(VecDeque::with_capacity(capacity)),
+            total: AtomicUsize::new(0),
+        }
+    }
+
+    /// Records the `error` of `operation`, dropping the oldest error when full
+    pub(crate) fn push<E: Display + ?Sized>(&self, operation: &'static str, error: &E) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        if self.capacity == 0 {
+            return;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() == self.capacity {
+            errors.pop_front();
+        }
+        errors.push_back(ClientError {
+            timestamp,
+            operation,
+            message: error.to_string(),
+        });
+    }
+
+    /// Number of errors recorded since the client was created
+    pub(crate) fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// Recorded errors, oldest first
+    pub(crate) fn errors(&self) -> Vec<ClientError> {
+        self.errors.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_percentiles() {
+        let mut status = OperationStatus::default();
+        assert_eq!(status.latency(), LatencyPercentiles::default());
+        for ms in (1..=200).rev() {
+            status.record(&Ok::<_, String>(()), Duration::from_millis(ms));
+        }
+        let latency = status.latency();
+        assert_eq!(latency.samples, 128);
+        assert_eq!(latency.p50, 64);
+        assert_eq!(latency.p90, 116);
+        assert_eq!(latency.p99, 127);
+        assert_eq!(latency.max, 128);
+    }
+
+    #[test]
+    fn test_actuator() {
+        let mut report = HealthReport {
+            registered: true,
+            ..HealthReport::default()
+        };
+        assert_eq!(report.to_actuator()["status"], "DOWN");
+        report.eureka_reachable = true;
+        let actuator = report.to_actuator();
+        assert_eq!(actuator["status"], "UP");
+        assert_eq!(
+            actuator["components"]["eureka"]["details"]["registered"],
+            true
+        );
+    }
+
+    #[test]
+    fn test_error_log() {
+        let log = ErrorLog::new(2);
+        log.push("register", "first");
+        log.push("heartbeat", "second");
+        log.push("fetch_registry", "third");
+        let errors = log.errors();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].operation, "heartbeat");
+        assert_eq!(errors[0].message, "second");
+        assert_eq!(errors[1].operation, "fetch_registry");
+        assert_eq!(log.total(), 3);
+    }
+}
@@ -1,27 +1,82 @@
-pub use crate::rest::structures::{Instance, PortData, SecurePort, StatusType};
-use crate::rest::EurekaRestClient;
-use crate::EurekaError;
+use crate::clock::SharedClock;
+use crate::diagnostics::Diagnostics;
+use crate::events::{EventKind, Events};
+use crate::health::{ErrorLog, HealthReport, LatencyObserver, LatencyPercentiles, OperationStatus};
+use crate::rest::structures::{Instance, StatusType};
+use crate::rest::EurekaApi;
+use crate::{trace, EurekaError};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub struct InstanceClient {
-    client: Arc<EurekaRestClient>,
+    client: Arc<dyn EurekaApi>,
     config: Arc<Instance>,
     is_running: Arc<AtomicBool>,
+    registered: Arc<AtomicBool>,
+    /// Whether `stop` or `drain` deregistered the instance, which isn't deregistered again
+    /// when dropped
+    deregistered: AtomicBool,
+    heartbeat: Arc<RwLock<OperationStatus>>,
+    errors: Arc<ErrorLog>,
+    diagnostics: Diagnostics,
+    events: Events,
+    clock: SharedClock,
+    heartbeat_interval: Duration,
 }
 
 impl InstanceClient {
-    pub fn new(base_url: String, config: Instance) -> Self {
+    /// Lease renewal interval of the eureka clients
+    const DEFAULT_RENEWAL_INTERVAL_IN_SECS: u32 = 30;
+    const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+    pub fn new(client: Arc<dyn EurekaApi>, config: Instance) -> Self {
         InstanceClient {
-            client: Arc::new(EurekaRestClient::new(base_url)),
+            client,
             config: Arc::new(config),
             is_running: Arc::new(AtomicBool::new(false)),
+            registered: Arc::new(AtomicBool::new(false)),
+            deregistered: AtomicBool::new(false),
+            heartbeat: Arc::new(RwLock::new(OperationStatus::default())),
+            errors: Arc::new(ErrorLog::default()),
+            diagnostics: Diagnostics::default(),
+            events: Events::default(),
+            clock: SharedClock::default(),
+            heartbeat_interval: InstanceClient::DEFAULT_HEARTBEAT_INTERVAL,
         }
     }
 
+    /// Keep the errors of the registration and heartbeats in `errors`
+    pub(crate) fn with_error_log(mut self, errors: Arc<ErrorLog>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    pub(crate) fn with_diagnostics(mut self, diagnostics: Diagnostics) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Report the registrations and status changes on `events`
+    pub(crate) fn with_events(mut self, events: Events) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Time the heartbeats and registration retries with `clock`
+    pub(crate) fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Send a heartbeat every `heartbeat_interval`
+    pub(crate) fn with_heartbeat_interval(mut self, heartbeat_interval: Duration) -> Self {
+        self.heartbeat_interval = heartbeat_interval;
+        self
+    }
+
     fn get_instance_id(&self) -> String {
         let mut instance_id = self.config.host_name.clone();
         if let Some(ref inst_id) = self.config.instance_id {
@@ -30,69 +85,393 @@ impl InstanceClient {
         instance_id
     }
 
+    /// Registers the instance, retrying until it succeeds, then sends its heartbeats
     pub fn start(&self) {
-        while let Err(e) = self.client.register(&self.config.app, &*self.config) {
-            error!("Failed to register app: {}", e);
-            thread::sleep(Duration::from_secs(15));
-        }
-        debug!("Registered app with eureka");
+        // Without a deadline the retries only end with a success
+        let _ = self.start_until(None);
+    }
+
+    /// Registers the instance and sends its heartbeats, failing on an error which retries
+    /// won't fix, e.g. a 400 of an invalid registration, or when still failing after `deadline`
+    pub fn try_start(&self, deadline: Duration) -> Result<(), EurekaError> {
+        self.start_until(Some(self.clock.now() + deadline))
+    }
+
+    fn start_until(&self, deadline: Option<Instant>) -> Result<(), EurekaError> {
+        let instance_id = self.get_instance_id();
+        self.retry("register", "register app", deadline, || {
+            trace::instrument("register", &self.config.app, &instance_id, || {
+                self.client.register(&self.config.app, &*self.config)
+            })
+        })?;
+        diag!(self.diagnostics, Debug, "Registered app with eureka");
+        self.registered.store(true, Ordering::Relaxed);
+        self.deregistered.store(false, Ordering::Relaxed);
+        self.events.emit(EventKind::Registered {
+            app: self.config.app.clone(),
+            instance_id: instance_id.clone(),
+        });
 
         self.is_running.store(true, Ordering::Relaxed);
 
         let is_running = Arc::clone(&self.is_running);
         let client = Arc::clone(&self.client);
         let config = Arc::clone(&self.config);
-        let instance_id = self.get_instance_id();
+        let registered = Arc::clone(&self.registered);
+        let heartbeat = Arc::clone(&self.heartbeat);
+        let errors = Arc::clone(&self.errors);
+        let diagnostics = self.diagnostics.clone();
+        let events = self.events.clone();
+        let clock = self.clock.clone();
+        let heartbeat_interval = self.heartbeat_interval;
+        let renewal_interval = Duration::from_secs(u64::from(
+            self.config
+                .lease_info
+                .as_ref()
+                .and_then(|lease_info| lease_info.renewal_interval_in_secs)
+                .unwrap_or(InstanceClient::DEFAULT_RENEWAL_INTERVAL_IN_SECS),
+        ));
         thread::spawn(move || {
             let do_regist = || {
-                match client.register(&config.app, &*config) {
+                match trace::instrument("register", &config.app, &instance_id, || {
+                    client.register(&config.app, &*config)
+                }) {
                     Ok(_) => {
-                        info!("update status to UP");
+                        registered.store(true, Ordering::Relaxed);
+                        events.emit(EventKind::Registered {
+                            app: config.app.clone(),
+                            instance_id: instance_id.clone(),
+                        });
+                        diag!(diagnostics, Info, "update status to UP");
                         client
                             .update_status(&config.app, &instance_id, StatusType::Up)
-                            .map(|_| info!("update status ok!"))
-                            .map_err(|err| error!("Failed to set app to UP: {}", err));
+                            .map(|_| {
+                                diag!(diagnostics, Info, "update status ok!");
+                                events.emit(EventKind::StatusChange {
+                                    app: config.app.clone(),
+                                    instance_id: instance_id.clone(),
+                                    status: StatusType::Up,
+                                });
+                            })
+                            .map_err(|err| {
+                                diag!(diagnostics, Error, "Failed to set app to UP: {}", err);
+                                errors.push("update_status", &err);
+                            });
                     }
                     Err(e) => {
-                        error!("Failed to register app: {}", e);
+                        diag!(diagnostics, Error, "Failed to register app: {}", e);
+                        errors.push("register", &e);
                     }
                 };
             };
-            thread::sleep(Duration::from_secs(30));
+            clock.sleep(heartbeat_interval);
             while is_running.load(Ordering::Relaxed) {
-                let resp = client.send_heartbeat(&config.app, &instance_id);
+                let start = clock.now();
+                let resp = trace::instrument("heartbeat", &config.app, &instance_id, || {
+                    client.send_heartbeat(&config.app, &instance_id)
+                });
+                let elapsed = clock.now() - start;
+                heartbeat.write().unwrap().record(&resp, elapsed);
+                // The lease expires after a few missed renewals, a slow heartbeat is an early sign
+                if elapsed >= renewal_interval / 2 {
+                    diag!(
+                        diagnostics,
+                        Warn,
+                        "Heartbeat took {:?}, close to the {:?} lease renewal interval",
+                        elapsed,
+                        renewal_interval
+                    );
+                }
+                if let Err(ref e) = resp {
+                    errors.push("heartbeat", e);
+                }
                 match resp {
-                    Err(EurekaError::UnexpectedState(_)) => {
-                        warn!("App not registered with eureka, reregistering");
+                    Err(ref e) if e.is_not_found() => {
+                        diag!(
+                            diagnostics,
+                            Warn,
+                            "App not registered with eureka, reregistering"
+                        );
+                        registered.store(false, Ordering::Relaxed);
                         do_regist();
                     }
+                    Err(ref e) if matches!(e.root(), EurekaError::RateLimited) => {
+                        diag!(diagnostics, Warn, "Heartbeat dropped by rate limiter");
+                    }
+                    // The lease outlives a few missed renewals, the next heartbeat may well succeed
                     Err(e) => {
-                        error!("Failed to send heartbeat: {}, reregistering", e);
-                        do_regist();
+                        diag!(diagnostics, Error, "Failed to send heartbeat: {}", e);
                     }
                     Ok(_) => {
-                        debug!("Sent heartbeat successfully");
+                        diag!(diagnostics, Debug, "Sent heartbeat successfully");
                     }
                 }
-                thread::sleep(Duration::from_secs(30));
+                clock.sleep(heartbeat_interval);
             }
         });
 
-        while let Err(e) =
+        if let Err(e) = self.retry("update_status", "set app to UP", deadline, || {
             self.client
                 .update_status(&self.config.app, &self.get_instance_id(), StatusType::Up)
-        {
-            error!("Failed to set app to UP: {}", e);
-            thread::sleep(Duration::from_secs(15));
+        }) {
+            self.is_running.store(false, Ordering::Relaxed);
+            // An instance which never went UP isn't left in the registry
+            if let Err(error) = self.deregister(&self.get_instance_id()) {
+                diag!(
+                    self.diagnostics,
+                    Error,
+                    "Failed to deregister app: {}",
+                    error
+                );
+                self.errors.push("deregister", &error);
+            }
+            return Err(e);
+        }
+        self.events.emit(EventKind::StatusChange {
+            app: self.config.app.clone(),
+            instance_id: self.get_instance_id(),
+            status: StatusType::Up,
+        });
+        Ok(())
+    }
+
+    /// Runs `request` every 15 seconds until it succeeds. With a `deadline`, gives up once
+    /// it passed or on an error which isn't retryable
+    fn retry<F>(
+        &self,
+        operation: &'static str,
+        description: &str,
+        deadline: Option<Instant>,
+        request: F,
+    ) -> Result<(), EurekaError>
+    where
+        F: Fn() -> Result<(), EurekaError>,
+    {
+        let interval = Duration::from_secs(15);
+        loop {
+            let e = match request() {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            diag!(self.diagnostics, Error, "Failed to {}: {}", description, e);
+            self.errors.push(operation, &e);
+            match deadline {
+                Some(deadline) => {
+                    let now = self.clock.now();
+                    if !e.is_retryable() || now >= deadline {
+                        return Err(e);
+                    }
+                    self.clock.sleep(interval.min(deadline - now));
+                }
+                None => self.clock.sleep(interval),
+            }
         }
     }
+
+    fn deregister(&self, instance_id: &str) -> Result<(), EurekaError> {
+        self.client.deregister(&self.config.app, instance_id)?;
+        self.registered.store(false, Ordering::Relaxed);
+        self.deregistered.store(true, Ordering::Relaxed);
+        self.events.emit(EventKind::Deregistered {
+            app: self.config.app.clone(),
+            instance_id: instance_id.to_string(),
+        });
+        Ok(())
+    }
+}
+
+impl InstanceClient {
+    /// Sends the round-trip time of each heartbeat to `observer`
+    pub(crate) fn set_heartbeat_observer(&self, observer: Arc<dyn LatencyObserver>) {
+        self.heartbeat.write().unwrap().set_observer(observer);
+    }
+
+    pub(crate) fn is_registered(&self) -> bool {
+        self.registered.load(Ordering::Relaxed)
+    }
+
+    /// Round-trip times of the last heartbeats
+    pub fn heartbeat_latency(&self) -> LatencyPercentiles {
+        self.heartbeat.read().unwrap().latency()
+    }
+
+    /// Fills the registration and heartbeat state of `report`
+    pub fn report_health(&self, report: &mut HealthReport) {
+        let heartbeat = self.heartbeat.read().unwrap();
+        report.registered = self.registered.load(Ordering::Relaxed);
+        report.last_heartbeat_age = heartbeat.age();
+        report.last_heartbeat_error = heartbeat.last_error();
+        report.eureka_reachable |= heartbeat.is_ok();
+    }
+
+    /// Sets the status of the instance on the eureka servers
+    pub fn set_status(&self, status: StatusType) -> Result<(), EurekaError> {
+        let instance_id = self.get_instance_id();
+        self.client
+            .update_status(&self.config.app, &instance_id, status)
+            .map_err(|e| {
+                self.errors.push("update_status", &e);
+                e
+            })?;
+        self.events.emit(EventKind::StatusChange {
+            app: self.config.app.clone(),
+            instance_id,
+            status,
+        });
+        Ok(())
+    }
+
+    /// Stops the heartbeats and deregisters the instance
+    pub fn stop(&self) {
+        self.is_running.store(false, Ordering::Relaxed);
+        self.deregister(&self.get_instance_id()).map_err(|e| {
+            diag!(self.diagnostics, Error, "Failed to deregister app: {}", e);
+            self.errors.push("deregister", &e);
+        });
+    }
+
+    /// Stops the heartbeats and marks the instance OUT_OF_SERVICE, then deregisters
+    /// it after `delay` so that peers stop calling it first
+    pub fn drain(&self, delay: Duration) {
+        self.is_running.store(false, Ordering::Relaxed);
+        let instance_id = self.get_instance_id();
+        diag!(
+            self.diagnostics,
+            Info,
+            "Draining instance {} for {:?}",
+            instance_id,
+            delay
+        );
+        self.client
+            .update_status(&self.config.app, &instance_id, StatusType::OutOfService)
+            .map(|_| {
+                self.events.emit(EventKind::StatusChange {
+                    app: self.config.app.clone(),
+                    instance_id: instance_id.clone(),
+                    status: StatusType::OutOfService,
+                })
+            })
+            .map_err(|e| {
+                diag!(
+                    self.diagnostics,
+                    Error,
+                    "Failed to set app to OUT_OF_SERVICE: {}",
+                    e
+                );
+                self.errors.push("update_status", &e);
+            });
+        self.clock.sleep(delay);
+        self.deregister(&instance_id).map_err(|e| {
+            diag!(self.diagnostics, Error, "Failed to deregister app: {}", e);
+            self.errors.push("deregister", &e);
+        });
+    }
 }
 
 impl Drop for InstanceClient {
     fn drop(&mut self) {
         self.is_running.store(false, Ordering::Relaxed);
-        let _ = self
-            .client
-            .deregister(&self.config.app, &self.get_instance_id());
+        if !self.deregistered.load(Ordering::Relaxed) {
+            let _ = self.deregister(&self.get_instance_id());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::test_util::{self, Faults, FaultyApi, ReplayApi};
+    use crate::StatusCode;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_try_start() {
+        let api = Arc::new(FaultyApi::new(Arc::new(ReplayApi::new("replay"))));
+        api.set_faults(Faults {
+            server_errors: 10,
+            ..Faults::default()
+        });
+        let mock = Arc::new(MockClock::default());
+        let clock = SharedClock::default();
+        clock.set_clock(Arc::clone(&mock) as Arc<dyn Clock>);
+        let api_client = Arc::clone(&api) as Arc<dyn EurekaApi>;
+        let client =
+            InstanceClient::new(api_client, test_util::instance("APP").build()).with_clock(clock);
+
+        let error = thread::scope(|scope| {
+            let start = scope.spawn(|| client.try_start(Duration::from_secs(40)));
+            // Retried after 15 s, 15 s and the 10 s left
+            for _ in 0..3 {
+                mock.wait_for_sleepers(1);
+                assert!(mock.advance_to_next_wakeup());
+            }
+            start.join().unwrap()
+        })
+        .unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(mock.elapsed(), Duration::from_secs(40));
+        assert!(!client.is_registered());
+
+        api.set_faults(Faults::default());
+        client.try_start(Duration::from_secs(40)).unwrap();
+        assert!(client.is_registered());
+
+        // The heartbeats wait for the time to pass their interval
+        let mut report = HealthReport::default();
+        mock.wait_for_sleepers(1);
+        client.report_health(&mut report);
+        assert_eq!(report.last_heartbeat_age, None);
+        assert!(mock.advance_to_next_wakeup());
+        mock.wait_for_sleepers(1);
+        client.report_health(&mut report);
+        assert!(report.last_heartbeat_age.is_some());
+        assert_eq!(mock.elapsed(), Duration::from_secs(70));
+
+        client.stop();
+        // Woken up, the heartbeat thread stops
+        assert!(mock.advance_to_next_wakeup());
+    }
+
+    #[test]
+    fn test_deregister_once() {
+        let api = Arc::new(FaultyApi::new(Arc::new(ReplayApi::new("replay"))));
+        api.set_faults(Faults {
+            status_errors: 1,
+            ..Faults::default()
+        });
+        let mock = Arc::new(MockClock::default());
+        let clock = SharedClock::default();
+        clock.set_clock(Arc::clone(&mock) as Arc<dyn Clock>);
+        let (sender, receiver) = mpsc::channel();
+        let events = Events::default();
+        events.set_sender(sender);
+        let api_client = Arc::clone(&api) as Arc<dyn EurekaApi>;
+        let client = InstanceClient::new(api_client, test_util::instance("APP").build())
+            .with_clock(clock)
+            .with_events(events);
+        let deregistered = |kinds: &[EventKind]| {
+            kinds
+                .iter()
+                .filter(|kind| matches!(kind, EventKind::Deregistered { .. }))
+                .count()
+        };
+
+        // Registered but failing to go UP, the instance is deregistered
+        assert!(client.try_start(Duration::from_secs(0)).is_err());
+        assert!(!client.is_registered());
+        let kinds: Vec<_> = receiver.try_iter().map(|event| event.kind).collect();
+        assert!(matches!(kinds[0], EventKind::Registered { .. }));
+        assert_eq!(deregistered(&kinds), 1);
+
+        // Stopped, it isn't deregistered again when dropped
+        client.try_start(Duration::from_secs(0)).unwrap();
+        client.stop();
+        mock.wait_for_sleepers(2);
+        drop(client);
+        let kinds: Vec<_> = receiver.try_iter().map(|event| event.kind).collect();
+        assert_eq!(deregistered(&kinds), 1);
+        // Woken up, the heartbeat threads stop
+        while mock.advance_to_next_wakeup() {}
     }
 }
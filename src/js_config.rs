@@ -0,0 +1,153 @@
+//! Configs in the eureka-js-client format, easing the migration of Node services
+
+use serde_json::{Map, Value};
+
+use crate::rest::structures::Instance;
+use crate::{BaseConfig, EurekaConfig, EurekaError, ParseError};
+
+impl BaseConfig {
+    /// Reads a config in the eureka-js-client format, e.g.
+    /// `{ "eureka": { "host", "port", "servicePath" }, "instance": { "port": { "$", "@enabled" } } }`
+    ///
+    /// Missing settings keep their default. eureka-js-client options unknown to this client
+    /// are ignored, `ec2Region` is read as `region` and the `/apps/` suffix of its service
+    /// paths and urls is dropped.
+    pub fn from_json_value(value: Value) -> Result<Self, EurekaError> {
+        let mut eureka = serde_json::to_value(EurekaConfig::default())
+            .map_err(|e| EurekaError::ParseError(ParseError::new(e.to_string())))?;
+        let mut instance = serde_json::to_value(Instance::default())
+            .map_err(|e| EurekaError::ParseError(ParseError::new(e.to_string())))?;
+        if let Some(js_eureka) = value.get("eureka") {
+            merge(&mut eureka, js_eureka_config(js_eureka.clone()));
+        }
+        if let Some(js_instance) = value.get("instance") {
+            merge(&mut instance, js_instance_config(js_instance.clone()));
+        }
+        Ok(BaseConfig {
+            eureka: serde_json::from_value(eureka)
+                .map_err(|e| EurekaError::InvalidConfig("eureka", e.to_string()))?,
+            instance: serde_json::from_value(instance)
+                .map_err(|e| EurekaError::InvalidConfig("instance", e.to_string()))?,
+        })
+    }
+}
+
+fn js_eureka_config(mut eureka: Value) -> Value {
+    if let Value::Object(ref mut eureka) = eureka {
+        if let Some(region) = eureka.remove("ec2Region") {
+            eureka.entry("region").or_insert(region);
+        }
+        if let Some(Value::String(ref mut path)) = eureka.get_mut("servicePath") {
+            *path = trim_apps_path(path).to_string();
+        }
+        if let Some(Value::Object(ref mut service_urls)) = eureka.get_mut("serviceUrls") {
+            for url in service_urls
+                .values_mut()
+                .filter_map(Value::as_array_mut)
+                .flatten()
+            {
+                if let Value::String(ref mut url) = *url {
+                    *url = trim_apps_path(url).to_string();
+                }
+            }
+        }
+    }
+    eureka
+}
+
+/// Accepts plain numbers for the ports, enabled as eureka-js-client does
+fn js_instance_config(mut instance: Value) -> Value {
+    if let Value::Object(ref mut instance) = instance {
+        for key in &["port", "securePort"] {
+            if let Some(port) = instance.get_mut(*key) {
+                if port.is_number() {
+                    let mut object = Map::new();
+                    object.insert("$".to_string(), port.take());
+                    object.insert("@enabled".to_string(), Value::Bool(true));
+                    *port = Value::Object(object);
+                }
+            }
+        }
+    }
+    instance
+}
+
+/// `/eureka/v2` for the `/eureka/v2/apps/` paths of eureka-js-client
+fn trim_apps_path(path: &str) -> &str {
+    let path = path.trim_end_matches('/');
+    path.strip_suffix("/apps").unwrap_or(path)
+}
+
+/// Overwrites the fields of `target` set in `source`, merging nested objects
+fn merge(target: &mut Value, source: Value) {
+    match (target, source) {
+        (Value::Object(target), Value::Object(source)) => {
+            for (key, value) in source {
+                match target.get_mut(&key) {
+                    Some(field) => merge(field, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (_, Value::Null) => {}
+        (target, source) => *target = source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::structures::PortData;
+
+    #[test]
+    fn test_from_json_value() {
+        let config = BaseConfig::from_json_value(serde_json::json!({
+            "eureka": {
+                "host": "eureka.local",
+                "port": 8080,
+                "servicePath": "/eureka/v2/apps/",
+                "ec2Region": "eu-west-1",
+                "serviceUrls": { "default": ["http://eureka.local:8080/eureka/v2/apps/"] },
+                "maxRetries": 5,
+                "useDns": false
+            },
+            "instance": {
+                "app": "orders",
+                "hostName": "orders.local",
+                "ipAddr": "10.0.0.1",
+                "port": { "$": 3000, "@enabled": "true" },
+                "securePort": 3443,
+                "vipAddress": "orders",
+                "dataCenterInfo": {
+                    "@class": "com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo",
+                    "name": "MyOwn"
+                }
+            }
+        }))
+        .unwrap();
+        assert_eq!(config.eureka.host, "eureka.local");
+        assert_eq!(config.eureka.port, 8080);
+        assert_eq!(config.eureka.service_path, "/eureka/v2");
+        assert_eq!(config.eureka.region, "eu-west-1");
+        assert_eq!(
+            config.eureka.service_urls["default"],
+            vec!["http://eureka.local:8080/eureka/v2".to_string()]
+        );
+        assert_eq!(config.eureka.max_retries, 5);
+        assert_eq!(config.eureka.heartbeat_interval, 30_000);
+        assert_eq!(config.instance.app, "orders");
+        assert_eq!(config.instance.port, PortData::new(3000, true));
+        assert_eq!(config.instance.secure_port.value, 3443);
+        assert!(config.instance.secure_port.enabled);
+    }
+
+    #[test]
+    fn test_from_json_value_invalid() {
+        assert!(BaseConfig::from_json_value(serde_json::json!({
+            "eureka": { "port": "not a port" }
+        }))
+        .is_err());
+    }
+}
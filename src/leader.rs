@@ -0,0 +1,178 @@
+//! Leader election among the instances of an app, see [`LeaderElection`]
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::EurekaClient;
+
+type Listener = Arc<dyn Fn(bool) + Send + Sync>;
+
+/// Elects the UP instance of the app with the lowest instance id as its leader, e.g. to run
+/// a singleton background job on a single instance
+///
+/// The instances elect the same leader once their registries agree, for a refresh interval
+/// two of them may both lead. Leadership is evaluated again once the registry changed. An
+/// instance which isn't UP in the registry, or doesn't register, doesn't lead.
+///
+/// ```ignore
+/// let election = LeaderElection::new(Arc::clone(&client));
+/// election.on_change(|leader| info!("Leading the cleanup: {}", leader));
+/// election.start();
+/// ```
+#[derive(Clone)]
+pub struct LeaderElection {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    client: Arc<EurekaClient>,
+    leader: AtomicBool,
+    /// Registry generation the leadership was last evaluated at
+    evaluated_at: Mutex<Option<usize>>,
+    listeners: Mutex<Vec<Listener>>,
+}
+
+impl fmt::Debug for LeaderElection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LeaderElection")
+            .field("app", &self.inner.client.config.instance.app)
+            .field("leader", &self.inner.leader.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+impl LeaderElection {
+    /// Election among the instances of the app of `client`
+    pub fn new(client: Arc<EurekaClient>) -> Self {
+        LeaderElection {
+            inner: Arc::new(Inner {
+                client,
+                leader: AtomicBool::new(false),
+                evaluated_at: Mutex::new(None),
+                listeners: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Whether the instance leads its app, evaluated again when the registry changed
+    pub fn is_leader(&self) -> bool {
+        self.inner.evaluate()
+    }
+
+    /// Calls `listener` with the new leadership whenever it changes, from the thread noticing
+    /// the change
+    pub fn on_change<F: Fn(bool) + Send + Sync + 'static>(&self, listener: F) {
+        let mut listeners = self
+            .inner
+            .listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        listeners.push(Arc::new(listener));
+    }
+
+    /// Evaluates the leadership every `registry_fetch_interval` in the background, for the
+    /// listeners to notice the changes without calling [`is_leader`](Self::is_leader)
+    ///
+    /// The thread stops once the election and its clones are dropped.
+    pub fn start(&self) {
+        let inner = Arc::downgrade(&self.inner);
+        let interval = self
+            .inner
+            .client
+            .config
+            .eureka
+            .registry_fetch_interval
+            .max(1);
+        let interval = Duration::from_millis(interval as u64);
+        thread::spawn(move || {
+            while let Some(inner) = inner.upgrade() {
+                inner.evaluate();
+                drop(inner);
+                thread::sleep(interval);
+            }
+        });
+    }
+}
+
+impl Inner {
+    fn evaluate(&self) -> bool {
+        let generation = self.client.registry.generation();
+        let mut evaluated_at = self
+            .evaluated_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if *evaluated_at == Some(generation) {
+            return self.leader.load(Ordering::SeqCst);
+        }
+        *evaluated_at = Some(generation);
+        let leader = self.client.registry.leads_peers();
+        if self.leader.swap(leader, Ordering::SeqCst) == leader {
+            return leader;
+        }
+        // Released for the listeners to call `is_leader`
+        drop(evaluated_at);
+        let app = &self.client.config.instance.app;
+        diag!(
+            self.client.diagnostics,
+            Info,
+            "leader of {}: {}",
+            app,
+            leader
+        );
+        // Called without the lock, for them to add listeners
+        let listeners = self
+            .listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        for listener in listeners.iter() {
+            listener(leader);
+        }
+        leader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{instance, MockEurekaServer};
+    use crate::BaseConfig;
+
+    #[test]
+    fn test_leader_election() {
+        let server = MockEurekaServer::start().unwrap();
+        server.register(instance("JOBS").id("jobs-1").up().build());
+        server.register(instance("JOBS").id("jobs-2").up().build());
+        let mut config = BaseConfig::default();
+        config.eureka = server.config();
+        config.instance.app = "JOBS".to_string();
+        config.instance.instance_id = Some("jobs-2".to_string());
+        let client = Arc::new(EurekaClient::new(config));
+        let election = LeaderElection::new(Arc::clone(&client));
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&changes);
+        election.on_change(move |leader| sink.lock().unwrap().push(leader));
+        // Listeners may add listeners
+        let nested = election.clone();
+        election.on_change(move |_| nested.on_change(|_| {}));
+
+        assert!(!election.is_leader());
+        client.refresh_registry().unwrap();
+        assert!(!election.is_leader());
+        assert_eq!(client.peers().len(), 1);
+
+        server.register(instance("JOBS").id("jobs-1").down().build());
+        client.refresh_registry().unwrap();
+        assert!(election.is_leader());
+        assert!(election.is_leader());
+        assert_eq!(*changes.lock().unwrap(), vec![true]);
+
+        server.register(instance("JOBS").id("jobs-0").up().build());
+        client.refresh_registry().unwrap();
+        assert!(!election.is_leader());
+        assert_eq!(*changes.lock().unwrap(), vec![true, false]);
+    }
+}
@@ -1,33 +1,185 @@
 #![allow(unused, deprecated)]
 
+#[cfg(feature = "actix")]
+extern crate actix_web;
+#[cfg(feature = "reqwest-middleware")]
+extern crate async_trait;
+#[cfg(feature = "axum")]
+extern crate axum;
+#[cfg(any(feature = "reqwest-middleware", feature = "hyper"))]
+extern crate http;
+#[cfg(feature = "hyper")]
+extern crate hyper_util;
 extern crate itertools;
 #[macro_use]
 extern crate log;
 extern crate percent_encoding;
-#[macro_use]
-extern crate quick_error;
 extern crate rand;
 extern crate reqwest;
+#[cfg(feature = "reqwest-middleware")]
+extern crate reqwest_middleware;
+#[cfg(feature = "rocket")]
+extern crate rocket;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[cfg(feature = "tower")]
+extern crate futures_core;
+#[cfg(any(feature = "trace-context", feature = "otel"))]
+extern crate opentelemetry;
 extern crate serde_json;
+extern crate thiserror;
+#[cfg(any(
+    feature = "tower",
+    feature = "tonic",
+    feature = "axum",
+    feature = "warp"
+))]
+extern crate tokio;
+#[cfg(feature = "warp")]
+extern crate tokio_stream;
+#[cfg(feature = "tonic")]
+extern crate tonic;
+#[cfg(feature = "tower")]
+extern crate tower;
+#[cfg(feature = "hyper")]
+extern crate tower_service;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "trace-context")]
+extern crate tracing_opentelemetry;
+#[cfg(feature = "dns")]
+extern crate trust_dns_resolver;
+#[cfg(feature = "warp")]
+extern crate warp;
 
-pub use reqwest::{Error as ReqwestError, Method, Response, StatusCode};
-use reqwest::Client as ReqwestClient;
-use reqwest::header::HeaderMap;
+use rand::random;
+pub use reqwest::header::HeaderMap;
+use reqwest::header::{HeaderValue, CONTENT_TYPE};
+pub use reqwest::{multipart, Body, Error as ReqwestError, Method, RequestBuilder};
+use reqwest::{Client, Request, Url};
+pub use reqwest::{Response, StatusCode};
 pub use serde::de::DeserializeOwned;
 pub use serde::Serialize;
 
-pub use self::instance::{Instance, PortData, SecurePort, StatusType};
+#[cfg(feature = "actix")]
+pub use self::actix_server::{actuator, serve_actix};
+pub use self::address::ResolvedInstance;
+pub use self::app_client::AppClientConfig;
+use self::app_client::AppClients;
+pub use self::aws::{AdvertisePolicy, AwsMetadata, EcsMetadata, EcsTaskInfo, TerminationWatcher};
+#[cfg(feature = "axum")]
+pub use self::axum_server::serve_axum;
+#[cfg(feature = "tower")]
+pub use self::balance::{EurekaBalance, EurekaDiscover};
+pub use self::client_set::{EurekaClientSet, LookupPolicy};
+use self::clock::SharedClock;
+pub use self::clock::{Clock, MockClock, SystemClock};
+#[cfg(feature = "spring-config")]
+pub use self::config_client::{ConfigClient, ConfigClientConfig};
+#[cfg(feature = "hyper")]
+pub use self::connector::EurekaConnector;
+pub use self::datacenter::{
+    detect_provider, AzureMetadata, DataCenter, DataCenterKind, DataCenterProvider, GceMetadata,
+};
+use self::diagnostics::Diagnostics;
+pub use self::diagnostics::{DiagnosticsSink, Level, LogSink};
+pub use self::discovery::ServiceDiscovery;
+pub use self::error::{EurekaError, Operation, ParseError};
+pub use self::events::{ClientEvent, EventKind};
+pub use self::hash_ring::HashRing;
+use self::health::ErrorLog;
+pub use self::health::{ClientError, HealthReport, LatencyPercentiles, LatencyStats};
 use self::instance::InstanceClient;
-use self::registry::RegistryClient;
+pub use self::leader::LeaderElection;
+#[cfg(feature = "reqwest-middleware")]
+pub use self::middleware::EurekaMiddleware;
+#[cfg(feature = "otel")]
+pub use self::otel::EurekaMetrics;
+pub use self::ratelimit::RateLimitStats;
+use self::ratelimit::RateLimiter;
+pub use self::registry::{diff as diff_registry, InstanceChange, ParseReport, RegistryDiff};
+use self::registry::{up_instances, Peers, RegistryClient};
+#[cfg(feature = "dns")]
+pub use self::resolver::DnsResolver;
+pub use self::resolver::{
+    ClusterResolver, ConfigResolver, EndpointHealth, EurekaEndpoint, RefreshingResolver,
+    ShuffledResolver, StaticResolver,
+};
+pub use self::response::CallResponse;
+pub use self::rest::builder::InstanceBuilder;
+pub use self::rest::codec::{Codec, JsonCodec, XmlCodec};
+pub use self::rest::structures::{
+    self, ActionType, AmazonMetaDataType, AppMetaDataType, Application, Applications,
+    DataCenterInfo, DcNameType, Instance, LeaseInfo, PortData, SecurePort, StatusType,
+};
+pub use self::rest::{EurekaApi, EurekaRestClient, InstanceIter, SharedInstanceIter};
+#[cfg(feature = "rocket")]
+pub use self::rocket_fairing::EurekaFairing;
+pub use self::routing::Route;
+pub use self::sidecar::{Sidecar, SidecarConfig};
+#[cfg(feature = "warp")]
+pub use self::warp_server::{serve_warp, with_eureka};
+use std::collections::HashMap;
+use std::net::Ipv6Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
 
+#[cfg(feature = "actix")]
+mod actix_server;
+mod address;
+mod app_client;
 mod aws;
+#[cfg(feature = "axum")]
+mod axum_server;
+#[cfg(feature = "tower")]
+mod balance;
+mod client_set;
+mod clock;
+#[cfg(feature = "spring-config")]
+mod config_client;
+#[cfg(feature = "hyper")]
+mod connector;
+mod datacenter;
+mod discovery;
+mod error;
+#[cfg(feature = "tonic")]
+mod grpc;
+#[macro_use]
+mod diagnostics;
+mod events;
+mod hash_ring;
+mod health;
 mod instance;
+mod js_config;
+mod leader;
+#[cfg(feature = "reqwest-middleware")]
+mod middleware;
+#[cfg(feature = "otel")]
+mod otel;
+mod ratelimit;
 mod registry;
 mod resolver;
+mod response;
 mod rest;
+#[cfg(feature = "rocket")]
+mod rocket_fairing;
+mod routing;
+#[doc(hidden)]
+pub mod service;
+mod sidecar;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+mod trace;
+mod validation;
+#[cfg(feature = "warp")]
+mod warp_server;
+
+/// Min age of the registry cache for an unreachable instance to refresh it
+const REFRESH_ON_DEMAND_AFTER: Duration = Duration::from_secs(5);
 
 /// Eureka client config
 pub struct ClientConfig {
@@ -50,7 +202,7 @@ pub struct EurekaInstanceConfig {}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EurekaConfig {
-    /// Server host, default localhost
+    /// Server host, or the DNS name of the cluster with `use_dns`, default localhost
     pub host: String,
     /// Server port, default 8761
     pub port: u16,
@@ -70,13 +222,69 @@ pub struct EurekaConfig {
     pub service_path: String,
     /// Use ssl
     pub ssl: bool,
+    /// Resolve the eureka servers from the DNS TXT records of `host`, needs the `dns` feature
     pub use_dns: bool,
+    /// Try the eureka servers and the instances of the instance zone first, default true
     pub prefer_same_zone: bool,
+    /// Pick the instances on the host of the instance first, then the ones on its `rack`
+    /// metadata, before the ones of its zone, default false
+    pub prefer_same_host: bool,
+    /// Eureka server list refresh interval in milli-seconds, 0 to disable, default 300,000
     pub cluster_refresh_interval: usize,
+    /// Region of the instance, also looked up in DNS, default us-east-1
+    pub region: String,
+    /// Availability zones by region, the instance is in the first zone of its region
+    /// unless its metadata or data center info tell otherwise
+    pub availability_zones: HashMap<String, Vec<String>>,
+    /// Eureka server urls by zone, tried in `availability_zones` then name order after
+    /// the instance zone. `host` and `port` are used when empty
+    pub service_urls: HashMap<String, Vec<String>>,
+    /// Eureka server urls by zone for the registry queries, e.g. read-only replicas.
+    /// Registry queries go to `service_urls` when empty
+    pub read_service_urls: HashMap<String, Vec<String>>,
+    /// Consecutive failures after which a eureka server is quarantined, default 3
+    pub quarantine_threshold: usize,
+    /// Time a failing eureka server is skipped in milli-seconds, default 60,000
+    pub quarantine_duration: usize,
     pub fetch_metadata: bool,
     pub register_with_eureka: bool,
     pub use_local_metadata: bool,
     pub prefer_ip_address: bool,
+    /// Address family of the instances to call when an app has both, default dual
+    pub ip_preference: IpPreference,
+    /// Send the B3 single header along with the W3C trace context in `make_request`,
+    /// needs the `trace-context` feature, default false
+    pub b3_propagation: bool,
+    /// Metadata service the data center info and zone are looked up from, default MyOwn
+    pub data_center: DataCenterKind,
+    /// EC2 address Amazon instances register with, default AdvertisePrivateIp
+    pub advertise_policy: AdvertisePolicy,
+    /// Drain the instance on an EC2 spot interruption or auto scaling termination, default false
+    pub watch_termination: bool,
+    /// Time between marking a terminating instance OUT_OF_SERVICE and deregistering it,
+    /// in milli-seconds, default 30,000
+    pub termination_drain_delay: usize,
+    /// Skip malformed instances instead of failing the whole registry fetch, default false
+    pub lenient_parsing: bool,
+    /// Settings of the requests to other apps by app name, matched case-insensitively
+    pub apps: HashMap<String, AppClientConfig>,
+    /// Domain the apps are called under when the registry cache is empty or stale, e.g.
+    /// `service.internal` to call `orders.service.internal` during a eureka outage. Empty,
+    /// the default, to fail the requests instead
+    pub dns_fallback_suffix: String,
+    /// Port the apps are called on under `dns_fallback_suffix`, default 80
+    pub dns_fallback_port: u16,
+    /// Age of the registry cache in milli-seconds past which it is stale for the DNS
+    /// fallback, default 90,000
+    pub dns_fallback_after: usize,
+    /// Number of errors kept for `recent_errors`, default 32
+    pub recent_errors_capacity: usize,
+    /// Max number of eureka requests sent in a burst, default 10
+    pub rate_limit_burst: usize,
+    /// Eureka requests allowed per second once the burst is used, default 2
+    pub rate_limit_refill_rate: usize,
+    /// Max number of instances `broadcast` calls at once, default 8
+    pub broadcast_concurrency: usize,
 }
 
 impl Default for EurekaConfig {
@@ -94,35 +302,113 @@ impl Default for EurekaConfig {
             ssl: false,
             use_dns: false,
             prefer_same_zone: true,
+            prefer_same_host: false,
             cluster_refresh_interval: 300_000,
+            region: "us-east-1".to_string(),
+            availability_zones: HashMap::new(),
+            service_urls: HashMap::new(),
+            read_service_urls: HashMap::new(),
+            quarantine_threshold: 3,
+            quarantine_duration: 60_000,
             fetch_metadata: true,
             register_with_eureka: true,
             use_local_metadata: false,
             prefer_ip_address: false,
+            ip_preference: IpPreference::Dual,
+            b3_propagation: false,
+            data_center: DataCenterKind::MyOwn,
+            advertise_policy: AdvertisePolicy::AdvertisePrivateIp,
+            watch_termination: false,
+            termination_drain_delay: 30_000,
+            lenient_parsing: false,
+            apps: HashMap::new(),
+            dns_fallback_suffix: String::new(),
+            dns_fallback_port: 80,
+            dns_fallback_after: 90_000,
+            recent_errors_capacity: 32,
+            rate_limit_burst: 10,
+            rate_limit_refill_rate: 2,
+            broadcast_concurrency: 8,
         }
     }
 }
 
+/// Address family preferred when picking an instance by its `ipAddr`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IpPreference {
+    /// IPv4 instances first, IPv6 ones when there is no IPv4 one
+    PreferV4,
+    /// IPv6 instances first, IPv4 ones when there is no IPv6 one
+    PreferV6,
+    /// Any instance
+    Dual,
+}
+
+impl Default for IpPreference {
+    fn default() -> Self {
+        IpPreference::Dual
+    }
+}
+
+impl EurekaConfig {
+    /// Checks that `host` and the configured service urls make valid eureka urls
+    pub fn validate(&self) -> Result<(), EurekaError> {
+        if self.host.is_empty() {
+            return Err(EurekaError::InvalidConfig(
+                "host",
+                "must not be empty".to_string(),
+            ));
+        }
+        EurekaEndpoint::parse(&eureka_base_url(self))?;
+        for url in self
+            .service_urls
+            .values()
+            .chain(self.read_service_urls.values())
+            .flatten()
+        {
+            EurekaEndpoint::parse(url)?;
+        }
+        Ok(())
+    }
+
+    /// Availability zones of `region`
+    pub fn zones(&self) -> &[String] {
+        self.availability_zones
+            .get(&self.region)
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Settings of the requests to `app`, matched case-insensitively
+    pub fn app_config(&self, app: &str) -> Option<&AppClientConfig> {
+        self.apps
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(app))
+            .map(|(_, config)| config)
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct BaseConfig {
     pub eureka: EurekaConfig,
     pub instance: Instance,
 }
 
-quick_error! {
-    #[derive(Debug)]
-    pub enum EurekaError {
-        Network(err: ReqwestError) {
-            description(err.description())
-            cause(err)
-        }
-        Request(status: StatusCode) {
-            description(status.canonical_reason().unwrap_or("Unknown Status Code"))
-        }
-        UnexpectedState(description: String) {
-            description(description)
-        }
-        ParseError(description: String) {}
+impl BaseConfig {
+    /// Zone of the instance, from its `zone` metadata, its AWS availability zone
+    /// or the first availability zone of the region
+    pub fn local_zone(&self) -> Option<&str> {
+        let aws_zone = self
+            .instance
+            .data_center_info
+            .metadata
+            .as_ref()
+            .map(|metadata| metadata.availability_zone.as_str())
+            .filter(|zone| !zone.is_empty());
+        self.instance
+            .zone()
+            .or(aws_zone)
+            .or_else(|| self.eureka.zones().first().map(String::as_str))
     }
 }
 
@@ -130,65 +416,503 @@ quick_error! {
 pub struct EurekaClient {
     base_url: String,
     config: BaseConfig,
-    client: ReqwestClient,
+    clients: AppClients,
     registry: RegistryClient,
-    instance: Option<InstanceClient>,
+    /// Hash rings by upper case app name and virtual nodes, with the registry generation
+    /// they were built at
+    rings: Mutex<HashMap<(String, usize), (usize, Arc<HashRing>)>>,
+    instance: Option<Arc<InstanceClient>>,
+    termination_watcher: Option<TerminationWatcher>,
+    /// Limiter of the built-in REST client, none with [`with_api`](Self::with_api)
+    limiter: Option<Arc<RateLimiter>>,
+    endpoint_health: Arc<EndpointHealth>,
+    errors: Arc<ErrorLog>,
+    diagnostics: Diagnostics,
+    clock: SharedClock,
 }
 
 impl EurekaClient {
-    pub fn new(config: BaseConfig) -> Self {
-        let base_url = {
-            let ssl = config.eureka.ssl;
-            let protocol = if ssl { "https" } else { "http" };
-            let host = &config.eureka.host;
-            let port = config.eureka.port;
-            let service_path = &config.eureka.service_path;
-            format!("{}://{}:{}{}", protocol, host, port, service_path)
-        };
+    /// Creates a client, logging the config errors, see [`try_new`](Self::try_new)
+    pub fn new(mut config: BaseConfig) -> Self {
+        apply_data_center(&mut config);
+        if let Err(e) = config.validate() {
+            error!("Invalid eureka config: {}", e);
+        }
+        let clock = SharedClock::default();
+        let limiter = Arc::new(
+            RateLimiter::new(
+                config.eureka.rate_limit_burst,
+                config.eureka.rate_limit_refill_rate,
+            )
+            .with_clock(clock.clone()),
+        );
+        let endpoint_health = Arc::new(EndpointHealth::new(
+            config.eureka.quarantine_threshold,
+            Duration::from_millis(config.eureka.quarantine_duration as u64),
+        ));
+        // One connection pool for the eureka requests and the requests to the apps
+        let http = Client::new();
+        let mut api = EurekaRestClient::with_resolver(
+            cluster_resolver(&config, config_resolver(&config)),
+            Arc::clone(&limiter),
+        )
+        .with_endpoint_health(Arc::clone(&endpoint_health))
+        .with_http_client(http.clone());
+        if !config.eureka.read_service_urls.is_empty() {
+            let read_resolver = Arc::new(ConfigResolver::with_service_urls(
+                &config.eureka,
+                &config.eureka.read_service_urls,
+                config.local_zone(),
+            ));
+            api = api.with_read_resolver(cluster_resolver(&config, read_resolver));
+        }
+        EurekaClient::with_parts(
+            config,
+            Arc::new(api),
+            http,
+            Some(limiter),
+            endpoint_health,
+            clock,
+        )
+    }
+
+    /// Creates a client, failing on an invalid config before any request is sent
+    pub fn try_new(config: BaseConfig) -> Result<Self, EurekaError> {
+        config.validate()?;
+        Ok(EurekaClient::new(config))
+    }
+
+    /// Creates a client sending its eureka requests through `api`
+    ///
+    /// The rate limit and quarantine settings only apply to the built-in REST client,
+    /// [`rate_limit_stats`](Self::rate_limit_stats) stay at zero.
+    pub fn with_api(mut config: BaseConfig, api: Arc<dyn EurekaApi>) -> Self {
+        apply_data_center(&mut config);
+        EurekaClient::with_parts(
+            config,
+            api,
+            Client::new(),
+            None,
+            Arc::new(EndpointHealth::default()),
+            SharedClock::default(),
+        )
+    }
+
+    fn with_parts(
+        config: BaseConfig,
+        api: Arc<dyn EurekaApi>,
+        http: Client,
+        limiter: Option<Arc<RateLimiter>>,
+        endpoint_health: Arc<EndpointHealth>,
+        clock: SharedClock,
+    ) -> Self {
         let mut instance = config.instance.clone();
+        config.eureka.advertise_policy.apply(&mut instance);
+        if instance.zone().is_none() {
+            if let Some(zone) = config.local_zone() {
+                instance.set_metadata_value(AppMetaDataType::ZONE, zone);
+            }
+        }
         instance.vip_address = instance.app.clone();
         instance.secure_vip_address = instance.vip_address.clone();
+        let errors = Arc::new(ErrorLog::new(config.eureka.recent_errors_capacity));
+        let diagnostics = Diagnostics::default();
+        let events = endpoint_health.events();
         EurekaClient {
-            base_url: base_url.clone(),
-            client: ReqwestClient::new(),
-            registry: RegistryClient::new(base_url.clone()),
+            base_url: eureka_base_url(&config.eureka),
+            clients: AppClients::new(&config.eureka.apps, http),
+            registry: RegistryClient::new(Arc::clone(&api), config.eureka.lenient_parsing)
+                .with_ip_preference(config.eureka.ip_preference)
+                .with_local_zone(if config.eureka.prefer_same_zone {
+                    config.local_zone().map(String::from)
+                } else {
+                    None
+                })
+                .with_local_instance(if config.eureka.prefer_same_host {
+                    Some(instance.clone())
+                } else {
+                    None
+                })
+                .with_peers(if config.eureka.register_with_eureka {
+                    let id = instance
+                        .instance_id
+                        .as_deref()
+                        .unwrap_or(&instance.host_name);
+                    Some(Peers::new(&instance.app, id))
+                } else {
+                    None
+                })
+                .with_stale_after(if config.eureka.fetch_registry {
+                    // A few refreshes missed
+                    let interval = config.eureka.registry_fetch_interval as u64;
+                    Some(Duration::from_millis(interval * 3))
+                } else {
+                    None
+                })
+                .with_error_log(Arc::clone(&errors))
+                .with_diagnostics(diagnostics.clone())
+                .with_events(events.clone())
+                .with_clock(clock.clone())
+                .with_fetch_interval(Duration::from_millis(
+                    config.eureka.registry_fetch_interval.max(1) as u64,
+                )),
+            rings: Mutex::new(HashMap::new()),
+            termination_watcher: if config.eureka.register_with_eureka
+                && config.eureka.watch_termination
+            {
+                Some(TerminationWatcher::new(
+                    AwsMetadata::default().with_timeout(Duration::from_secs(2)),
+                ))
+            } else {
+                None
+            },
             instance: if config.eureka.register_with_eureka {
-                Some(InstanceClient::new(base_url, instance))
+                Some(Arc::new(
+                    InstanceClient::new(api, instance)
+                        .with_error_log(Arc::clone(&errors))
+                        .with_diagnostics(diagnostics.clone())
+                        .with_events(events)
+                        .with_clock(clock.clone())
+                        .with_heartbeat_interval(Duration::from_millis(
+                            config.eureka.heartbeat_interval.max(1) as u64,
+                        )),
+                ))
             } else {
                 None
             },
             config,
+            limiter,
+            endpoint_health,
+            errors,
+            diagnostics,
+            clock,
+        }
+    }
+
+    /// Send the internal events of the client to `sink` instead of the `log` crate
+    pub fn with_diagnostics_sink(self, sink: Arc<dyn DiagnosticsSink>) -> Self {
+        self.diagnostics.set_sink(sink);
+        self
+    }
+
+    /// Time the heartbeats, registry refreshes, retries and rate limit with `clock`, e.g. a
+    /// [`MockClock`] to test them without waiting
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        self.clock.set_clock(clock);
+        self
+    }
+
+    /// Send the registrations, deregistrations, status changes, eureka server quarantines,
+    /// registry refreshes and changes of the peers to `sender`, e.g. to persist them as an
+    /// audit trail
+    pub fn with_event_sender(self, sender: Sender<ClientEvent>) -> Self {
+        self.endpoint_health.events().set_sender(sender);
+        self
+    }
+
+    /// Fetches the registry now instead of waiting for the next refresh
+    pub fn refresh_registry(&self) -> Result<(), EurekaError> {
+        self.registry
+            .update_app_cache()
+            .map_err(EurekaError::UnexpectedState)
+    }
+
+    /// Report of the last registry parse, listing the skipped malformed instances
+    pub fn last_parse_report(&self) -> ParseReport {
+        self.registry.last_parse_report()
+    }
+
+    /// Copy of the cached registry, compare two of them with [`diff_registry`]
+    pub fn dump_registry(&self) -> Applications {
+        self.registry.dump()
+    }
+
+    /// Counters of eureka requests allowed and dropped by the rate limiter, zero for a client
+    /// created [`with_api`](Self::with_api), whose requests aren't rate limited
+    pub fn rate_limit_stats(&self) -> RateLimitStats {
+        self.limiter
+            .as_ref()
+            .map(|limiter| limiter.stats())
+            .unwrap_or_default()
+    }
+
+    /// Round-trip time percentiles of the last heartbeats and registry fetches
+    pub fn latency_stats(&self) -> LatencyStats {
+        LatencyStats {
+            heartbeat: self
+                .instance
+                .as_ref()
+                .map(|instance| instance.heartbeat_latency())
+                .unwrap_or_default(),
+            registry_fetch: self.registry.fetch_latency(),
+        }
+    }
+
+    /// Service urls of the eureka servers quarantined after repeated failures
+    pub fn quarantined_endpoints(&self) -> Vec<String> {
+        self.endpoint_health.quarantined()
+    }
+
+    /// Last errors of the registration, heartbeats, registry fetches and requests, oldest first
+    pub fn recent_errors(&self) -> Vec<ClientError> {
+        self.errors.errors()
+    }
+
+    /// Registration, heartbeat and registry state, e.g. for a `/health` endpoint
+    pub fn health(&self) -> HealthReport {
+        let mut report = HealthReport {
+            quarantined_endpoints: self.endpoint_health.quarantined(),
+            ..HealthReport::default()
+        };
+        self.registry.report_health(&mut report);
+        if let Some(ref instance) = self.instance {
+            instance.report_health(&mut report);
         }
+        report
+    }
+
+    /// Body of a Spring Boot actuator `/info` endpoint, naming the app and instance
+    pub fn actuator_info(&self) -> serde_json::Value {
+        let instance = &self.config.instance;
+        serde_json::json!({
+            "app": {
+                "name": instance.app,
+                "instanceId": instance.instance_id.as_ref().unwrap_or(&instance.host_name),
+                "version": instance.version(),
+            },
+        })
     }
 
     pub fn start(&self) {
         self.registry.start();
         if let Some(ref instance) = self.instance {
             instance.start();
+            self.watch_termination(instance);
         }
     }
 
-    pub fn find_app_address(&self, app_id: &str) -> Option<String> {
-        let instance = self.registry.get_instance_by_app_name(app_id);
-        if let Some(instance) = instance {
-            let ssl = self.config.eureka.ssl;
-            let host = instance.ip_addr;
-            let port = if ssl {
-                instance.secure_port.value
-            } else {
-                instance.port.value
-            };
-            println!("app {} addr {}:{}", app_id, host, port);
-            Some(format!("{}:{}", host, port))
-        } else {
+    /// Starts the client, failing instead of retrying forever when the instance can't be
+    /// registered, e.g. on a 400 of an invalid registration or when eureka stays
+    /// unreachable for `deadline`
+    pub fn try_start(&self, deadline: Duration) -> Result<(), EurekaError> {
+        if let Some(ref instance) = self.instance {
+            instance.try_start(deadline)?;
+        }
+        self.registry.start();
+        if let Some(ref instance) = self.instance {
+            self.watch_termination(instance);
+        }
+        Ok(())
+    }
+
+    fn watch_termination(&self, instance: &Arc<InstanceClient>) {
+        if let Some(ref watcher) = self.termination_watcher {
+            let instance = Arc::downgrade(instance);
+            let delay = Duration::from_millis(self.config.eureka.termination_drain_delay as u64);
+            watcher.start(move |_| {
+                if let Some(instance) = instance.upgrade() {
+                    instance.drain(delay);
+                }
+            });
+        }
+    }
+
+    /// Sets the status of the instance on the eureka servers, e.g. DOWN while a dependency
+    /// is unavailable
+    pub fn set_status(&self, status: StatusType) -> Result<(), EurekaError> {
+        match self.instance {
+            Some(ref instance) => instance.set_status(status),
+            None => Err(EurekaError::UnexpectedState(
+                "The instance is not registered with eureka".to_string(),
+            )),
+        }
+    }
+
+    /// Stops the heartbeats and deregisters the instance, e.g. on a graceful shutdown
+    pub fn deregister(&self) {
+        if let Some(ref instance) = self.instance {
+            instance.stop();
+        }
+    }
+
+    /// Routing rules of `app`, e.g. `client.route("ORDERS").weight("version=2.0", 10)` for a
+    /// canary taking 10 % of the requests
+    pub fn route(&self, app: &str) -> Route<'_> {
+        Route::new(self.registry.routes(), app)
+    }
+
+    /// Restricts the requests to `app` to its instances whose `key` metadata is `value`, e.g.
+    /// `group=green` to switch the traffic from the blue instances to the green ones
+    ///
+    /// The requests fail with [`EurekaError::NoUpInstance`] while no UP instance carries the
+    /// label. The label replaces the one selected before, if any.
+    pub fn label_selector(&self, app: &str, key: &str, value: &str) {
+        diag!(
+            self.diagnostics,
+            Info,
+            "selecting the instances of {} with {}={}",
+            app,
+            key,
+            value
+        );
+        self.registry.routes().set_label(app, key, value);
+    }
+
+    /// Removes the label selector of `app`, sending its requests to any instance
+    pub fn clear_label_selector(&self, app: &str) {
+        self.registry.routes().clear_label(app);
+    }
+
+    /// UP instance of `app` picked as [`make_request`](Self::make_request) does, on the port
+    /// it enables, the secure one when it enables both and the client uses ssl
+    pub fn resolve(&self, app: &str) -> Option<ResolvedInstance> {
+        if let Some(endpoint) = self.pick_static_endpoint(app) {
+            return Some(endpoint);
+        }
+        let resolved = match self.registry.get_instance_by_app_name(app) {
+            Some(instance) => self.resolve_instance(app, &instance),
+            None => self.dns_fallback(app)?,
+        };
+        diag!(
+            self.diagnostics,
+            Debug,
+            "app {} addr {}",
+            app,
+            resolved.address()
+        );
+        Some(resolved)
+    }
+
+    /// The UP instances of `app`, matched case-insensitively, e.g. to fan requests out
+    pub fn resolve_all(&self, app: &str) -> Vec<ResolvedInstance> {
+        if let Some(endpoints) = self.static_endpoints(app) {
+            return endpoints;
+        }
+        let routes = self.registry.routes();
+        up_instances(&self.registry.app_cache(), app)
+            .iter()
+            .filter(|instance| routes.has_label(app, instance))
+            .map(|instance| self.resolve_instance(app, instance))
+            .collect()
+    }
+
+    /// The other UP instances of the app of the instance, e.g. to gossip or elect a leader
+    /// among them, none when it doesn't register with eureka
+    ///
+    /// Each registry refresh changing them sends a `PeersChanged` event, see
+    /// [`with_event_sender`](Self::with_event_sender).
+    pub fn peers(&self) -> Vec<ResolvedInstance> {
+        let app = &self.config.instance.app;
+        self.registry
+            .peers()
+            .iter()
+            .map(|instance| self.resolve_instance(app, instance))
+            .collect()
+    }
+
+    /// Consistent hash ring of the UP instances of `app`, placing each one `virtual_nodes`
+    /// times, e.g. [`HashRing::DEFAULT_VIRTUAL_NODES`], to map keys to the instances owning
+    /// them
+    ///
+    /// The ring is built again once the registry changed, call it again rather than keeping
+    /// the ring.
+    pub fn hash_ring(&self, app: &str, virtual_nodes: usize) -> Arc<HashRing> {
+        let generation = self.registry.generation();
+        let key = (app.to_uppercase(), virtual_nodes);
+        let mut rings = self.rings.lock().unwrap_or_else(PoisonError::into_inner);
+        match rings.get(&key) {
+            Some((built_at, ring)) if *built_at == generation => Arc::clone(ring),
+            _ => {
+                let ring = Arc::new(HashRing::new(self.resolve_all(app), virtual_nodes));
+                diag!(
+                    self.diagnostics,
+                    Debug,
+                    "hash ring of {} has {} instances",
+                    app,
+                    ring.len()
+                );
+                rings.insert(key, (generation, Arc::clone(&ring)));
+                ring
+            }
+        }
+    }
+
+    /// The `endpoints` setting of `app` overriding its instances in the registry, `None`
+    /// when it has none
+    fn static_endpoints(&self, app: &str) -> Option<Vec<ResolvedInstance>> {
+        let config = self.config.eureka.app_config(app)?;
+        let secure = config.secure.unwrap_or(false);
+        let endpoints: Vec<ResolvedInstance> = config
+            .parse_endpoints(app)
+            .ok()?
+            .iter()
+            .map(|(host, port)| ResolvedInstance::on_host(app, host, *port, secure))
+            .collect();
+        if endpoints.is_empty() {
             None
+        } else {
+            Some(endpoints)
+        }
+    }
+
+    /// Random endpoint of the `endpoints` setting of `app`
+    fn pick_static_endpoint(&self, app: &str) -> Option<ResolvedInstance> {
+        let mut endpoints = self.static_endpoints(app)?;
+        Some(endpoints.swap_remove(random::<usize>() % endpoints.len()))
+    }
+
+    /// `app` under `dns_fallback_suffix` when the registry cache is empty or stale, e.g.
+    /// during an outage of all the eureka servers
+    fn dns_fallback(&self, app: &str) -> Option<ResolvedInstance> {
+        let config = &self.config.eureka;
+        let suffix = config.dns_fallback_suffix.trim_matches('.');
+        let max_age = Duration::from_millis(config.dns_fallback_after as u64);
+        if suffix.is_empty() || !self.registry.is_stale(max_age) {
+            return None;
         }
+        let host = format!("{}.{}", app.to_lowercase(), suffix);
+        diag!(
+            self.diagnostics,
+            Warn,
+            "registry unavailable, calling {} on {}",
+            app,
+            host
+        );
+        let secure = config.app_config(app).and_then(|config| config.secure);
+        Some(ResolvedInstance::on_host(
+            app,
+            &host,
+            config.dns_fallback_port,
+            secure.unwrap_or(false),
+        ))
+    }
+
+    /// Address of `instance` of `app`, on the port `AppClientConfig::secure` forces if any
+    fn resolve_instance(&self, app: &str, instance: &Instance) -> ResolvedInstance {
+        let secure = self
+            .config
+            .eureka
+            .app_config(app)
+            .and_then(|config| config.secure);
+        match secure {
+            Some(secure) => ResolvedInstance::on_port(instance, secure),
+            None => ResolvedInstance::new(instance, self.config.eureka.ssl),
+        }
+    }
+
+    /// `ip:port` of an UP instance of `app_id`
+    #[deprecated(
+        since = "0.3.0",
+        note = "use `resolve`, which tells the port and scheme"
+    )]
+    pub fn find_app_address(&self, app_id: &str) -> Option<String> {
+        self.resolve(app_id).map(|instance| instance.address())
     }
 
     /// Sends a request to another app in this eureka cluster, and returns the response.
     ///
-    /// This method assumes that your services all communicate using JSON.
-    /// Future methods may be added to allow other request body types.
+    /// The body is sent as JSON, see [`make_request_raw`](Self::make_request_raw) and
+    /// [`make_request_multipart`](Self::make_request_multipart) for other body types.
     ///
     /// You can add additional headers such as `Authorization` using the `headers` parameter.
     pub fn make_request<V: Serialize>(
@@ -199,42 +923,275 @@ impl EurekaClient {
         body: &V,
         mut headers: HeaderMap,
     ) -> Result<Response, EurekaError> {
-        log::debug!("finding app {}", app);
-        let instance = self.registry.get_instance_by_app_name(app);
-        if let Some(instance) = instance {
-            //println!("app {} instance {:?}", app, instance);
-            let ssl = self.config.eureka.ssl;
-            let protocol = if ssl { "https" } else { "http" };
-            let host = instance.ip_addr;
-            let port = if ssl {
-                instance.secure_port.value
-            } else {
-                instance.port.value
+        self.make_request_with(app, path, method, headers, |request| request.json(body))
+    }
+
+    /// Sends a request with a body of `content_type`, e.g. bytes, text, a url encoded form,
+    /// a file or a stream wrapped in a [`Body`]
+    pub fn make_request_raw<B: Into<Body>>(
+        &self,
+        app: &str,
+        path: &str,
+        method: Method,
+        body: B,
+        content_type: &str,
+        mut headers: HeaderMap,
+    ) -> Result<Response, EurekaError> {
+        let value = HeaderValue::from_str(content_type).map_err(|e| {
+            EurekaError::UnexpectedState(format!("Invalid content type {}: {}", content_type, e))
+        })?;
+        headers.insert(CONTENT_TYPE, value);
+        self.make_request_with(app, path, method, headers, |request| request.body(body))
+    }
+
+    /// Sends a `multipart/form-data` request
+    pub fn make_request_multipart(
+        &self,
+        app: &str,
+        path: &str,
+        method: Method,
+        form: multipart::Form,
+        mut headers: HeaderMap,
+    ) -> Result<Response, EurekaError> {
+        self.make_request_with(app, path, method, headers, |request| {
+            request.multipart(form)
+        })
+    }
+
+    /// Sends a request to an UP instance whose `vipAddress` lists `vip`, on its port, e.g. to
+    /// reach one partition of apps sharing a name
+    pub fn make_request_vip<V: Serialize>(
+        &self,
+        vip: &str,
+        path: &str,
+        method: Method,
+        body: &V,
+        headers: HeaderMap,
+    ) -> Result<Response, EurekaError> {
+        self.make_request_by_vip(vip, false, path, method, headers, |request| {
+            request.json(body)
+        })
+    }
+
+    /// Sends a request to an UP instance whose `secureVipAddress` lists `vip`, over https on
+    /// its secure port
+    pub fn make_request_secure_vip<V: Serialize>(
+        &self,
+        vip: &str,
+        path: &str,
+        method: Method,
+        body: &V,
+        headers: HeaderMap,
+    ) -> Result<Response, EurekaError> {
+        self.make_request_by_vip(vip, true, path, method, headers, |request| {
+            request.json(body)
+        })
+    }
+
+    fn make_request_by_vip<F>(
+        &self,
+        vip: &str,
+        secure: bool,
+        path: &str,
+        method: Method,
+        headers: HeaderMap,
+        build: F,
+    ) -> Result<Response, EurekaError>
+    where
+        F: FnOnce(RequestBuilder) -> RequestBuilder,
+    {
+        let result = trace::instrument("make_request", vip, "", || {
+            diag!(self.diagnostics, Debug, "finding vip {}", vip);
+            let pick = || {
+                let instance = self.registry.find_instance_by_vip(vip, secure)?;
+                Ok(ResolvedInstance::on_port(&instance, secure))
             };
-            log::debug!("app {} addr {}:{}", app, host, port);
-            self.client
-                .request(
-                    method,
-                    &format!(
-                        "{}://{}:{}/{}",
-                        protocol,
-                        host,
-                        port,
-                        path.trim_left_matches('/')
-                    ),
-                )
-                .headers(headers)
-                .json(body)
-                .send()
-                .map_err(EurekaError::Network)
-        } else {
-            Err(EurekaError::UnexpectedState(format!(
-                "Could not find app {}",
-                app
-            )))
+            self.send_to_pick(pick, path, method, headers, build)
+        });
+        if let Err(ref e) = result {
+            self.errors.push("make_request", e);
+        }
+        result
+    }
+
+    /// Sends a request built by `build`, e.g. to add a body, query parameters or a bearer
+    /// token: `|request| request.query(&[("page", 2)]).bearer_auth(token)`
+    pub fn make_request_with<F>(
+        &self,
+        app: &str,
+        path: &str,
+        method: Method,
+        headers: HeaderMap,
+        build: F,
+    ) -> Result<Response, EurekaError>
+    where
+        F: FnOnce(RequestBuilder) -> RequestBuilder,
+    {
+        let result = trace::instrument("make_request", app, "", || {
+            self.send_request(app, path, method, headers, build)
+        });
+        if let Err(ref e) = result {
+            self.errors.push("make_request", e);
+        }
+        result
+    }
+
+    fn send_request<F>(
+        &self,
+        app: &str,
+        path: &str,
+        method: Method,
+        headers: HeaderMap,
+        build: F,
+    ) -> Result<Response, EurekaError>
+    where
+        F: FnOnce(RequestBuilder) -> RequestBuilder,
+    {
+        diag!(self.diagnostics, Debug, "finding app {}", app);
+        if let Some(endpoint) = self.pick_static_endpoint(app) {
+            return self.send_to(app, &endpoint, path, method, headers, build);
+        }
+        let pick = || match self.registry.find_instance(app) {
+            Ok(instance) => Ok(self.resolve_instance(app, &instance)),
+            Err(e) => self.dns_fallback(app).ok_or(e),
+        };
+        self.send_to_pick(pick, path, method, headers, build)
+    }
+
+    /// Sends the request to the instance `pick` returns, and once more to a fresh pick when
+    /// that instance is unreachable, e.g. gone since the last registry refresh
+    ///
+    /// The unreachable instance is evicted from the cache before retrying on another cached
+    /// one. Only when there is none is the registry refreshed, at most once at a time and
+    /// every `REFRESH_ON_DEMAND_AFTER`. Requests whose body can't be sent twice, e.g. a
+    /// stream, aren't retried.
+    fn send_to_pick<P, F>(
+        &self,
+        pick: P,
+        path: &str,
+        method: Method,
+        headers: HeaderMap,
+        build: F,
+    ) -> Result<Response, EurekaError>
+    where
+        P: Fn() -> Result<ResolvedInstance, EurekaError>,
+        F: FnOnce(RequestBuilder) -> RequestBuilder,
+    {
+        let instance = pick()?;
+        let app = &instance.app;
+        let request = self.build_request(app, &instance, path, method, headers, build)?;
+        let retry = request.try_clone();
+        let error = match self.execute(app, &instance, request) {
+            Err(e) if e.is_unreachable() => e,
+            result => return result,
+        };
+        let mut request = match retry {
+            Some(request) => request,
+            None => return Err(error),
+        };
+
+        diag!(
+            self.diagnostics,
+            Warn,
+            "instance {} of {} is unreachable, retrying on another one: {}",
+            instance.instance_id,
+            app,
+            error
+        );
+        self.registry.evict_instance(app, &instance.instance_id);
+        let instance = match pick() {
+            Ok(instance) => instance,
+            Err(_) => match self.registry.refresh_on_demand(REFRESH_ON_DEMAND_AFTER) {
+                Ok(true) => {
+                    // Still in the registry until its lease expires
+                    self.registry.evict_instance(app, &instance.instance_id);
+                    pick().map_err(|_| error)?
+                }
+                Ok(false) => return Err(error),
+                Err(e) => {
+                    diag!(self.diagnostics, Warn, "{}", e);
+                    return Err(error);
+                }
+            },
+        };
+        let url = self.request_url(&instance.app, &instance, path);
+        *request.url_mut() = Url::parse(&url).map_err(|e| {
+            EurekaError::UnexpectedState(format!("Invalid url {}: {}", url, e))
+                .context(Operation::Call, &url)
+        })?;
+        self.execute(&instance.app, &instance, request)
+    }
+
+    /// Sends a request to `instance` of `app`
+    fn send_to<F>(
+        &self,
+        app: &str,
+        instance: &ResolvedInstance,
+        path: &str,
+        method: Method,
+        headers: HeaderMap,
+        build: F,
+    ) -> Result<Response, EurekaError>
+    where
+        F: FnOnce(RequestBuilder) -> RequestBuilder,
+    {
+        let request = self.build_request(app, instance, path, method, headers, build)?;
+        self.execute(app, instance, request)
+    }
+
+    /// Request to `instance` of `app` built by `build`, with the trace context headers
+    fn build_request<F>(
+        &self,
+        app: &str,
+        instance: &ResolvedInstance,
+        path: &str,
+        method: Method,
+        mut headers: HeaderMap,
+        build: F,
+    ) -> Result<Request, EurekaError>
+    where
+        F: FnOnce(RequestBuilder) -> RequestBuilder,
+    {
+        let url = self.request_url(app, instance, path);
+        trace::inject_trace_context(&mut headers, self.config.eureka.b3_propagation);
+        self.clients.add_default_headers(app, &mut headers);
+        build(self.clients.get(app).request(method, &url).headers(headers))
+            .build()
+            .map_err(|e| EurekaError::network(e).context(Operation::Call, &url))
+    }
+
+    /// Url of `path` on `instance`, under the base path of `app`
+    fn request_url(&self, app: &str, instance: &ResolvedInstance, path: &str) -> String {
+        match self.config.eureka.app_config(app) {
+            Some(config) => instance.url(&config.path(path)),
+            None => instance.url(path),
         }
     }
 
+    fn execute(
+        &self,
+        app: &str,
+        instance: &ResolvedInstance,
+        request: Request,
+    ) -> Result<Response, EurekaError> {
+        let address = instance.address();
+        diag!(self.diagnostics, Debug, "app {} addr {}", app, address);
+        trace::record_endpoint(&address);
+        let url = request.url().to_string();
+        self.clients
+            .get(app)
+            .execute(request)
+            .map(|resp| {
+                trace::record_status(resp.status());
+                resp
+            })
+            .map_err(|e| EurekaError::network(e).context(Operation::Call, &url))
+    }
+
+    /// Calls another app and reads the JSON body of its 2xx response, e.g. `()` or `Option`
+    /// for a 204
+    ///
+    /// Error responses fail with their status and body, see [`EurekaError::status`].
     pub fn call<V: Serialize, R: DeserializeOwned>(
         &self,
         app: &str,
@@ -243,14 +1200,207 @@ impl EurekaClient {
         body: &V,
         mut headers: HeaderMap,
     ) -> Result<R, EurekaError> {
-        let mut resp = self.make_request(app, path, method, body, headers)?;
-        match resp.status() {
-            StatusCode::OK => Ok(resp.json().map_err(EurekaError::Network)?),
-            s => Err(EurekaError::Request(resp.status())),
+        let resp = self.make_request(app, path, method, body, headers)?;
+        response::read_success(resp).map(|resp| resp.body)
+    }
+
+    /// Calls another app with a request built by `build`, see
+    /// [`make_request_with`](Self::make_request_with)
+    pub fn call_with<R, F>(
+        &self,
+        app: &str,
+        path: &str,
+        method: Method,
+        headers: HeaderMap,
+        build: F,
+    ) -> Result<R, EurekaError>
+    where
+        R: DeserializeOwned,
+        F: FnOnce(RequestBuilder) -> RequestBuilder,
+    {
+        let resp = self.make_request_with(app, path, method, headers, build)?;
+        response::read_success(resp).map(|resp| resp.body)
+    }
+
+    /// Calls another app and returns the status and headers of its response, with the JSON
+    /// `R` body of a 2xx status or the JSON `E` body of an error status
+    ///
+    /// Error bodies which aren't an `E` fail with their status and text.
+    pub fn call_with_error<V, R, E>(
+        &self,
+        app: &str,
+        path: &str,
+        method: Method,
+        body: &V,
+        headers: HeaderMap,
+    ) -> Result<CallResponse<Result<R, E>>, EurekaError>
+    where
+        V: Serialize,
+        R: DeserializeOwned,
+        E: DeserializeOwned,
+    {
+        let resp = self.make_request(app, path, method, body, headers)?;
+        response::read(resp)
+    }
+
+    /// Calls every UP instance of `app` concurrently, e.g. to invalidate their caches, and
+    /// returns the JSON body of each 2xx response or the error of each failed call, by
+    /// instance id
+    ///
+    /// At most `broadcast_concurrency` instances are called at once.
+    /// An app without UP instances gives an empty `Vec`.
+    pub fn broadcast<V, R>(
+        &self,
+        app: &str,
+        path: &str,
+        method: Method,
+        body: &V,
+        headers: HeaderMap,
+    ) -> Vec<(String, Result<R, EurekaError>)>
+    where
+        V: Serialize + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let instances = self.resolve_all(app);
+        diag!(
+            self.diagnostics,
+            Debug,
+            "broadcasting to {} instances of {}",
+            instances.len(),
+            app
+        );
+        let concurrency = self.config.eureka.broadcast_concurrency.max(1);
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new((0..instances.len()).map(|_| None).collect::<Vec<_>>());
+        thread::scope(|scope| {
+            let mut workers = Vec::new();
+            for _ in 0..concurrency.min(instances.len()) {
+                workers.push(scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let instance = match instances.get(index) {
+                        Some(instance) => instance,
+                        None => break,
+                    };
+                    let (method, headers) = (method.clone(), headers.clone());
+                    let id = &instance.instance_id;
+                    let result = trace::instrument("broadcast", app, id, || {
+                        let build = |request: RequestBuilder| request.json(body);
+                        self.send_to(app, instance, path, method, headers, build)
+                    })
+                    .and_then(response::read_success)
+                    .map(|resp| resp.body);
+                    if let Err(ref e) = result {
+                        self.errors.push("broadcast", e);
+                    }
+                    let mut slots = results.lock().unwrap_or_else(PoisonError::into_inner);
+                    slots[index] = Some(result);
+                }));
+            }
+            // Joined for a panicking call not to panic the scope, its result stays missing
+            for worker in workers {
+                let _ = worker.join();
+            }
+        });
+        let results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+        instances
+            .iter()
+            .zip(results)
+            .map(|(instance, result)| {
+                let result = result.unwrap_or_else(|| {
+                    Err(EurekaError::UnexpectedState(
+                        "Broadcast call panicked".to_string(),
+                    ))
+                });
+                (instance.instance_id.clone(), result)
+            })
+            .collect()
+    }
+}
+
+fn eureka_base_url(config: &EurekaConfig) -> String {
+    let protocol = if config.ssl { "https" } else { "http" };
+    format!(
+        "{}://{}{}",
+        protocol,
+        host_port(&config.host, config.port),
+        normalize_service_path(&config.service_path)
+    )
+}
+
+/// Service path with a leading slash, without empty segments nor trailing slash,
+/// e.g. `/eureka/v2` for `eureka//v2/`
+fn normalize_service_path(path: &str) -> String {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            percent_encoding::utf8_percent_encode(segment, percent_encoding::DEFAULT_ENCODE_SET)
+                .to_string()
+        })
+        .fold(String::new(), |path, segment| path + "/" + &segment)
+}
+
+/// `host:port`, with IPv6 literals in brackets
+fn host_port(host: &str, port: u16) -> String {
+    if host.parse::<Ipv6Addr>().is_ok() {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Fills the data center info and zone of the instance from the `data_center` provider
+fn apply_data_center(config: &mut BaseConfig) {
+    if let Some(provider) = config.eureka.data_center.provider() {
+        match provider.data_center() {
+            Ok(data_center) => data_center.apply_to(&mut config.instance),
+            Err(e) => error!("Failed to read the {} metadata: {}", provider.name(), e),
         }
     }
 }
 
+/// Eureka servers of `resolver`, shuffled per instance within each zone and
+/// refreshed every `cluster_refresh_interval`
+fn cluster_resolver(
+    config: &BaseConfig,
+    resolver: Arc<dyn ClusterResolver>,
+) -> Arc<dyn ClusterResolver> {
+    let seed = config
+        .instance
+        .instance_id
+        .as_ref()
+        .unwrap_or(&config.instance.host_name);
+    let resolver = Arc::new(ShuffledResolver::new(resolver, seed));
+    if config.eureka.cluster_refresh_interval == 0 {
+        return resolver;
+    }
+    let refreshing = RefreshingResolver::new(resolver);
+    refreshing.start(Duration::from_millis(
+        config.eureka.cluster_refresh_interval as u64,
+    ));
+    Arc::new(refreshing)
+}
+
+/// Eureka servers of the config, looked up in DNS with `use_dns`
+#[cfg(feature = "dns")]
+fn config_resolver(config: &BaseConfig) -> Arc<dyn ClusterResolver> {
+    let local_zone = config.local_zone();
+    if config.eureka.use_dns {
+        match DnsResolver::new(&config.eureka, local_zone.map(String::from)) {
+            Ok(resolver) => return Arc::new(resolver),
+            Err(e) => error!("{}, using the configured eureka servers", e),
+        }
+    }
+    Arc::new(ConfigResolver::new(&config.eureka, local_zone))
+}
+
+#[cfg(not(feature = "dns"))]
+fn config_resolver(config: &BaseConfig) -> Arc<dyn ClusterResolver> {
+    if config.eureka.use_dns {
+        warn!("use_dns needs the dns feature, using the configured eureka servers");
+    }
+    Arc::new(ConfigResolver::new(&config.eureka, config.local_zone()))
+}
+
 fn path_segment_encode(value: &str) -> String {
     percent_encoding::utf8_percent_encode(value, percent_encoding::PATH_SEGMENT_ENCODE_SET)
         .to_string()
@@ -0,0 +1,165 @@
+//! `reqwest-middleware` resolving `eureka://APP/path` urls, behind the `reqwest-middleware`
+//! feature
+
+use std::sync::Arc;
+
+use http::Extensions;
+use rand::seq::SliceRandom;
+use reqwest_middleware::reqwest::{Request, Response, Url};
+use reqwest_middleware::{Error, Middleware, Next, Result};
+
+use crate::discovery::ServiceDiscovery;
+use crate::rest::structures::Instance;
+use crate::{EurekaClient, EurekaError, ResolvedInstance};
+
+/// Middleware sending the requests to `eureka://APP/path` urls to an UP instance of `APP`
+///
+/// The app name is matched case-insensitively, as url hosts are lowercased. A request
+/// failing to connect is sent to another instance, unless its body can't be cloned.
+/// Requests to other schemes go through unchanged.
+#[derive(Debug, Clone)]
+pub struct EurekaMiddleware {
+    discovery: Arc<dyn ServiceDiscovery>,
+    ssl: bool,
+    max_attempts: usize,
+}
+
+impl EurekaMiddleware {
+    pub const SCHEME: &'static str = "eureka";
+
+    /// Middleware sending the requests to the instances of `discovery`, on the port each of
+    /// them enables, the secure one when both are and `ssl`
+    pub fn new(discovery: Arc<dyn ServiceDiscovery>, ssl: bool) -> Self {
+        EurekaMiddleware {
+            discovery,
+            ssl,
+            max_attempts: 3,
+        }
+    }
+
+    /// Tries at most `max_attempts` instances per request, default 3
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// UP instances of `app` in random order
+    fn instances(&self, app: &str) -> Vec<Instance> {
+        let mut instances = self.discovery.instances(app);
+        instances.shuffle(&mut rand::thread_rng());
+        instances
+    }
+
+    /// `url` with the scheme and address of `instance`, as [`EurekaClient::resolve`] picks them
+    fn instance_url(&self, url: &Url, instance: &Instance) -> Result<Url> {
+        let instance = ResolvedInstance::new(instance, self.ssl);
+        let mut resolved = format!("{}{}", instance.base_url(), url.path());
+        if let Some(query) = url.query() {
+            resolved.push('?');
+            resolved.push_str(query);
+        }
+        Url::parse(&resolved).map_err(Error::middleware)
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for EurekaMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        if req.url().scheme() != EurekaMiddleware::SCHEME {
+            return next.run(req, extensions).await;
+        }
+        let app = req.url().host_str().unwrap_or_default().to_string();
+        let mut instances = self
+            .instances(&app)
+            .into_iter()
+            .take(self.max_attempts)
+            .peekable();
+        while let Some(instance) = instances.next() {
+            let retry = if instances.peek().is_some() {
+                req.try_clone()
+            } else {
+                None
+            };
+            *req.url_mut() = self.instance_url(req.url(), &instance)?;
+            match (next.clone().run(req, extensions).await, retry) {
+                (Err(e), Some(retry)) if e.is_connect() => {
+                    warn!(
+                        "Failed to connect to {} instance {}: {}, trying another one",
+                        app, instance.ip_addr, e
+                    );
+                    req = retry;
+                }
+                (result, _) => return result,
+            }
+        }
+        Err(Error::middleware(EurekaError::UnexpectedState(format!(
+            "Could not find app {}",
+            app
+        ))))
+    }
+}
+
+impl EurekaClient {
+    /// Middleware sending the requests to `eureka://APP/path` urls to the instances of `APP`
+    pub fn middleware(&self) -> EurekaMiddleware {
+        EurekaMiddleware::new(self.discovery(), self.config.eureka.ssl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::RegistryDiscovery;
+    use crate::registry::{AppCache, Apps};
+    use crate::rest::structures::{PortData, SecurePort, StatusType};
+    use std::time::Duration;
+
+    #[test]
+    fn test_instance_url() {
+        let mut instance = Instance::default();
+        instance.app = "MY-APP".to_string();
+        instance.status = StatusType::Up;
+        instance.ip_addr = "fd00::1".to_string();
+        instance.port = PortData::new(8080, true);
+        let mut down = instance.clone();
+        down.status = StatusType::Down;
+        let mut apps = Apps::new();
+        apps.insert(
+            "MY-APP".to_string(),
+            vec![Arc::new(instance), Arc::new(down)],
+        );
+        let middleware = EurekaMiddleware::new(
+            Arc::new(RegistryDiscovery::new(
+                Arc::new(AppCache::new(apps)),
+                Duration::from_secs(30),
+            )),
+            false,
+        );
+
+        let url = Url::parse("eureka://my-app/api/items?page=2").unwrap();
+        let instances = middleware.instances(url.host_str().unwrap());
+        assert_eq!(instances.len(), 1);
+        assert_eq!(
+            middleware
+                .instance_url(&url, &instances[0])
+                .unwrap()
+                .as_str(),
+            "http://[fd00::1]:8080/api/items?page=2"
+        );
+        assert!(middleware.instances("other-app").is_empty());
+
+        // An instance only enabling its secure port is called on it, even without ssl
+        let mut secure = instances[0].clone();
+        secure.port = PortData::new(8080, false);
+        secure.secure_port = SecurePort::new(8443, true);
+        assert_eq!(
+            middleware.instance_url(&url, &secure).unwrap().as_str(),
+            "https://[fd00::1]:8443/api/items?page=2"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! OpenTelemetry metrics of the client, behind the `otel` feature
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use opentelemetry::metrics::{Histogram, Meter, ObservableCounter, ObservableGauge};
+use opentelemetry::KeyValue;
+
+use crate::health::LatencyObserver;
+use crate::EurekaClient;
+
+/// Instruments of a client registered with [`EurekaClient::register_metrics`]
+///
+/// The counters and gauges stop being reported once this is dropped.
+#[derive(Debug)]
+pub struct EurekaMetrics {
+    requests: ObservableCounter<u64>,
+    errors: ObservableCounter<u64>,
+    quarantined_endpoints: ObservableGauge<u64>,
+    registered: ObservableGauge<u64>,
+}
+
+/// Records the run durations of an operation, in seconds
+#[derive(Debug)]
+struct DurationHistogram {
+    histogram: Histogram<f64>,
+}
+
+impl LatencyObserver for DurationHistogram {
+    fn observe(&self, elapsed: Duration, success: bool) {
+        let outcome = if success { "success" } else { "failure" };
+        self.histogram
+            .record(elapsed.as_secs_f64(), &[KeyValue::new("outcome", outcome)]);
+    }
+}
+
+fn duration_histogram(
+    meter: &Meter,
+    name: &'static str,
+    description: &'static str,
+) -> Arc<DurationHistogram> {
+    Arc::new(DurationHistogram {
+        histogram: meter
+            .f64_histogram(name)
+            .with_description(description)
+            .with_unit("s")
+            .build(),
+    })
+}
+
+impl EurekaClient {
+    /// Exports the request and error counters, the heartbeat and registry fetch durations
+    /// and the registration state of the client through `meter`
+    pub fn register_metrics(&self, meter: &Meter) -> EurekaMetrics {
+        self.registry.set_fetch_observer(duration_histogram(
+            meter,
+            "eureka.client.registry_fetch.duration",
+            "Duration of the registry fetches",
+        ));
+        if let Some(ref instance) = self.instance {
+            instance.set_heartbeat_observer(duration_histogram(
+                meter,
+                "eureka.client.heartbeat.duration",
+                "Duration of the heartbeats",
+            ));
+        }
+
+        let limiter = Arc::clone(&self.limiter);
+        let requests = meter
+            .u64_observable_counter("eureka.client.requests")
+            .with_description("Eureka requests let through or dropped by the rate limiter")
+            .with_callback(move |observer| {
+                let stats = limiter.stats();
+                observer.observe(stats.allowed as u64, &[KeyValue::new("result", "allowed")]);
+                observer.observe(stats.dropped as u64, &[KeyValue::new("result", "dropped")]);
+            })
+            .build();
+        let error_log = Arc::clone(&self.errors);
+        let errors = meter
+            .u64_observable_counter("eureka.client.errors")
+            .with_description("Failed eureka operations and requests")
+            .with_callback(move |observer| observer.observe(error_log.total() as u64, &[]))
+            .build();
+        let endpoint_health = Arc::clone(&self.endpoint_health);
+        let quarantined_endpoints = meter
+            .u64_observable_gauge("eureka.client.quarantined_endpoints")
+            .with_description("Eureka servers quarantined after repeated failures")
+            .with_callback(move |observer| {
+                observer.observe(endpoint_health.quarantined().len() as u64, &[])
+            })
+            .build();
+        // Weak, a dropped client deregisters its instance
+        let instance = self.instance.as_ref().map(Arc::downgrade);
+        let registered = meter
+            .u64_observable_gauge("eureka.client.registered")
+            .with_description("1 when the instance is registered with eureka")
+            .with_callback(move |observer| {
+                let registered = instance
+                    .as_ref()
+                    .and_then(|instance| instance.upgrade())
+                    .map_or(false, |instance| instance.is_registered());
+                observer.observe(registered as u64, &[])
+            })
+            .build();
+        EurekaMetrics {
+            requests,
+            errors,
+            quarantined_endpoints,
+            registered,
+        }
+    }
+}
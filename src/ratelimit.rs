@@ -0,0 +1,215 @@
+//! Token bucket limiting outbound eureka requests
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::clock::SharedClock;
+
+/// Counters of the rate limiter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitStats {
+    /// Requests let through
+    pub allowed: usize,
+    /// Requests dropped because the bucket was empty
+    pub dropped: usize,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token bucket shared by all requests sent to the eureka server
+///
+/// A request takes one token, however many servers it fails over to. Registrations and
+/// deregistrations wait for a token instead of being dropped.
+#[derive(Debug)]
+pub struct RateLimiter {
+    burst: usize,
+    refill_rate: usize,
+    bucket: Mutex<Bucket>,
+    allowed: AtomicUsize,
+    dropped: AtomicUsize,
+    clock: SharedClock,
+}
+
+impl RateLimiter {
+    /// `burst` is the bucket capacity, `refill_rate` the tokens added per second
+    pub fn new(burst: usize, refill_rate: usize) -> Self {
+        RateLimiter {
+            burst,
+            refill_rate,
+            bucket: Mutex::new(Bucket {
+                tokens: burst as f64,
+                last_refill: Instant::now(),
+            }),
+            allowed: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Refill the bucket with the time of `clock`
+    pub(crate) fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.bucket.get_mut().unwrap().last_refill = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    /// Take one token, returns false if the request should be dropped
+    pub fn try_acquire(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        if self.take(&mut bucket) {
+            true
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    /// Take one token, waiting for the bucket to refill when it is empty
+    ///
+    /// A bucket which never refills lets the request through, it would wait forever.
+    pub fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                if self.take(&mut bucket) {
+                    return;
+                }
+                if self.refill_rate == 0 {
+                    self.allowed.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.refill_rate as f64)
+            };
+            self.clock.sleep(wait);
+        }
+    }
+
+    /// Refills the bucket and takes a token out of it if there is one
+    fn take(&self, bucket: &mut Bucket) -> bool {
+        let now = self.clock.now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_rate as f64).min(self.burst as f64);
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            self.allowed.fetch_add(1, Ordering::Relaxed);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn stats(&self) -> RateLimitStats {
+        RateLimitStats {
+            allowed: self.allowed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(10, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::resolver::StaticResolver;
+    use crate::rest::{EurekaApi, EurekaRestClient};
+    use crate::test_util::{instance, MockEurekaServer};
+    use crate::EurekaError;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_drop_after_burst() {
+        let limiter = RateLimiter::new(2, 0);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        assert_eq!(
+            limiter.stats(),
+            RateLimitStats {
+                allowed: 2,
+                dropped: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_limit_eureka_requests() {
+        let server = MockEurekaServer::start().unwrap();
+        // The first server refuses the connection, the request fails over with a single token
+        let urls = ["http://127.0.0.1:1".to_string(), server.service_url()];
+        let resolver = StaticResolver::from_urls(&urls);
+        let mock = Arc::new(MockClock::default());
+        let clock = SharedClock::default();
+        clock.set_clock(Arc::clone(&mock) as Arc<dyn Clock>);
+        let limiter = Arc::new(RateLimiter::new(1, 1).with_clock(clock));
+        let client = EurekaRestClient::with_resolver(Arc::new(resolver), Arc::clone(&limiter));
+        let instance = instance("APP").id("app-1").build();
+
+        client.register("APP", &instance).unwrap();
+        assert!(matches!(
+            client.send_heartbeat("APP", "app-1").unwrap_err().root(),
+            EurekaError::RateLimited
+        ));
+        // The deregistration waits for the bucket to refill instead of being dropped
+        thread::scope(|scope| {
+            let deregister = scope.spawn(|| client.deregister("APP", "app-1"));
+            mock.wait_for_sleepers(1);
+            assert!(mock.advance_to_next_wakeup());
+            deregister.join().unwrap().unwrap();
+        });
+        assert_eq!(mock.elapsed(), Duration::from_secs(1));
+        assert_eq!(
+            limiter.stats(),
+            RateLimitStats {
+                allowed: 2,
+                dropped: 1
+            }
+        );
+
+        let client = client.without_rate_limit();
+        client.register("APP", &instance).unwrap();
+        client.send_heartbeat("APP", "app-1").unwrap();
+        assert_eq!(limiter.stats().dropped, 1);
+    }
+
+    #[test]
+    fn test_acquire_without_refill() {
+        let limiter = RateLimiter::new(1, 0);
+        limiter.acquire();
+        assert!(!limiter.try_acquire());
+        limiter.acquire();
+        assert_eq!(
+            limiter.stats(),
+            RateLimitStats {
+                allowed: 2,
+                dropped: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_refill() {
+        let mock = Arc::new(MockClock::default());
+        let clock = SharedClock::default();
+        clock.set_clock(Arc::clone(&mock) as Arc<dyn Clock>);
+        let limiter = RateLimiter::new(1, 2).with_clock(clock);
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        mock.advance(Duration::from_millis(500));
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+}
@@ -1,70 +1,410 @@
-use itertools::Itertools;
 use rand::random;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::rest::structures::{Instance, StatusType};
-use crate::rest::EurekaRestClient;
+use crate::clock::SharedClock;
+use crate::diagnostics::Diagnostics;
+use crate::events::{EventKind, Events};
+use crate::health::{ErrorLog, HealthReport, LatencyObserver, LatencyPercentiles, OperationStatus};
+use crate::rest::structures::{AppMetaDataType, Application, Applications, Instance, StatusType};
+use crate::rest::EurekaApi;
+use crate::routing::Routes;
+use crate::{trace, EurekaError, IpPreference};
+
+/// Outcome of the last registry parse
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseReport {
+    /// Instances parsed and cached
+    pub parsed: usize,
+    /// Errors of the malformed instances skipped in lenient mode
+    pub skipped: Vec<String>,
+}
 
 #[derive(Debug)]
 pub struct RegistryClient {
-    client: Arc<EurekaRestClient>,
-    app_cache: Arc<RwLock<HashMap<String, Vec<Instance>>>>,
+    client: Arc<dyn EurekaApi>,
+    app_cache: Arc<AppCache>,
+    /// Changes of the cache so far, e.g. to rebuild what is derived from it
+    generation: Arc<AtomicUsize>,
+    parse_report: Arc<RwLock<ParseReport>>,
+    fetch_status: Arc<RwLock<OperationStatus>>,
+    errors: Arc<ErrorLog>,
+    diagnostics: Diagnostics,
+    events: Events,
+    lenient_parsing: bool,
+    ip_preference: IpPreference,
+    local_zone: Option<String>,
+    /// Instance of the client, whose host and rack are preferred
+    local_instance: Option<Instance>,
+    routes: Routes,
+    peers: Option<Arc<Peers>>,
     is_running: Arc<AtomicBool>,
+    clock: SharedClock,
+    stale_after: Option<Duration>,
+    fetch_interval: Duration,
+    /// Start of the last refresh on demand, locked while it is in flight
+    on_demand: Mutex<Option<Instant>>,
 }
 
 impl RegistryClient {
-    pub fn new(base_url: String) -> Self {
+    pub fn new(client: Arc<dyn EurekaApi>, lenient_parsing: bool) -> Self {
         RegistryClient {
-            client: Arc::new(EurekaRestClient::new(base_url)),
-            app_cache: Arc::new(RwLock::new(HashMap::new())),
+            client,
+            app_cache: Arc::new(AppCache::default()),
+            generation: Arc::new(AtomicUsize::new(0)),
+            parse_report: Arc::new(RwLock::new(ParseReport::default())),
+            fetch_status: Arc::new(RwLock::new(OperationStatus::default())),
+            errors: Arc::new(ErrorLog::default()),
+            diagnostics: Diagnostics::default(),
+            events: Events::default(),
+            lenient_parsing,
+            ip_preference: IpPreference::Dual,
+            local_zone: None,
+            local_instance: None,
+            routes: Routes::default(),
+            peers: None,
             is_running: Arc::new(AtomicBool::new(false)),
+            clock: SharedClock::default(),
+            stale_after: None,
+            fetch_interval: Duration::from_secs(30),
+            on_demand: Mutex::new(None),
         }
     }
 
+    /// Pick the instances of the preferred address family first
+    pub fn with_ip_preference(mut self, ip_preference: IpPreference) -> Self {
+        self.ip_preference = ip_preference;
+        self
+    }
+
+    /// Keep the errors of the registry fetches in `errors`
+    pub(crate) fn with_error_log(mut self, errors: Arc<ErrorLog>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    pub(crate) fn with_diagnostics(mut self, diagnostics: Diagnostics) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Report the cache refreshes on `events`
+    pub(crate) fn with_events(mut self, events: Events) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Time the registry fetches with `clock`
+    pub(crate) fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Fetch the registry every `fetch_interval` once started
+    pub(crate) fn with_fetch_interval(mut self, fetch_interval: Duration) -> Self {
+        self.fetch_interval = fetch_interval;
+        self
+    }
+
+    /// Consider the cache stale when not refreshed for `stale_after`, `None` when the
+    /// registry isn't fetched
+    pub(crate) fn with_stale_after(mut self, stale_after: Option<Duration>) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    /// Pick the instances of `zone` first
+    pub fn with_local_zone(mut self, zone: Option<String>) -> Self {
+        self.local_zone = zone;
+        self
+    }
+
+    /// Pick the instances on the host of `instance` first, then the ones on its rack, before
+    /// the ones of the local zone
+    pub(crate) fn with_local_instance(mut self, instance: Option<Instance>) -> Self {
+        self.local_instance = instance;
+        self
+    }
+
+    /// Report the changes of `peers` on the events
+    pub(crate) fn with_peers(mut self, peers: Option<Peers>) -> Self {
+        self.peers = peers.map(Arc::new);
+        self
+    }
+
     pub fn update_app_cache(&self) -> Result<(), String> {
-        RegistryClient::update_app_cache_internal(&self.client, &self.app_cache)
+        RegistryClient::update_app_cache_internal(
+            &self.client,
+            &self.app_cache,
+            &self.generation,
+            &self.parse_report,
+            &self.fetch_status,
+            &self.errors,
+            &self.diagnostics,
+            &self.events,
+            self.peers.as_deref(),
+            &self.clock,
+            self.lenient_parsing,
+        )
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn update_app_cache_internal(
-        client: &Arc<EurekaRestClient>,
-        app_cache: &Arc<RwLock<HashMap<String, Vec<Instance>>>>,
+        client: &Arc<dyn EurekaApi>,
+        app_cache: &AppCache,
+        generation: &AtomicUsize,
+        parse_report: &Arc<RwLock<ParseReport>>,
+        fetch_status: &Arc<RwLock<OperationStatus>>,
+        errors: &ErrorLog,
+        diagnostics: &Diagnostics,
+        events: &Events,
+        peers: Option<&Peers>,
+        clock: &SharedClock,
+        lenient_parsing: bool,
     ) -> Result<(), String> {
-        let resp = client.get_all_instances();
-        match resp {
-            Ok(instances) => {
-                // println!("got instances {:?}", instances);
-                *app_cache.write().unwrap() = group_instances_by_app(instances);
+        let start = clock.now();
+        let result = trace::instrument("fetch_registry", "", "", || {
+            RegistryClient::fetch_app_cache(
+                client,
+                app_cache,
+                parse_report,
+                diagnostics,
+                lenient_parsing,
+            )
+        });
+        write(fetch_status).record(&result, clock.now() - start);
+        match result {
+            Ok(changed_apps) => {
+                // Unchanged, what is derived from the cache is still valid
+                if !changed_apps.is_empty() {
+                    generation.fetch_add(1, Ordering::Relaxed);
+                }
+                let apps = app_cache.load();
+                events.emit(EventKind::CacheRefreshed {
+                    applications: apps.len(),
+                    instances: apps.values().map(Vec::len).sum(),
+                    changed_apps,
+                });
+                if let Some(peers) = peers {
+                    peers.update(app_cache, events);
+                }
                 Ok(())
             }
-            Err(e) => Err(format!("Failed to fetch registry: {:?}", e)),
+            Err(e) => {
+                errors.push("fetch_registry", &e);
+                Err(e)
+            }
         }
     }
+
+    fn fetch_app_cache(
+        client: &Arc<dyn EurekaApi>,
+        app_cache: &AppCache,
+        parse_report: &Arc<RwLock<ParseReport>>,
+        diagnostics: &Diagnostics,
+        lenient_parsing: bool,
+    ) -> Result<Vec<String>, String> {
+        // The unchanged instances are taken from the cache rather than copied
+        let cached = app_cache.load();
+        let instances = client
+            .stream_all_instances_reusing(&cached)
+            .map_err(|e| format!("Failed to fetch registry: {:?}", e))?;
+        let mut report = ParseReport::default();
+        let mut error = None;
+        // Registries rarely gain apps, the cached count sizes the map
+        let apps_hint = cached.len();
+        let apps = group_instances_by_app(
+            apps_hint,
+            instances
+                .scan((), |_, instance| match instance {
+                    Ok(instance) => {
+                        report.parsed += 1;
+                        Some(Some(instance))
+                    }
+                    // A malformed instance doesn't prevent reading the next ones
+                    Err(EurekaError::ParseError(e)) if lenient_parsing => {
+                        diag!(diagnostics, Warn, "Skipping malformed instance: {}", e);
+                        report.skipped.push(e.to_string());
+                        Some(None)
+                    }
+                    Err(e) => {
+                        error = Some(e);
+                        None
+                    }
+                })
+                .flatten(),
+        );
+        match error {
+            Some(e) => Err(format!("Failed to parse registry: {:?}", e)),
+            None => {
+                let changed_apps = app_cache.merge(apps);
+                *write(parse_report) = report;
+                Ok(changed_apps)
+            }
+        }
+    }
+
     pub fn start(&self) {
         self.is_running.store(true, Ordering::Relaxed);
 
         let is_running = Arc::clone(&self.is_running);
         let client = Arc::clone(&self.client);
         let app_cache = Arc::clone(&self.app_cache);
+        let generation = Arc::clone(&self.generation);
+        let parse_report = Arc::clone(&self.parse_report);
+        let fetch_status = Arc::clone(&self.fetch_status);
+        let errors = Arc::clone(&self.errors);
+        let diagnostics = self.diagnostics.clone();
+        let events = self.events.clone();
+        let peers = self.peers.clone();
+        let clock = self.clock.clone();
+        let fetch_interval = self.fetch_interval;
+        let lenient_parsing = self.lenient_parsing;
         self.update_app_cache();
         thread::spawn(move || {
             while is_running.load(Ordering::Relaxed) {
-                RegistryClient::update_app_cache_internal(&client, &app_cache)
-                    .map_err(|e| println!("{}", e));
-                thread::sleep(Duration::from_secs(30));
+                RegistryClient::update_app_cache_internal(
+                    &client,
+                    &app_cache,
+                    &generation,
+                    &parse_report,
+                    &fetch_status,
+                    &errors,
+                    &diagnostics,
+                    &events,
+                    peers.as_deref(),
+                    &clock,
+                    lenient_parsing,
+                )
+                .map_err(|e| diag!(diagnostics, Error, "{}", e));
+                clock.sleep(fetch_interval);
             }
         });
     }
 
-    pub fn get_instance_by_app_name(&self, app: &str) -> Option<Instance> {
-        // Clone the result to avoid holding onto a lock on the app cache indefinitely
-        self.app_cache
-            .read()
-            .unwrap()
-            .get(app)
+    /// Refreshes the cache on demand, e.g. when an instance is unreachable, unless another
+    /// refresh on demand is in flight or one of the last `min_age` or the cache is younger,
+    /// returns whether it refreshed
+    ///
+    /// Requests failing at once refresh the cache once, rather than each of them.
+    pub(crate) fn refresh_on_demand(&self, min_age: Duration) -> Result<bool, String> {
+        let mut started_at = match self.on_demand.try_lock() {
+            Ok(started_at) => started_at,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return Ok(false),
+        };
+        let now = self.clock.now();
+        let recent = started_at.map_or(false, |at| now.duration_since(at) < min_age);
+        if recent || !self.is_stale(min_age) {
+            return Ok(false);
+        }
+        *started_at = Some(now);
+        self.update_app_cache().map(|()| true)
+    }
+
+    /// Cached instances by app name, shared with the discovery of the `tower` feature
+    pub(crate) fn app_cache(&self) -> Arc<AppCache> {
+        Arc::clone(&self.app_cache)
+    }
+
+    /// Whether the cache is empty, was never fetched or wasn't refreshed for `max_age`
+    pub(crate) fn is_stale(&self, max_age: Duration) -> bool {
+        let age = read(&self.fetch_status).age().map(Duration::from_millis);
+        self.app_cache.load().is_empty() || age.map_or(true, |age| age > max_age)
+    }
+
+    /// Number of changes of the cache so far, refreshes and evictions
+    pub(crate) fn generation(&self) -> usize {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Report of the last successful registry parse
+    pub fn last_parse_report(&self) -> ParseReport {
+        read(&self.parse_report).clone()
+    }
+
+    /// Copy of the cached registry, apps sorted by name
+    pub fn dump(&self) -> Applications {
+        let apps = self.app_cache.load();
+        let mut applications: Vec<Application> = apps
+            .iter()
+            .map(|(name, instances)| Application {
+                name: name.clone(),
+                instances: instances
+                    .iter()
+                    .map(|instance| Instance::clone(instance))
+                    .collect(),
+            })
+            .collect();
+        applications.sort_by(|a, b| a.name.cmp(&b.name));
+        Applications {
+            versions_delta: None,
+            apps_hashcode: None,
+            applications,
+        }
+    }
+
+    /// Sends the round-trip time of each registry fetch to `observer`
+    pub(crate) fn set_fetch_observer(&self, observer: Arc<dyn LatencyObserver>) {
+        write(&self.fetch_status).set_observer(observer);
+    }
+
+    /// Round-trip times of the last registry fetches
+    pub fn fetch_latency(&self) -> LatencyPercentiles {
+        read(&self.fetch_status).latency()
+    }
+
+    /// Fills the registry fetch state of `report`
+    pub fn report_health(&self, report: &mut HealthReport) {
+        let fetch_status = read(&self.fetch_status);
+        report.registry_age = fetch_status.age();
+        report.last_registry_error = fetch_status.last_error();
+        report.eureka_reachable |= fetch_status.is_ok();
+        report.registry_degraded = self.is_degraded();
+    }
+
+    /// Whether a thread panicked while holding the registry state. The last cache written
+    /// is still served, as a whole registry replaces the previous one.
+    pub fn is_degraded(&self) -> bool {
+        self.app_cache.is_poisoned()
+            || self.parse_report.is_poisoned()
+            || self.fetch_status.is_poisoned()
+    }
+
+    /// UP instance of `app` picked as [`get_instance_by_app_name`](Self::get_instance_by_app_name)
+    /// does, or why there is none
+    pub fn find_instance(&self, app: &str) -> Result<Arc<Instance>, EurekaError> {
+        if let Some(instance) = self.get_instance_by_app_name(app) {
+            return Ok(instance);
+        }
+        let instances = app_instances(&self.app_cache.load(), app).map(Vec::len);
+        let age = read(&self.fetch_status).age().map(Duration::from_millis);
+        match (instances, self.stale_after) {
+            (Some(instances), _) => Err(EurekaError::NoUpInstance {
+                app: app.to_string(),
+                instances,
+            }),
+            (None, Some(stale_after)) if age.map_or(true, |age| age > stale_after) => {
+                Err(EurekaError::StaleRegistry {
+                    app: app.to_string(),
+                    age,
+                })
+            }
+            (None, _) => Err(EurekaError::AppNotFound(app.to_string())),
+        }
+    }
+
+    /// Random UP instance of `app`, matched case-insensitively, shared with the cache rather
+    /// than copied
+    pub fn get_instance_by_app_name(&self, app: &str) -> Option<Arc<Instance>> {
+        let apps = self.app_cache.load();
+        app_instances(&apps, app)
             .and_then(|instances| {
                 //random select one UP node
                 let mut valid_ids: Vec<usize> = Vec::new();
@@ -73,15 +413,117 @@ impl RegistryClient {
                         valid_ids.push(i);
                     }
                 }
-                if !valid_ids.is_empty() {
-                    let index = valid_ids[random::<usize>() % valid_ids.len()];
-                    instances.get(index)
-                } else {
-                    None
-                }
+                valid_ids = self.routes.select(app, instances, valid_ids);
+                self.pick(instances, valid_ids)
             })
             .cloned()
     }
+
+    /// UP instance whose `vipAddress`, or `secureVipAddress` when `secure`, lists `vip`,
+    /// picked with the address and zone preferences
+    pub fn find_instance_by_vip(
+        &self,
+        vip: &str,
+        secure: bool,
+    ) -> Result<Arc<Instance>, EurekaError> {
+        let instances = vip_instances(&self.app_cache, vip, secure);
+        self.pick(&instances, (0..instances.len()).collect())
+            .cloned()
+            .ok_or_else(|| EurekaError::VipNotFound(vip.to_string()))
+    }
+
+    /// The other UP instances of the app of the client, none when it doesn't register
+    pub(crate) fn peers(&self) -> Vec<Arc<Instance>> {
+        match self.peers {
+            Some(ref peers) => peers.of(&self.app_cache),
+            None => Vec::new(),
+        }
+    }
+
+    /// Whether the instance of the client is UP with the lowest instance id of its app, false
+    /// when it doesn't register
+    pub(crate) fn leads_peers(&self) -> bool {
+        match self.peers {
+            Some(ref peers) => peers.leads(&self.app_cache),
+            None => false,
+        }
+    }
+
+    /// Routing rules of the apps, applied before the address and zone preferences
+    pub(crate) fn routes(&self) -> &Routes {
+        &self.routes
+    }
+
+    /// Removes the instance `instance_id` of `app` from the cache until the next refresh
+    /// lists it again, e.g. when it stopped accepting connections
+    pub(crate) fn evict_instance(&self, app: &str, instance_id: &str) -> bool {
+        let evicted = self.app_cache.update(|apps| match apps.get_mut(app) {
+            Some(instances) => {
+                let before = instances.len();
+                instances.retain(|instance| self::instance_id(instance) != instance_id);
+                instances.len() < before
+            }
+            None => false,
+        });
+        if evicted {
+            self.generation.fetch_add(1, Ordering::Relaxed);
+        }
+        evicted
+    }
+}
+
+impl RegistryClient {
+    /// Random instance out of the `valid_ids` indices of `instances`, preferring the
+    /// addresses of the preferred family, then the closest ones: on the local host, rack or
+    /// zone
+    fn pick<'a>(
+        &self,
+        instances: &'a [Arc<Instance>],
+        mut valid_ids: Vec<usize>,
+    ) -> Option<&'a Arc<Instance>> {
+        let preferred: Vec<usize> = valid_ids
+            .iter()
+            .cloned()
+            .filter(|&i| self.is_preferred_address(&instances[i].ip_addr))
+            .collect();
+        if !preferred.is_empty() {
+            valid_ids = preferred;
+        }
+        let local = self.local_instance.as_ref();
+        let local_rack = local.and_then(Instance::rack);
+        let local_zone = self.local_zone.as_deref();
+        let closer: [&dyn Fn(&Instance) -> bool; 3] = [
+            &|instance| local.map_or(false, |local| is_same_host(local, instance)),
+            &|instance| local_rack.is_some() && instance.rack() == local_rack,
+            &|instance| local_zone.is_some() && instance.zone() == local_zone,
+        ];
+        for is_closer in &closer {
+            let closest: Vec<usize> = valid_ids
+                .iter()
+                .cloned()
+                .filter(|&i| is_closer(&instances[i]))
+                .collect();
+            if !closest.is_empty() {
+                valid_ids = closest;
+                break;
+            }
+        }
+        if !valid_ids.is_empty() {
+            let index = valid_ids[random::<usize>() % valid_ids.len()];
+            instances.get(index)
+        } else {
+            None
+        }
+    }
+
+    fn is_preferred_address(&self, ip_addr: &str) -> bool {
+        match (self.ip_preference, ip_addr.parse::<IpAddr>()) {
+            (IpPreference::PreferV4, Ok(IpAddr::V4(_))) => true,
+            (IpPreference::PreferV6, Ok(IpAddr::V6(_))) => true,
+            (IpPreference::Dual, _) => true,
+            _ => false,
+        }
+    }
 }
 
 impl Drop for RegistryClient {
@@ -90,11 +532,863 @@ impl Drop for RegistryClient {
     }
 }
 
-fn group_instances_by_app(instances: Vec<Instance>) -> HashMap<String, Vec<Instance>> {
-    instances
-        .into_iter()
-        .group_by(|i| i.app.clone())
+/// Instances by app name, shared by the snapshots of the cache and the lookups
+///
+/// Each fetch parses all the instances again, borrowing their fields from the response, and
+/// yields the cached copy of the unchanged ones. Only the changed ones are copied, which
+/// [`AppCache::merge`] then compares with the cache.
+pub(crate) type Apps = HashMap<String, Vec<Arc<Instance>>>;
+
+/// Cached instances by app name, an immutable snapshot each fetch replaces as a whole
+///
+/// The lock is only held to swap the snapshot or take a reference to it, the lookups read
+/// their snapshot without it, while a fetch parses and replaces the registry.
+#[derive(Debug, Default)]
+pub(crate) struct AppCache {
+    apps: RwLock<Arc<Apps>>,
+}
+
+impl AppCache {
+    pub(crate) fn new(apps: Apps) -> Self {
+        AppCache {
+            apps: RwLock::new(Arc::new(apps)),
+        }
+    }
+
+    /// Current snapshot, unaffected by the later fetches
+    pub(crate) fn load(&self) -> Arc<Apps> {
+        Arc::clone(&read(&self.apps))
+    }
+
+    /// Replaces the snapshot with the fetched `apps`, unless they are the cached ones, keeping
+    /// the cached instances they didn't change. Returns the names of the apps whose instances
+    /// changed, sorted
+    ///
+    /// The instances only renewing their lease are unchanged, their lease stays the cached one.
+    /// The fetched apps are compared with the snapshot without the lock, only held to swap
+    /// it. They are compared again when the snapshot changed meanwhile, e.g. by an eviction.
+    pub(crate) fn merge(&self, mut apps: Apps) -> Vec<String> {
+        loop {
+            let snapshot = self.load();
+            let changed = reuse_unchanged(&snapshot, &mut apps);
+            let mut cached = write(&self.apps);
+            if Arc::ptr_eq(&*cached, &snapshot) {
+                if !changed.is_empty() {
+                    *cached = Arc::new(apps);
+                }
+                return changed;
+            }
+        }
+    }
+
+    /// Applies `change` to the snapshot, copied first when lookups still read it
+    pub(crate) fn update<R, F>(&self, change: F) -> R
+    where
+        F: FnOnce(&mut Apps) -> R,
+    {
+        change(Arc::make_mut(&mut write(&self.apps)))
+    }
+
+    /// Whether a thread panicked while changing the snapshot
+    fn is_poisoned(&self) -> bool {
+        self.apps.is_poisoned()
+    }
+}
+
+/// Replaces the instances of `apps` the `cached` ones didn't change with the cached ones,
+/// returns the names of the apps whose instances changed, sorted
+fn reuse_unchanged(cached: &Apps, apps: &mut Apps) -> Vec<String> {
+    let mut changed = Vec::new();
+    for (name, instances) in apps.iter_mut() {
+        let previous = cached.get(name).map_or(&[][..], Vec::as_slice);
+        // The servers list the instances in the same order from one fetch to the next,
+        // the instances are only looked up by id once that order changed
+        let mut by_id: Option<HashMap<&str, &Arc<Instance>>> = None;
+        let mut reused = 0;
+        for (i, instance) in instances.iter_mut().enumerate() {
+            let known = match previous.get(i) {
+                Some(known) if instance_id(known) == instance_id(instance) => Some(known),
+                _ => by_id
+                    .get_or_insert_with(|| {
+                        previous
+                            .iter()
+                            .map(|instance| (instance_id(instance), instance))
+                            .collect()
+                    })
+                    .get(instance_id(instance))
+                    .copied(),
+            };
+            if let Some(known) = known {
+                // Yielded from the cache by the fetch when unchanged
+                if Arc::ptr_eq(known, instance) || same_registration(known, instance) {
+                    *instance = Arc::clone(known);
+                    reused += 1;
+                }
+            }
+        }
+        // Only listed in another order, the instances are unchanged
+        if reused != instances.len() || instances.len() != previous.len() {
+            changed.push(name.clone());
+        }
+    }
+    changed.extend(
+        cached
+            .keys()
+            .filter(|name| !apps.contains_key(*name))
+            .cloned(),
+    );
+    changed.sort();
+    changed
+}
+
+/// Instance of the client, whose peers are the other UP instances of its app
+#[derive(Debug)]
+pub(crate) struct Peers {
+    app: String,
+    instance_id: String,
+    /// Ids of the peers at the last refresh, sorted
+    last: Mutex<Option<Vec<String>>>,
+}
+
+impl Peers {
+    pub(crate) fn new(app: &str, instance_id: &str) -> Self {
+        Peers {
+            app: app.to_string(),
+            instance_id: instance_id.to_string(),
+            last: Mutex::new(None),
+        }
+    }
+
+    fn of(&self, app_cache: &AppCache) -> Vec<Arc<Instance>> {
+        up_instances(app_cache, &self.app)
+            .into_iter()
+            .filter(|instance| instance_id(instance) != self.instance_id)
+            .collect()
+    }
+
+    /// Whether the instance is UP and its id is the lowest of the UP instances of the app
+    fn leads(&self, app_cache: &AppCache) -> bool {
+        let instances = up_instances(app_cache, &self.app);
+        let lowest = instances.iter().map(|instance| instance_id(instance)).min();
+        lowest == Some(self.instance_id.as_str())
+    }
+
+    /// Emits a `PeersChanged` event when the peers differ from the ones of the last refresh
+    fn update(&self, app_cache: &AppCache, events: &Events) {
+        let mut ids: Vec<String> = self
+            .of(app_cache)
+            .iter()
+            .map(|instance| instance_id(instance).to_string())
+            .collect();
+        ids.sort();
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        if last.as_ref() != Some(&ids) {
+            events.emit(EventKind::PeersChanged {
+                app: self.app.clone(),
+                peers: ids.clone(),
+            });
+            *last = Some(ids);
+        }
+    }
+}
+
+/// Instances added, removed and changed between two registry dumps
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RegistryDiff {
+    /// `app/instance id` of the instances only in the second dump
+    pub added: Vec<String>,
+    /// `app/instance id` of the instances only in the first dump
+    pub removed: Vec<String>,
+    pub changed: Vec<InstanceChange>,
+}
+
+/// Instance whose status, address or metadata differs between two registry dumps
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstanceChange {
+    /// `app/instance id`
+    pub instance: String,
+    /// Changed fields, e.g. `status: UP -> DOWN`
+    pub changes: Vec<String>,
+}
+
+impl RegistryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for RegistryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instance in &self.added {
+            writeln!(f, "+ {}", instance)?;
+        }
+        for instance in &self.removed {
+            writeln!(f, "- {}", instance)?;
+        }
+        for change in &self.changed {
+            writeln!(f, "~ {}: {}", change.instance, change.changes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Changes from the `before` to the `after` registry dump
+///
+/// Lease renewals and timestamps are ignored, they change on every heartbeat.
+pub fn diff(before: &Applications, after: &Applications) -> RegistryDiff {
+    let before = instances_by_key(before);
+    let after = instances_by_key(after);
+    let mut diff = RegistryDiff::default();
+    for (key, instance) in &after {
+        match before.get(key) {
+            None => diff.added.push(key.clone()),
+            Some(previous) => {
+                let changes = instance_changes(previous, instance);
+                if !changes.is_empty() {
+                    diff.changed.push(InstanceChange {
+                        instance: key.clone(),
+                        changes,
+                    });
+                }
+            }
+        }
+    }
+    diff.removed = before
+        .keys()
+        .filter(|key| !after.contains_key(*key))
+        .cloned()
+        .collect();
+    diff
+}
+
+/// Reads `lock`, recovering the data when a thread panicked while holding it
+pub(crate) fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Writes `lock`, recovering the data when a thread panicked while holding it
+pub(crate) fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Why `app` has no UP instance in `app_cache`, matched case-insensitively
+pub(crate) fn missing_instance(app_cache: &AppCache, app: &str) -> EurekaError {
+    let instances = app_instances(&app_cache.load(), app).map(Vec::len);
+    match instances {
+        Some(instances) => EurekaError::NoUpInstance {
+            app: app.to_string(),
+            instances,
+        },
+        None => EurekaError::AppNotFound(app.to_string()),
+    }
+}
+
+/// Whether `a` and `b` are the same registration, ignoring the lease renewals and timestamps
+/// which change on every heartbeat
+fn same_registration(a: &Instance, b: &Instance) -> bool {
+    // Destructured for a new field to be compared too
+    let Instance {
+        host_name,
+        instance_id,
+        app,
+        app_group_name,
+        asg_name,
+        sid,
+        ip_addr,
+        vip_address,
+        secure_vip_address,
+        status,
+        overridden_status,
+        port,
+        secure_port,
+        country_id,
+        home_page_url,
+        status_page_url,
+        health_check_url,
+        data_center_info,
+        lease_info: _,
+        metadata,
+        is_coordinating_discovery_server,
+        last_updated_timestamp: _,
+        last_dirty_timestamp: _,
+        action_type: _,
+    } = a;
+    *host_name == b.host_name
+        && *instance_id == b.instance_id
+        && *app == b.app
+        && *app_group_name == b.app_group_name
+        && *asg_name == b.asg_name
+        && *sid == b.sid
+        && *ip_addr == b.ip_addr
+        && *vip_address == b.vip_address
+        && *secure_vip_address == b.secure_vip_address
+        && *status == b.status
+        && *overridden_status == b.overridden_status
+        && *port == b.port
+        && *secure_port == b.secure_port
+        && *country_id == b.country_id
+        && *home_page_url == b.home_page_url
+        && *status_page_url == b.status_page_url
+        && *health_check_url == b.health_check_url
+        && *data_center_info == b.data_center_info
+        && *metadata == b.metadata
+        && *is_coordinating_discovery_server == b.is_coordinating_discovery_server
+}
+
+/// Whether `instance` has the host name or address of `local`
+fn is_same_host(local: &Instance, instance: &Instance) -> bool {
+    (!local.host_name.is_empty() && instance.host_name == local.host_name)
+        || (!local.ip_addr.is_empty() && instance.ip_addr == local.ip_addr)
+}
+
+/// Instance id of `instance`, its host name when it has none
+fn instance_id(instance: &Instance) -> &str {
+    instance
+        .instance_id
+        .as_deref()
+        .unwrap_or(&instance.host_name)
+}
+
+/// UP instances whose `vipAddress`, or `secureVipAddress` when `secure`, lists `vip` in
+/// its comma separated addresses, matched case-insensitively
+pub(crate) fn vip_instances(app_cache: &AppCache, vip: &str, secure: bool) -> Vec<Arc<Instance>> {
+    app_cache
+        .load()
+        .values()
+        .flatten()
+        .filter(|instance| instance.status == StatusType::Up)
+        .filter(|instance| {
+            let addresses = if secure {
+                &instance.secure_vip_address
+            } else {
+                &instance.vip_address
+            };
+            addresses
+                .split(',')
+                .any(|address| address.trim().eq_ignore_ascii_case(vip))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Instances of `app`, matched case-insensitively as every lookup of an app does
+pub(crate) fn app_instances<'a>(apps: &'a Apps, app: &str) -> Option<&'a Vec<Arc<Instance>>> {
+    // The registry names the apps in upper case, as they are usually looked up
+    apps.get(app).or_else(|| {
+        apps.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(app))
+            .map(|(_, instances)| instances)
+    })
+}
+
+/// UP instances of `app`, matched case-insensitively
+pub(crate) fn up_instances(app_cache: &AppCache, app: &str) -> Vec<Arc<Instance>> {
+    app_instances(&app_cache.load(), app)
         .into_iter()
-        .map(|(k, g)| (k, g.collect()))
+        .flatten()
+        .filter(|instance| instance.status == StatusType::Up)
+        .cloned()
         .collect()
 }
+
+fn instances_by_key(apps: &Applications) -> BTreeMap<String, &Instance> {
+    apps.applications
+        .iter()
+        .flat_map(|app| &app.instances)
+        .map(|instance| {
+            let id = instance.instance_id.as_ref().unwrap_or(&instance.host_name);
+            (format!("{}/{}", instance.app, id), instance)
+        })
+        .collect()
+}
+
+fn instance_changes(before: &Instance, after: &Instance) -> Vec<String> {
+    let mut changes = Vec::new();
+    let mut compare = |field: &str, before: String, after: String| {
+        if before != after {
+            changes.push(format!("{}: {} -> {}", field, before, after));
+        }
+    };
+    compare(
+        "status",
+        before.status.to_string(),
+        after.status.to_string(),
+    );
+    let overridden_status = |instance: &Instance| {
+        instance
+            .overridden_status
+            .map_or_else(|| "none".to_string(), |status| status.to_string())
+    };
+    compare(
+        "overriddenStatus",
+        overridden_status(before),
+        overridden_status(after),
+    );
+    compare(
+        "hostName",
+        before.host_name.clone(),
+        after.host_name.clone(),
+    );
+    compare("ipAddr", before.ip_addr.clone(), after.ip_addr.clone());
+    compare(
+        "port",
+        before.port.value.to_string(),
+        after.port.value.to_string(),
+    );
+    compare(
+        "securePort",
+        before.secure_port.value.to_string(),
+        after.secure_port.value.to_string(),
+    );
+    if before.metadata.as_ref().map(|m| &m.map) != after.metadata.as_ref().map(|m| &m.map) {
+        changes.push("metadata".to_string());
+    }
+    changes
+}
+
+/// `instances` by app name, in a map sized for `apps_hint` apps
+fn group_instances_by_app<I>(apps_hint: usize, instances: I) -> Apps
+where
+    I: IntoIterator<Item = Arc<Instance>>,
+{
+    let mut apps: Apps = HashMap::with_capacity(apps_hint);
+    for instance in instances {
+        apps.entry(instance.app.clone())
+            .or_insert_with(Vec::new)
+            .push(instance);
+    }
+    apps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::codec::{Codec, XmlCodec};
+    use crate::rest::InstanceIter;
+    use crate::test_util::{synthetic_applications, ReplayApi};
+    use crate::ParseError;
+
+    #[derive(Debug)]
+    struct MockApi {
+        malformed: bool,
+    }
+
+    impl EurekaApi for MockApi {
+        fn register(&self, _: &str, _: &Instance) -> Result<(), EurekaError> {
+            Ok(())
+        }
+
+        fn deregister(&self, _: &str, _: &str) -> Result<(), EurekaError> {
+            Ok(())
+        }
+
+        fn send_heartbeat(&self, _: &str, _: &str) -> Result<(), EurekaError> {
+            Ok(())
+        }
+
+        fn get_all_instances(&self) -> Result<Vec<Instance>, EurekaError> {
+            let mut up = Instance::default();
+            up.app = "UP_APP".to_string();
+            up.status = StatusType::Up;
+            let mut down = Instance::default();
+            down.app = "DOWN_APP".to_string();
+            down.status = StatusType::Down;
+            let mut v4 = Instance::default();
+            v4.app = "DUAL_APP".to_string();
+            v4.status = StatusType::Up;
+            v4.ip_addr = "10.0.0.1".to_string();
+            let mut v6 = v4.clone();
+            v6.ip_addr = "fd00::1".to_string();
+            v6.set_metadata_value(AppMetaDataType::ZONE, "us-east-1b");
+            Ok(vec![up, down, v4, v6])
+        }
+
+        fn stream_all_instances(&self) -> Result<InstanceIter, EurekaError> {
+            let mut instances: Vec<_> = self.get_all_instances()?.into_iter().map(Ok).collect();
+            if self.malformed {
+                let malformed = ParseError::new("malformed");
+                instances.insert(1, Err(EurekaError::ParseError(malformed)));
+            }
+            Ok(Box::new(instances.into_iter()))
+        }
+
+        fn get_instances_by_app(&self, _: &str) -> Result<Vec<Instance>, EurekaError> {
+            Ok(vec![])
+        }
+
+        fn get_instance_by_app_and_instance(
+            &self,
+            _: &str,
+            _: &str,
+        ) -> Result<Instance, EurekaError> {
+            Err(EurekaError::UnexpectedState("not found".to_string()))
+        }
+
+        fn update_status(&self, _: &str, _: &str, _: StatusType) -> Result<(), EurekaError> {
+            Ok(())
+        }
+
+        fn delete_status_override(
+            &self,
+            _: &str,
+            _: &str,
+            _: Option<StatusType>,
+        ) -> Result<(), EurekaError> {
+            Ok(())
+        }
+
+        fn update_metadata(&self, _: &str, _: &str, _: &str, _: &str) -> Result<(), EurekaError> {
+            Ok(())
+        }
+
+        fn update_asg_status(&self, _: &str, _: bool) -> Result<(), EurekaError> {
+            Ok(())
+        }
+
+        fn get_instances_by_vip_address(&self, _: &str) -> Result<Vec<Instance>, EurekaError> {
+            Ok(vec![])
+        }
+
+        fn get_instances_by_svip_address(&self, _: &str) -> Result<Vec<Instance>, EurekaError> {
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn test_update_app_cache() {
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false);
+        assert!(registry.is_stale(Duration::from_secs(90)));
+        registry.update_app_cache().unwrap();
+        assert!(!registry.is_stale(Duration::from_secs(90)));
+        assert_eq!(registry.generation(), 1);
+        assert_eq!(
+            registry.get_instance_by_app_name("UP_APP").unwrap().app,
+            "UP_APP"
+        );
+        // Lookups share the cached instance
+        let first = registry.get_instance_by_app_name("UP_APP").unwrap();
+        let second = registry.find_instance("UP_APP").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(registry.get_instance_by_app_name("DOWN_APP").is_none());
+        assert!(registry.get_instance_by_app_name("MISSING").is_none());
+
+        // Same registry, the cached instances and what is derived from them are kept
+        registry.update_app_cache().unwrap();
+        assert_eq!(registry.generation(), 1);
+        assert!(Arc::ptr_eq(
+            &first,
+            &registry.find_instance("UP_APP").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_merge() {
+        let instance = |id: &str, status: StatusType| {
+            let mut instance = Instance::default();
+            instance.instance_id = Some(id.to_string());
+            instance.status = status;
+            Arc::new(instance)
+        };
+        let apps = |instances: Vec<Arc<Instance>>| {
+            let mut apps = Apps::new();
+            apps.insert("APP".to_string(), instances);
+            apps
+        };
+        let app_cache = AppCache::default();
+        let a = instance("a", StatusType::Up);
+        let b = instance("b", StatusType::Up);
+        assert_eq!(app_cache.merge(apps(vec![a.clone(), b])), vec!["APP"]);
+
+        // Renewing its lease doesn't change an instance, the cached one is kept
+        let mut renewed = Instance::clone(&a);
+        renewed.lease_info = Some(crate::rest::structures::LeaseInfo::default());
+        let fetched = vec![Arc::new(renewed), instance("b", StatusType::Up)];
+        assert!(app_cache.merge(apps(fetched)).is_empty());
+        assert!(Arc::ptr_eq(&app_cache.load()["APP"][0], &a));
+
+        // Listed in another order, the instances are matched by id
+        let fetched = vec![instance("b", StatusType::Up), instance("a", StatusType::Up)];
+        assert!(app_cache.merge(apps(fetched)).is_empty());
+        let fetched = vec![
+            instance("b", StatusType::Down),
+            instance("a", StatusType::Up),
+        ];
+        assert_eq!(app_cache.merge(apps(fetched)), vec!["APP"]);
+        let cached = app_cache.load();
+        assert_eq!(cached["APP"][0].status, StatusType::Down);
+        assert!(Arc::ptr_eq(&cached["APP"][1], &a));
+
+        assert_eq!(app_cache.merge(Apps::new()), vec!["APP"]);
+        assert!(app_cache.load().is_empty());
+    }
+
+    #[test]
+    fn test_refresh_on_demand() {
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false);
+        let min_age = Duration::from_secs(5);
+        {
+            let _in_flight = registry.on_demand.lock().unwrap();
+            assert_eq!(registry.refresh_on_demand(min_age), Ok(false));
+        }
+        assert_eq!(registry.refresh_on_demand(min_age), Ok(true));
+        assert_eq!(registry.generation(), 1);
+        // Just refreshed
+        assert_eq!(registry.refresh_on_demand(min_age), Ok(false));
+    }
+
+    /// Budget of `benches/registry.rs`, a refresh of an unchanged registry of 10k instances
+    /// in less than 50 ms, checked with `cargo test --release -- --ignored refresh_budget`
+    #[test]
+    #[ignore]
+    fn test_refresh_budget() {
+        let dir = std::env::temp_dir().join(format!("eureka-budget-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let payload = XmlCodec
+            .encode_applications(&synthetic_applications(1000, 10))
+            .unwrap();
+        std::fs::write(dir.join("apps.xml"), payload).unwrap();
+        let registry = RegistryClient::new(Arc::new(ReplayApi::new(&dir)), false);
+        registry.update_app_cache().unwrap();
+
+        let started = Instant::now();
+        for _ in 0..10 {
+            registry.update_app_cache().unwrap();
+        }
+        let elapsed = started.elapsed() / 10;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(registry.generation(), 1);
+        assert!(
+            elapsed < Duration::from_millis(50),
+            "refresh took {:?}",
+            elapsed
+        );
+    }
+
+    #[test]
+    fn test_find_instance() {
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false)
+            .with_stale_after(Some(Duration::from_secs(90)));
+        match registry.find_instance("UP_APP") {
+            Err(EurekaError::StaleRegistry { age: None, .. }) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        registry.update_app_cache().unwrap();
+        assert_eq!(registry.find_instance("UP_APP").unwrap().app, "UP_APP");
+        assert_eq!(registry.find_instance("up_app").unwrap().app, "UP_APP");
+        match registry.find_instance("down_app") {
+            Err(EurekaError::NoUpInstance { instances: 1, .. }) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        match registry.find_instance("DOWN_APP") {
+            Err(EurekaError::NoUpInstance { instances: 1, .. }) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        match registry.find_instance("MISSING") {
+            Err(EurekaError::AppNotFound(ref app)) if app == "MISSING" => {}
+            result => panic!("unexpected {:?}", result),
+        }
+
+        let instance = registry
+            .find_instance_by_vip("Eureka-Client", true)
+            .unwrap();
+        assert_eq!(instance.status, StatusType::Up);
+        match registry.find_instance_by_vip("orders", false) {
+            Err(EurekaError::VipNotFound(ref vip)) if vip == "orders" => {}
+            result => panic!("unexpected {:?}", result),
+        }
+
+        let snapshot = registry.app_cache().load();
+        assert!(registry.evict_instance("UP_APP", "localhost"));
+        assert!(!registry.evict_instance("UP_APP", "localhost"));
+        // Lookups keep reading the snapshot they started with
+        assert_eq!(snapshot["UP_APP"].len(), 1);
+        match registry.find_instance("UP_APP") {
+            Err(EurekaError::NoUpInstance { instances: 0, .. }) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_poisoned_cache() {
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false);
+        registry.update_app_cache().unwrap();
+        let app_cache = registry.app_cache();
+        let _ = thread::spawn(move || {
+            app_cache.update(|_| panic!("poisoning the registry cache"));
+        })
+        .join();
+
+        assert!(registry.is_degraded());
+        assert!(registry.get_instance_by_app_name("UP_APP").is_some());
+        registry.update_app_cache().unwrap();
+        let mut report = HealthReport::default();
+        registry.report_health(&mut report);
+        assert!(report.registry_degraded);
+    }
+
+    #[test]
+    fn test_ip_preference() {
+        for &(preference, ip_addr) in &[
+            (IpPreference::PreferV4, "10.0.0.1"),
+            (IpPreference::PreferV6, "fd00::1"),
+        ] {
+            let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false)
+                .with_ip_preference(preference);
+            registry.update_app_cache().unwrap();
+            for _ in 0..10 {
+                let instance = registry.get_instance_by_app_name("DUAL_APP").unwrap();
+                assert_eq!(instance.ip_addr, ip_addr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_local_zone() {
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false)
+            .with_local_zone(Some("us-east-1b".to_string()));
+        registry.update_app_cache().unwrap();
+        for _ in 0..10 {
+            let instance = registry.get_instance_by_app_name("DUAL_APP").unwrap();
+            assert_eq!(instance.ip_addr, "fd00::1");
+        }
+        // No instance in the zone, fall back to the others
+        assert!(registry.get_instance_by_app_name("UP_APP").is_some());
+    }
+
+    #[test]
+    fn test_affinity() {
+        let mut local = Instance::default();
+        local.host_name = "other".to_string();
+        local.ip_addr = "10.0.0.1".to_string();
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false)
+            .with_local_zone(Some("us-east-1b".to_string()))
+            .with_local_instance(Some(local.clone()));
+        registry.update_app_cache().unwrap();
+        for _ in 0..10 {
+            let instance = registry.get_instance_by_app_name("DUAL_APP").unwrap();
+            assert_eq!(instance.ip_addr, "10.0.0.1");
+        }
+
+        // Not on the host, fall back to the zone
+        local.ip_addr = "10.0.0.2".to_string();
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false)
+            .with_local_zone(Some("us-east-1b".to_string()))
+            .with_local_instance(Some(local));
+        registry.update_app_cache().unwrap();
+        for _ in 0..10 {
+            let instance = registry.get_instance_by_app_name("DUAL_APP").unwrap();
+            assert_eq!(instance.ip_addr, "fd00::1");
+        }
+    }
+
+    #[test]
+    fn test_report_health() {
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: true }), false);
+        let mut report = HealthReport::default();
+        registry.report_health(&mut report);
+        assert_eq!(report.registry_age, None);
+        assert!(!report.eureka_reachable);
+
+        assert!(registry.update_app_cache().is_err());
+        registry.report_health(&mut report);
+        assert!(report.last_registry_error.is_some());
+        assert!(!report.eureka_reachable);
+
+        let events = Events::default();
+        let (tx, rx) = std::sync::mpsc::channel();
+        events.set_sender(tx);
+        let registry =
+            RegistryClient::new(Arc::new(MockApi { malformed: false }), false).with_events(events);
+        registry.update_app_cache().unwrap();
+        assert_eq!(
+            rx.try_recv().unwrap().kind,
+            EventKind::CacheRefreshed {
+                applications: 3,
+                instances: 4,
+                changed_apps: vec![
+                    "DOWN_APP".to_string(),
+                    "DUAL_APP".to_string(),
+                    "UP_APP".to_string(),
+                ],
+            }
+        );
+        registry.report_health(&mut report);
+        assert!(report.registry_age.is_some());
+        assert_eq!(report.last_registry_error, None);
+        assert!(report.eureka_reachable);
+    }
+
+    #[test]
+    fn test_peers() {
+        let events = Events::default();
+        let (tx, rx) = std::sync::mpsc::channel();
+        events.set_sender(tx);
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false)
+            .with_events(events)
+            .with_peers(Some(Peers::new("up_app", "other")));
+        assert!(registry.peers().is_empty());
+        registry.update_app_cache().unwrap();
+        assert_eq!(registry.peers().len(), 1);
+        let kinds: Vec<EventKind> = rx.try_iter().map(|event| event.kind).collect();
+        assert_eq!(
+            kinds[1],
+            EventKind::PeersChanged {
+                app: "up_app".to_string(),
+                peers: vec!["localhost".to_string()],
+            }
+        );
+        // Same peers, no event
+        registry.update_app_cache().unwrap();
+        assert_eq!(rx.try_iter().count(), 1);
+
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false)
+            .with_peers(Some(Peers::new("UP_APP", "localhost")));
+        registry.update_app_cache().unwrap();
+        assert!(registry.peers().is_empty());
+    }
+
+    #[test]
+    fn test_dump_and_diff() {
+        let registry = RegistryClient::new(Arc::new(MockApi { malformed: false }), false);
+        registry.update_app_cache().unwrap();
+        let before = registry.dump();
+        let names: Vec<_> = before.applications.iter().map(|app| &app.name).collect();
+        assert_eq!(names, vec!["DOWN_APP", "DUAL_APP", "UP_APP"]);
+        assert!(diff(&before, &registry.dump()).is_empty());
+
+        let mut after = registry.dump();
+        after.applications.remove(0);
+        let mut added = Instance::default();
+        added.app = "NEW_APP".to_string();
+        added.instance_id = Some("new-1".to_string());
+        after.applications.push(Application {
+            name: "NEW_APP".to_string(),
+            instances: vec![added],
+        });
+        after.applications[1].instances[0].status = StatusType::Down;
+        let diff = diff(&before, &after);
+        assert_eq!(diff.added, vec!["NEW_APP/new-1"]);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.changed.len(), 1);
+        assert!(diff.changed[0].instance.starts_with("UP_APP/"));
+        assert_eq!(diff.changed[0].changes, vec!["status: UP -> DOWN"]);
+    }
+
+    #[test]
+    fn test_lenient_parsing() {
+        let strict = RegistryClient::new(Arc::new(MockApi { malformed: true }), false);
+        assert!(strict.update_app_cache().is_err());
+        assert!(strict.get_instance_by_app_name("UP_APP").is_none());
+
+        let lenient = RegistryClient::new(Arc::new(MockApi { malformed: true }), true);
+        lenient.update_app_cache().unwrap();
+        assert!(lenient.get_instance_by_app_name("UP_APP").is_some());
+        assert_eq!(
+            lenient.last_parse_report(),
+            ParseReport {
+                parsed: 4,
+                skipped: vec!["malformed".to_string()],
+            }
+        );
+    }
+}
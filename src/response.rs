@@ -0,0 +1,100 @@
+//! Responses of the apps called with [`EurekaClient::call`](crate::EurekaClient::call)
+
+use std::io::Read;
+
+use reqwest::header::HeaderMap;
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+
+use crate::rest::codec::json_error;
+use crate::{EurekaError, Operation};
+
+/// Status, headers and JSON body of a response
+#[derive(Debug, Clone)]
+pub struct CallResponse<T> {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: T,
+}
+
+/// Error body of the calls which don't decode error responses, never read
+#[derive(Debug, Deserialize)]
+enum NoErrorBody {}
+
+/// Response whose body is the JSON `R` of a 2xx status, or the JSON `E` of an error status
+///
+/// An error body which isn't an `E` fails with the status and text of the body.
+pub(crate) fn read<R, E>(mut resp: Response) -> Result<CallResponse<Result<R, E>>, EurekaError>
+where
+    R: DeserializeOwned,
+    E: DeserializeOwned,
+{
+    let url = resp.url().to_string();
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let mut body = Vec::new();
+    resp.read_to_end(&mut body)
+        .map_err(|e| EurekaError::Io(e).context(Operation::Call, &url))?;
+    let body = if status.is_success() {
+        Ok(decode(&body).map_err(|e| e.context(Operation::Call, &url))?)
+    } else {
+        match decode(&body) {
+            Ok(error) => Err(error),
+            Err(_) => {
+                let body = String::from_utf8_lossy(&body).into_owned();
+                let error = EurekaError::RequestDetailed {
+                    status,
+                    url: url.clone(),
+                    body,
+                };
+                return Err(error.context(Operation::Call, &url));
+            }
+        }
+    };
+    Ok(CallResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+/// Response whose body is the JSON `R` of a 2xx status, error statuses failing
+pub(crate) fn read_success<R: DeserializeOwned>(
+    resp: Response,
+) -> Result<CallResponse<R>, EurekaError> {
+    let resp = read::<R, NoErrorBody>(resp)?;
+    let body = match resp.body {
+        Ok(body) => body,
+        Err(never) => match never {},
+    };
+    Ok(CallResponse {
+        status: resp.status,
+        headers: resp.headers,
+        body,
+    })
+}
+
+/// JSON `body`, `null` when it is empty so that `()` and `Option` read 204 responses
+fn decode<T: DeserializeOwned>(body: &[u8]) -> Result<T, EurekaError> {
+    let body = if body.iter().all(u8::is_ascii_whitespace) {
+        &b"null"[..]
+    } else {
+        body
+    };
+    serde_json::from_slice(body).map_err(|e| json_error(body, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        decode::<()>(b"").unwrap();
+        assert_eq!(decode::<Option<u32>>(b" \n").unwrap(), None);
+        assert_eq!(decode::<Vec<u32>>(b"[1, 2]").unwrap(), vec![1, 2]);
+        assert!(decode::<NoErrorBody>(b"{\"error\": \"not found\"}").is_err());
+        let error = decode::<u32>(b"\"one\"").unwrap_err();
+        assert!(matches!(error, EurekaError::ParseError(_)));
+    }
+}
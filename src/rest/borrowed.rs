@@ -0,0 +1,392 @@
+//! Borrowed variants of the parse structures, for the registry fetch path
+//!
+//! Their text fields borrow from the document unless they had to be unescaped, so an
+//! instance is only copied into an owned [`Instance`] once it differs from the cached one.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use strong_xml::xmlparser::{ElementEnd, Token};
+use strong_xml::{XmlRead, XmlReader, XmlResult};
+
+use super::structures::{
+    xml_unescape, ActionType, AmazonMetaDataType, AppMetaDataType, DataCenterInfo, DcNameType,
+    Instance, LeaseInfo, PortData, SecurePort, StatusType,
+};
+
+#[derive(XmlRead, Debug)]
+#[xml(tag = "instance")]
+pub(crate) struct InstanceRef<'a> {
+    #[xml(flatten_text = "hostName")]
+    pub host_name: Cow<'a, str>,
+    #[xml(flatten_text = "instanceId")]
+    pub instance_id: Option<Cow<'a, str>>,
+    #[xml(flatten_text = "app")]
+    pub app: Cow<'a, str>,
+    #[xml(flatten_text = "appGroupName")]
+    pub app_group_name: Option<Cow<'a, str>>,
+    #[xml(flatten_text = "asgName")]
+    pub asg_name: Option<Cow<'a, str>>,
+    #[xml(flatten_text = "sid")]
+    pub sid: Option<Cow<'a, str>>,
+    #[xml(flatten_text = "ipAddr")]
+    pub ip_addr: Cow<'a, str>,
+    #[xml(flatten_text = "vipAddress")]
+    pub vip_address: Cow<'a, str>,
+    #[xml(flatten_text = "secureVipAddress")]
+    pub secure_vip_address: Cow<'a, str>,
+    #[xml(flatten_text = "status")]
+    pub status: StatusType,
+    #[xml(flatten_text = "overriddenstatus")]
+    pub overridden_status: Option<StatusType>,
+    #[xml(child = "port")]
+    pub port: PortData,
+    #[xml(child = "securePort")]
+    pub secure_port: SecurePort,
+    #[xml(flatten_text = "countryId")]
+    pub country_id: Option<u32>,
+    #[xml(flatten_text = "homePageUrl")]
+    pub home_page_url: Cow<'a, str>,
+    #[xml(flatten_text = "statusPageUrl")]
+    pub status_page_url: Cow<'a, str>,
+    #[xml(flatten_text = "healthCheckUrl")]
+    pub health_check_url: Cow<'a, str>,
+    #[xml(child = "dataCenterInfo")]
+    pub data_center_info: DataCenterInfoRef<'a>,
+    #[xml(child = "leaseInfo")]
+    pub lease_info: Option<LeaseInfo>,
+    #[xml(child = "metadata")]
+    pub metadata: Option<AppMetaDataRef<'a>>,
+    #[xml(flatten_text = "isCoordinatingDiscoveryServer")]
+    pub is_coordinating_discovery_server: Option<bool>,
+    #[xml(flatten_text = "lastUpdatedTimestamp")]
+    pub last_updated_timestamp: Option<u64>,
+    #[xml(flatten_text = "lastDirtyTimestamp")]
+    pub last_dirty_timestamp: Option<u64>,
+    #[xml(flatten_text = "actionType")]
+    pub action_type: Option<ActionType>,
+}
+
+impl InstanceRef<'_> {
+    /// Instance id, the host name when it has none
+    pub fn id(&self) -> &str {
+        self.instance_id.as_deref().unwrap_or(&self.host_name)
+    }
+
+    /// Whether `known` has the same registration, all the fields but the lease and
+    /// timestamps, as the registry merge compares instances
+    pub fn same_registration(&self, known: &Instance) -> bool {
+        // Destructured for a new field to be compared too
+        let InstanceRef {
+            host_name,
+            instance_id,
+            app,
+            app_group_name,
+            asg_name,
+            sid,
+            ip_addr,
+            vip_address,
+            secure_vip_address,
+            status,
+            overridden_status,
+            port,
+            secure_port,
+            country_id,
+            home_page_url,
+            status_page_url,
+            health_check_url,
+            data_center_info,
+            lease_info: _,
+            metadata,
+            is_coordinating_discovery_server,
+            last_updated_timestamp: _,
+            last_dirty_timestamp: _,
+            action_type: _,
+        } = self;
+        *host_name == known.host_name
+            && instance_id.as_deref() == known.instance_id.as_deref()
+            && *app == known.app
+            && app_group_name.as_deref() == known.app_group_name.as_deref()
+            && asg_name.as_deref() == known.asg_name.as_deref()
+            && sid.as_deref() == known.sid.as_deref()
+            && *ip_addr == known.ip_addr
+            && *vip_address == known.vip_address
+            && *secure_vip_address == known.secure_vip_address
+            && *status == known.status
+            && *overridden_status == known.overridden_status
+            && *port == known.port
+            && *secure_port == known.secure_port
+            && *country_id == known.country_id
+            && *home_page_url == known.home_page_url
+            && *status_page_url == known.status_page_url
+            && *health_check_url == known.health_check_url
+            && data_center_info.eq_owned(&known.data_center_info)
+            && match (metadata, &known.metadata) {
+                (Some(metadata), Some(known)) => metadata.eq_owned(known),
+                (None, None) => true,
+                _ => false,
+            }
+            && *is_coordinating_discovery_server == known.is_coordinating_discovery_server
+    }
+
+    pub fn into_owned(self) -> Instance {
+        let InstanceRef {
+            host_name,
+            instance_id,
+            app,
+            app_group_name,
+            asg_name,
+            sid,
+            ip_addr,
+            vip_address,
+            secure_vip_address,
+            status,
+            overridden_status,
+            port,
+            secure_port,
+            country_id,
+            home_page_url,
+            status_page_url,
+            health_check_url,
+            data_center_info,
+            lease_info,
+            metadata,
+            is_coordinating_discovery_server,
+            last_updated_timestamp,
+            last_dirty_timestamp,
+            action_type,
+        } = self;
+        Instance {
+            host_name: host_name.into_owned(),
+            instance_id: instance_id.map(Cow::into_owned),
+            app: app.into_owned(),
+            app_group_name: app_group_name.map(Cow::into_owned),
+            asg_name: asg_name.map(Cow::into_owned),
+            sid: sid.map(Cow::into_owned),
+            ip_addr: ip_addr.into_owned(),
+            vip_address: vip_address.into_owned(),
+            secure_vip_address: secure_vip_address.into_owned(),
+            status,
+            overridden_status,
+            port,
+            secure_port,
+            country_id,
+            home_page_url: home_page_url.into_owned(),
+            status_page_url: status_page_url.into_owned(),
+            health_check_url: health_check_url.into_owned(),
+            data_center_info: data_center_info.into_owned(),
+            lease_info,
+            metadata: metadata.map(AppMetaDataRef::into_owned),
+            is_coordinating_discovery_server,
+            last_updated_timestamp,
+            last_dirty_timestamp,
+            action_type,
+        }
+    }
+}
+
+#[derive(XmlRead, Debug)]
+#[xml(tag = "dataCenterInfo")]
+pub(crate) struct DataCenterInfoRef<'a> {
+    #[xml(attr = "class")]
+    pub class: Option<Cow<'a, str>>,
+    #[xml(flatten_text = "name")]
+    pub name: DcNameType,
+    #[xml(child = "metadata")]
+    pub metadata: Option<AmazonMetaDataRef<'a>>,
+}
+
+impl DataCenterInfoRef<'_> {
+    fn eq_owned(&self, known: &DataCenterInfo) -> bool {
+        self.class.as_deref() == known.class.as_deref()
+            && self.name == known.name
+            && match (&self.metadata, &known.metadata) {
+                (Some(metadata), Some(known)) => metadata.eq_owned(known),
+                (None, None) => true,
+                _ => false,
+            }
+    }
+
+    fn into_owned(self) -> DataCenterInfo {
+        DataCenterInfo {
+            class: self.class.map(Cow::into_owned),
+            name: self.name,
+            metadata: self.metadata.map(AmazonMetaDataRef::into_owned),
+        }
+    }
+}
+
+#[derive(XmlRead, Debug)]
+#[xml(tag = "metadata")]
+pub(crate) struct AmazonMetaDataRef<'a> {
+    #[xml(flatten_text = "ami-launch-index")]
+    pub ami_launch_index: Cow<'a, str>,
+    #[xml(flatten_text = "local-hostname")]
+    pub local_hostname: Cow<'a, str>,
+    #[xml(flatten_text = "availability-zone")]
+    pub availability_zone: Cow<'a, str>,
+    #[xml(flatten_text = "instance-id")]
+    pub instance_id: Cow<'a, str>,
+    #[xml(flatten_text = "public-ipv4")]
+    pub public_ipv4: Cow<'a, str>,
+    #[xml(flatten_text = "public-hostname")]
+    pub public_hostname: Cow<'a, str>,
+    #[xml(flatten_text = "ami-manifest-patch")]
+    pub ami_manifest_patch: Cow<'a, str>,
+    #[xml(flatten_text = "local-ipv4")]
+    pub local_ipv4: Cow<'a, str>,
+    #[xml(flatten_text = "hostname")]
+    pub hostname: Cow<'a, str>,
+    #[xml(flatten_text = "ami-id")]
+    pub ami_id: Cow<'a, str>,
+    #[xml(flatten_text = "instance-type")]
+    pub instance_type: Cow<'a, str>,
+}
+
+impl AmazonMetaDataRef<'_> {
+    fn eq_owned(&self, known: &AmazonMetaDataType) -> bool {
+        let AmazonMetaDataRef {
+            ami_launch_index,
+            local_hostname,
+            availability_zone,
+            instance_id,
+            public_ipv4,
+            public_hostname,
+            ami_manifest_patch,
+            local_ipv4,
+            hostname,
+            ami_id,
+            instance_type,
+        } = self;
+        *ami_launch_index == known.ami_launch_index
+            && *local_hostname == known.local_hostname
+            && *availability_zone == known.availability_zone
+            && *instance_id == known.instance_id
+            && *public_ipv4 == known.public_ipv4
+            && *public_hostname == known.public_hostname
+            && *ami_manifest_patch == known.ami_manifest_patch
+            && *local_ipv4 == known.local_ipv4
+            && *hostname == known.hostname
+            && *ami_id == known.ami_id
+            && *instance_type == known.instance_type
+    }
+
+    fn into_owned(self) -> AmazonMetaDataType {
+        AmazonMetaDataType {
+            ami_launch_index: self.ami_launch_index.into_owned(),
+            local_hostname: self.local_hostname.into_owned(),
+            availability_zone: self.availability_zone.into_owned(),
+            instance_id: self.instance_id.into_owned(),
+            public_ipv4: self.public_ipv4.into_owned(),
+            public_hostname: self.public_hostname.into_owned(),
+            ami_manifest_patch: self.ami_manifest_patch.into_owned(),
+            local_ipv4: self.local_ipv4.into_owned(),
+            hostname: self.hostname.into_owned(),
+            ami_id: self.ami_id.into_owned(),
+            instance_type: self.instance_type.into_owned(),
+        }
+    }
+}
+
+/// Metadata whose keys and values borrow from the document, parsed as [`AppMetaDataType`]
+#[derive(Default, Debug)]
+pub(crate) struct AppMetaDataRef<'a> {
+    pub class: Option<Cow<'a, str>>,
+    pub map: HashMap<&'a str, Cow<'a, str>>,
+}
+
+impl AppMetaDataRef<'_> {
+    fn eq_owned(&self, known: &AppMetaDataType) -> bool {
+        self.class.as_deref() == known.class.as_deref()
+            && self.map.len() == known.map.len()
+            && self
+                .map
+                .iter()
+                .all(|(key, value)| known.get(key) == Some(&**value))
+    }
+
+    pub fn into_owned(self) -> AppMetaDataType {
+        AppMetaDataType {
+            class: self.class.map(Cow::into_owned),
+            map: self
+                .map
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.into_owned()))
+                .collect(),
+        }
+    }
+}
+
+impl<'a> XmlRead<'a> for AppMetaDataRef<'a> {
+    fn from_reader(reader: &mut XmlReader<'a>) -> XmlResult<Self> {
+        let mut metadata = Self::default();
+        reader.read_till_element_start(AppMetaDataType::TAG)?;
+
+        // attr class
+        while let Some((attr_name, attr_val)) = reader.find_attribute()? {
+            if attr_name.eq("class") {
+                metadata.class = Some(attr_val.into());
+            }
+        }
+
+        // end?
+        let next = reader.next().unwrap()?;
+        if let Token::ElementEnd {
+            end: ElementEnd::Empty,
+            ..
+        } = next
+        {
+            return Ok(metadata);
+        }
+
+        // child, text is unescaped and CDATA sections are taken verbatim, values split
+        // across several of them are the only ones copied
+        let mut child: Option<(&'a str, Cow<'a, str>)> = None;
+        while let Some(token) = reader.next() {
+            match token? {
+                Token::ElementStart { local, .. } => {
+                    child = Some((local.as_str(), Cow::Borrowed("")));
+                }
+                Token::Text { text } => {
+                    if let Some((_, value)) = child.as_mut() {
+                        append(value, xml_unescape(text.as_str()));
+                    }
+                }
+                Token::Cdata { text, .. } => {
+                    if let Some((_, value)) = child.as_mut() {
+                        append(value, Cow::Borrowed(text.as_str()));
+                    }
+                }
+                Token::ElementEnd {
+                    end: ElementEnd::Empty,
+                    ..
+                } => {
+                    if let Some((key, value)) = child.take() {
+                        metadata.map.insert(key, value);
+                    }
+                }
+                Token::ElementEnd {
+                    end: ElementEnd::Close(_, local),
+                    ..
+                } => match child.take() {
+                    Some((key, value)) => {
+                        metadata.map.insert(key, value);
+                    }
+                    None if local.as_str() == AppMetaDataType::TAG => break,
+                    None => {}
+                },
+                _ => {}
+            }
+        }
+
+        Ok(metadata)
+    }
+}
+
+fn append<'a>(value: &mut Cow<'a, str>, text: Cow<'a, str>) {
+    if value.is_empty() {
+        *value = text;
+    } else {
+        value.to_mut().push_str(&text);
+    }
+}
@@ -0,0 +1,348 @@
+//! Builder of the registered instance
+
+use std::collections::HashMap;
+
+use super::structures::*;
+use crate::{host_port, EurekaError};
+
+/// Builds an [`Instance`], deriving the home page, status page and health check
+/// urls from host, port and context path when they are not set explicitly.
+#[derive(Debug, Clone)]
+pub struct InstanceBuilder {
+    app: String,
+    instance_id: Option<String>,
+    host_name: String,
+    ip_addr: String,
+    prefer_ip_address: bool,
+    port: PortData,
+    secure_port: SecurePort,
+    vip_address: Option<String>,
+    secure_vip_address: Option<String>,
+    status: StatusType,
+    context_path: String,
+    home_page_url: Option<String>,
+    status_page_path: String,
+    status_page_url: Option<String>,
+    health_check_path: String,
+    health_check_url: Option<String>,
+    data_center_info: DataCenterInfo,
+    lease_info: Option<LeaseInfo>,
+    metadata: HashMap<String, String>,
+}
+
+impl Default for InstanceBuilder {
+    fn default() -> Self {
+        let instance = Instance::default();
+        InstanceBuilder {
+            app: instance.app,
+            instance_id: None,
+            host_name: instance.host_name,
+            ip_addr: instance.ip_addr,
+            prefer_ip_address: false,
+            port: instance.port,
+            secure_port: instance.secure_port,
+            vip_address: None,
+            secure_vip_address: None,
+            status: instance.status,
+            context_path: "".to_string(),
+            home_page_url: None,
+            status_page_path: "/actuator/info".to_string(),
+            status_page_url: None,
+            health_check_path: "/actuator/health".to_string(),
+            health_check_url: None,
+            data_center_info: instance.data_center_info,
+            lease_info: None,
+            metadata: HashMap::new(),
+        }
+    }
+}
+
+impl InstanceBuilder {
+    pub fn new(app: &str) -> Self {
+        InstanceBuilder {
+            app: app.to_string(),
+            ..InstanceBuilder::default()
+        }
+    }
+
+    pub fn app(mut self, app: &str) -> Self {
+        self.app = app.to_string();
+        self
+    }
+
+    pub fn instance_id(mut self, instance_id: &str) -> Self {
+        self.instance_id = Some(instance_id.to_string());
+        self
+    }
+
+    pub fn host_name(mut self, host_name: &str) -> Self {
+        self.host_name = host_name.to_string();
+        self
+    }
+
+    pub fn ip_addr(mut self, ip_addr: &str) -> Self {
+        self.ip_addr = ip_addr.to_string();
+        self
+    }
+
+    /// Use the ip address instead of the host name in the derived urls
+    pub fn prefer_ip_address(mut self, prefer_ip_address: bool) -> Self {
+        self.prefer_ip_address = prefer_ip_address;
+        self
+    }
+
+    pub fn port(mut self, port: u16, enabled: bool) -> Self {
+        self.port = PortData::new(port, enabled);
+        self
+    }
+
+    pub fn secure_port(mut self, port: u16, enabled: bool) -> Self {
+        self.secure_port = SecurePort::new(port, enabled);
+        self
+    }
+
+    pub fn vip_address(mut self, vip_address: &str) -> Self {
+        self.vip_address = Some(vip_address.to_string());
+        self
+    }
+
+    pub fn secure_vip_address(mut self, secure_vip_address: &str) -> Self {
+        self.secure_vip_address = Some(secure_vip_address.to_string());
+        self
+    }
+
+    pub fn status(mut self, status: StatusType) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Context path prepended to the derived urls, e.g. `/api`
+    pub fn context_path(mut self, context_path: &str) -> Self {
+        self.context_path = context_path.to_string();
+        self
+    }
+
+    pub fn home_page_url(mut self, url: &str) -> Self {
+        self.home_page_url = Some(url.to_string());
+        self
+    }
+
+    /// Path of the status page, default `/actuator/info`
+    pub fn status_page_path(mut self, path: &str) -> Self {
+        self.status_page_path = path.to_string();
+        self
+    }
+
+    pub fn status_page_url(mut self, url: &str) -> Self {
+        self.status_page_url = Some(url.to_string());
+        self
+    }
+
+    /// Path of the health check, default `/actuator/health`
+    pub fn health_check_path(mut self, path: &str) -> Self {
+        self.health_check_path = path.to_string();
+        self
+    }
+
+    pub fn health_check_url(mut self, url: &str) -> Self {
+        self.health_check_url = Some(url.to_string());
+        self
+    }
+
+    pub fn data_center_info(mut self, data_center_info: DataCenterInfo) -> Self {
+        self.data_center_info = data_center_info;
+        self
+    }
+
+    pub fn lease_info(mut self, lease_info: LeaseInfo) -> Self {
+        self.lease_info = Some(lease_info);
+        self
+    }
+
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    fn validate(&self) -> Result<(), EurekaError> {
+        if self.app.is_empty() {
+            return Err(EurekaError::InvalidConfig(
+                "app",
+                "must not be empty".to_string(),
+            ));
+        }
+        if !self.port.enabled && !self.secure_port.enabled {
+            return Err(EurekaError::InvalidConfig(
+                "port",
+                "either port or securePort must be enabled".to_string(),
+            ));
+        }
+        if self.port.enabled && self.port.value == 0 {
+            return Err(EurekaError::InvalidConfig(
+                "port",
+                "enabled port must not be 0".to_string(),
+            ));
+        }
+        if self.secure_port.enabled && self.secure_port.value == 0 {
+            return Err(EurekaError::InvalidConfig(
+                "securePort",
+                "enabled secure port must not be 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Base url of the instance, secure when the secure port is enabled
+    fn base_url(&self) -> String {
+        let host = if self.prefer_ip_address {
+            &self.ip_addr
+        } else {
+            &self.host_name
+        };
+        let (scheme, port) = if self.secure_port.enabled {
+            ("https", self.secure_port.value)
+        } else {
+            ("http", self.port.value)
+        };
+        let context_path = self.context_path.trim_matches('/');
+        if context_path.is_empty() {
+            format!("{}://{}", scheme, host_port(host, port))
+        } else {
+            format!("{}://{}/{}", scheme, host_port(host, port), context_path)
+        }
+    }
+
+    pub fn build(self) -> Result<Instance, EurekaError> {
+        self.validate()?;
+        let base_url = self.base_url();
+        let InstanceBuilder {
+            app,
+            instance_id,
+            host_name,
+            ip_addr,
+            port,
+            secure_port,
+            vip_address,
+            secure_vip_address,
+            status,
+            home_page_url,
+            status_page_path,
+            status_page_url,
+            health_check_path,
+            health_check_url,
+            data_center_info,
+            lease_info,
+            metadata,
+            ..
+        } = self;
+
+        let url_of = |path: &str| format!("{}/{}", base_url, path.trim_start_matches('/'));
+        let home_page_url = home_page_url.unwrap_or_else(|| url_of("/"));
+        let status_page_url = status_page_url.unwrap_or_else(|| url_of(&status_page_path));
+        let health_check_url = health_check_url.unwrap_or_else(|| url_of(&health_check_path));
+        let port_value = if secure_port.enabled {
+            secure_port.value
+        } else {
+            port.value
+        };
+        let instance_id =
+            instance_id.unwrap_or_else(|| format!("{}:{}:{}", host_name, app, port_value));
+        let vip_address = vip_address.unwrap_or_else(|| app.clone());
+        let secure_vip_address = secure_vip_address.unwrap_or_else(|| vip_address.clone());
+        let metadata = if metadata.is_empty() {
+            None
+        } else {
+            Some(AppMetaDataType {
+                class: None,
+                map: metadata,
+            })
+        };
+        Ok(Instance {
+            host_name,
+            instance_id: Some(instance_id),
+            app,
+            ip_addr,
+            vip_address,
+            secure_vip_address,
+            status,
+            port,
+            secure_port,
+            home_page_url,
+            status_page_url,
+            health_check_url,
+            data_center_info,
+            lease_info,
+            metadata,
+            ..Instance::default()
+        })
+    }
+}
+
+impl Instance {
+    pub fn builder() -> InstanceBuilder {
+        InstanceBuilder::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_urls() {
+        let instance = Instance::builder()
+            .app("BENCH")
+            .host_name("bench.local")
+            .port(8080, true)
+            .context_path("/api/")
+            .build()
+            .unwrap();
+        assert_eq!(instance.home_page_url, "http://bench.local:8080/api/");
+        assert_eq!(
+            instance.status_page_url,
+            "http://bench.local:8080/api/actuator/info"
+        );
+        assert_eq!(
+            instance.health_check_url,
+            "http://bench.local:8080/api/actuator/health"
+        );
+        assert_eq!(
+            instance.instance_id,
+            Some("bench.local:BENCH:8080".to_string())
+        );
+        assert_eq!(instance.vip_address, "BENCH");
+
+        let instance = Instance::builder()
+            .ip_addr("10.0.0.1")
+            .prefer_ip_address(true)
+            .port(8080, false)
+            .secure_port(8443, true)
+            .health_check_url("http://10.0.0.1:9090/health")
+            .build()
+            .unwrap();
+        assert_eq!(
+            instance.status_page_url,
+            "https://10.0.0.1:8443/actuator/info"
+        );
+        assert_eq!(instance.health_check_url, "http://10.0.0.1:9090/health");
+
+        let instance = Instance::builder()
+            .ip_addr("fd00::1")
+            .prefer_ip_address(true)
+            .port(8080, true)
+            .build()
+            .unwrap();
+        assert_eq!(instance.home_page_url, "http://[fd00::1]:8080/");
+    }
+
+    #[test]
+    fn test_validate_ports() {
+        assert!(Instance::builder()
+            .port(8080, false)
+            .secure_port(8443, false)
+            .build()
+            .is_err());
+        assert!(Instance::builder().port(0, true).build().is_err());
+        assert!(Instance::builder().app("").build().is_err());
+    }
+}
@@ -0,0 +1,288 @@
+//! Wire formats of the eureka REST api
+//!
+//! XML and JSON are built in, other encodings (e.g. the binary formats of some
+//! eureka forks) can implement [`Codec`] behind their own cargo features.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::Read;
+use std::str;
+use std::sync::Arc;
+
+use strong_xml::xmlparser::{ElementEnd, Token, Tokenizer};
+use strong_xml::{XmlRead, XmlWrite};
+
+use super::stream::{InstanceStream, SharedInstanceStream};
+use super::structures::{Application, Applications, Instance};
+use super::{InstanceIter, SharedInstanceIter};
+use crate::{EurekaError, ParseError};
+
+pub trait Codec: Debug + Send + Sync {
+    /// Media type used for the `Accept` and `Content-Type` headers
+    fn content_type(&self) -> &'static str;
+
+    fn encode_instance(&self, instance: &Instance) -> Result<Vec<u8>, EurekaError>;
+
+    fn decode_instance(&self, body: &[u8]) -> Result<Instance, EurekaError>;
+
+    fn decode_application(&self, body: &[u8]) -> Result<Application, EurekaError>;
+
+    fn decode_applications(&self, body: &[u8]) -> Result<Applications, EurekaError>;
+
+    /// Encodes an applications document, e.g. to dump the cached registry
+    ///
+    /// The default implementation returns an error, clients never send this document.
+    fn encode_applications(&self, apps: &Applications) -> Result<Vec<u8>, EurekaError> {
+        Err(EurekaError::UnexpectedState(format!(
+            "{} can't encode applications",
+            self.content_type()
+        )))
+    }
+
+    /// Yields the instances of an applications document read from `reader`
+    ///
+    /// The default implementation buffers the whole document.
+    fn stream_instances(
+        &self,
+        mut reader: Box<dyn Read + Send>,
+    ) -> Result<InstanceIter, EurekaError> {
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).map_err(EurekaError::Io)?;
+        let apps = self.decode_applications(&body)?;
+        Ok(Box::new(
+            apps.applications
+                .into_iter()
+                .flat_map(|app| app.instances)
+                .map(Ok),
+        ))
+    }
+
+    /// Yields the instances of an applications document read from `reader`, the `known`
+    /// ones, by app, when their registration didn't change
+    ///
+    /// The default implementation copies every instance out of
+    /// [`stream_instances`](Self::stream_instances).
+    fn stream_instances_reusing(
+        &self,
+        reader: Box<dyn Read + Send>,
+        _known: &HashMap<String, Vec<Arc<Instance>>>,
+    ) -> Result<SharedInstanceIter, EurekaError> {
+        Ok(Box::new(
+            self.stream_instances(reader)?
+                .map(|instance| instance.map(Arc::new)),
+        ))
+    }
+}
+
+fn parse_error<E: Debug>(e: E) -> EurekaError {
+    EurekaError::ParseError(ParseError::new(format!("{:?}", e)))
+}
+
+/// Error of the XML `document`, located at its first malformed token if any
+pub(crate) fn xml_error<E: Debug>(document: &str, e: E) -> EurekaError {
+    EurekaError::ParseError(locate_xml(document, ParseError::new(format!("{:?}", e))))
+}
+
+/// Locates the first malformed token or mismatched end tag of `document`
+fn locate_xml(document: &str, error: ParseError) -> ParseError {
+    let mut path = Vec::new();
+    for token in Tokenizer::from(document) {
+        match token {
+            Ok(Token::ElementStart { local, .. }) => path.push(local.as_str()),
+            Ok(Token::ElementEnd {
+                end: ElementEnd::Close(_, local),
+                ..
+            }) => {
+                if path.last() != Some(&local.as_str()) {
+                    return error
+                        .at_offset(document, local.start())
+                        .with_path(path.join("/"));
+                }
+                path.pop();
+            }
+            Ok(Token::ElementEnd {
+                end: ElementEnd::Empty,
+                ..
+            }) => {
+                path.pop();
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let pos = e.pos();
+                return error
+                    .at(document, pos.row as usize, pos.col as usize)
+                    .with_path(path.join("/"));
+            }
+        }
+    }
+    if path.is_empty() {
+        error
+    } else {
+        // Truncated document
+        error
+            .at_offset(document, document.len())
+            .with_path(path.join("/"))
+    }
+}
+
+/// Error of the JSON `document`, located at the line and column serde_json stopped at
+pub(crate) fn json_error(document: &[u8], e: serde_json::Error) -> EurekaError {
+    let message = e.to_string();
+    // serde_json ends its messages with the location
+    let message = match message.rfind(" at line ") {
+        Some(index) if e.line() > 0 => message[..index].to_string(),
+        _ => message,
+    };
+    let mut error = ParseError::new(message);
+    if e.line() > 0 {
+        error = error.at(&String::from_utf8_lossy(document), e.line(), e.column());
+    }
+    EurekaError::ParseError(error)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XmlCodec;
+
+impl Codec for XmlCodec {
+    fn content_type(&self) -> &'static str {
+        "application/xml"
+    }
+
+    fn encode_instance(&self, instance: &Instance) -> Result<Vec<u8>, EurekaError> {
+        instance
+            .to_string()
+            .map(String::into_bytes)
+            .map_err(parse_error)
+    }
+
+    fn decode_instance(&self, body: &[u8]) -> Result<Instance, EurekaError> {
+        let document = str::from_utf8(body).map_err(parse_error)?;
+        Instance::from_str(document).map_err(|e| xml_error(document, e))
+    }
+
+    fn decode_application(&self, body: &[u8]) -> Result<Application, EurekaError> {
+        let document = str::from_utf8(body).map_err(parse_error)?;
+        Application::from_str(document).map_err(|e| xml_error(document, e))
+    }
+
+    fn decode_applications(&self, body: &[u8]) -> Result<Applications, EurekaError> {
+        let document = str::from_utf8(body).map_err(parse_error)?;
+        Applications::from_str(document).map_err(|e| xml_error(document, e))
+    }
+
+    fn encode_applications(&self, apps: &Applications) -> Result<Vec<u8>, EurekaError> {
+        apps.to_string()
+            .map(String::into_bytes)
+            .map_err(parse_error)
+    }
+
+    fn stream_instances(&self, reader: Box<dyn Read + Send>) -> Result<InstanceIter, EurekaError> {
+        Ok(Box::new(InstanceStream::new(reader)))
+    }
+
+    fn stream_instances_reusing(
+        &self,
+        reader: Box<dyn Read + Send>,
+        known: &HashMap<String, Vec<Arc<Instance>>>,
+    ) -> Result<SharedInstanceIter, EurekaError> {
+        Ok(Box::new(SharedInstanceStream::new(reader, known)))
+    }
+}
+
+/// JSON documents are wrapped in an object named after their root element
+#[derive(Serialize)]
+struct InstanceRoot<'a> {
+    instance: &'a Instance,
+}
+
+#[derive(Serialize)]
+struct ApplicationsRoot<'a> {
+    applications: &'a Applications,
+}
+
+#[derive(Deserialize)]
+struct InstanceDocument {
+    instance: Instance,
+}
+
+#[derive(Deserialize)]
+struct ApplicationDocument {
+    application: Application,
+}
+
+#[derive(Deserialize)]
+struct ApplicationsDocument {
+    applications: Applications,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn encode_instance(&self, instance: &Instance) -> Result<Vec<u8>, EurekaError> {
+        serde_json::to_vec(&InstanceRoot { instance }).map_err(parse_error)
+    }
+
+    fn decode_instance(&self, body: &[u8]) -> Result<Instance, EurekaError> {
+        serde_json::from_slice::<InstanceDocument>(body)
+            .map(|doc| doc.instance)
+            .map_err(|e| json_error(body, e))
+    }
+
+    fn decode_application(&self, body: &[u8]) -> Result<Application, EurekaError> {
+        serde_json::from_slice::<ApplicationDocument>(body)
+            .map(|doc| doc.application)
+            .map_err(|e| json_error(body, e))
+    }
+
+    fn decode_applications(&self, body: &[u8]) -> Result<Applications, EurekaError> {
+        serde_json::from_slice::<ApplicationsDocument>(body)
+            .map(|doc| doc.applications)
+            .map_err(|e| json_error(body, e))
+    }
+
+    fn encode_applications(&self, apps: &Applications) -> Result<Vec<u8>, EurekaError> {
+        serde_json::to_vec(&ApplicationsRoot { applications: apps }).map_err(parse_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codecs_round_trip() {
+        let instance = Instance::default();
+        for codec in &[&XmlCodec as &dyn Codec, &JsonCodec] {
+            let body = codec.encode_instance(&instance).unwrap();
+            assert_eq!(codec.decode_instance(&body).unwrap(), instance);
+        }
+    }
+
+    #[test]
+    fn test_located_errors() {
+        let xml = "<applications>\n  <application>\n    <name>APP</nme>\n";
+        match XmlCodec.decode_applications(xml.as_bytes()) {
+            Err(EurekaError::ParseError(e)) => {
+                assert_eq!(e.line, Some(3));
+                assert_eq!(e.path.as_deref(), Some("applications/application/name"));
+                assert_eq!(e.snippet.as_deref(), Some("<name>APP</nme>"));
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+
+        let json = "{\"instance\": {\n  \"hostName\": 42\n}}";
+        match JsonCodec.decode_instance(json.as_bytes()) {
+            Err(EurekaError::ParseError(e)) => {
+                assert_eq!(e.line, Some(2));
+                assert_eq!(e.snippet.as_deref(), Some("\"hostName\": 42"));
+                assert!(!e.message.contains(" at line "));
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
@@ -1,296 +1,549 @@
-//! Eureka rest client (with xml serialization)
+//! Eureka rest client (xml serialization by default, see [`codec`])
 
 use reqwest::header::{ACCEPT, ACCEPT_ENCODING, CONTENT_TYPE};
-use reqwest::{Client, StatusCode};
+use reqwest::{Client, Response, StatusCode};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
 
-use strong_xml::{XmlRead, XmlWrite};
-
-use crate::{path_segment_encode, query_encode, EurekaError};
+use crate::ratelimit::RateLimiter;
+use crate::resolver::{ClusterResolver, EndpointHealth, StaticResolver};
+use crate::{path_segment_encode, query_encode, trace, EurekaError, Operation};
 
+use self::codec::{Codec, XmlCodec};
 use self::structures::*;
 
+mod borrowed;
+pub mod builder;
+pub mod codec;
+pub mod stream;
 pub mod structures;
 
-const ACCEPT_XML: &str = "application/xml";
+/// Captures the url and response body of an unsuccessful request
+fn request_error(url: &str, mut resp: Response) -> EurekaError {
+    let status = resp.status();
+    let body = resp.text().unwrap_or_default();
+    error!("Request to {} failed with {}: {}", url, status, body);
+    EurekaError::RequestDetailed {
+        status,
+        url: url.to_string(),
+        body,
+    }
+}
+
+fn is_server_error(error: &EurekaError) -> bool {
+    match error {
+        EurekaError::RequestDetailed { status, .. } => status.is_server_error(),
+        _ => false,
+    }
+}
+
+/// Instances yielded one by one while a registry is read
+pub type InstanceIter = Box<dyn Iterator<Item = Result<Instance, EurekaError>> + Send>;
+
+/// Instances yielded one by one while a registry is read, shared with a cache
+pub type SharedInstanceIter = Box<dyn Iterator<Item = Result<Arc<Instance>, EurekaError>> + Send>;
+
+/// Eureka REST operations
+///
+/// Implemented by [`EurekaRestClient`], it can be wrapped (metrics, caching)
+/// or mocked to use the registry and instance clients without a live server.
+pub trait EurekaApi: Debug + Send + Sync {
+    /// Register new application instance
+    fn register(&self, app_id: &str, data: &Instance) -> Result<(), EurekaError>;
+
+    /// De-register application instance
+    fn deregister(&self, app_id: &str, instance_id: &str) -> Result<(), EurekaError>;
+
+    /// Send application instance heartbeat
+    fn send_heartbeat(&self, app_id: &str, instance_id: &str) -> Result<(), EurekaError>;
+
+    /// Query for all instances
+    fn get_all_instances(&self) -> Result<Vec<Instance>, EurekaError>;
+
+    /// Query for all instances, yielding them one at a time
+    fn stream_all_instances(&self) -> Result<InstanceIter, EurekaError> {
+        Ok(Box::new(self.get_all_instances()?.into_iter().map(Ok)))
+    }
+
+    /// Query for all instances, yielding the `known` ones, by app, whose registration
+    /// didn't change instead of copies of them
+    ///
+    /// The default implementation copies every instance out of
+    /// [`stream_all_instances`](Self::stream_all_instances).
+    fn stream_all_instances_reusing(
+        &self,
+        _known: &HashMap<String, Vec<Arc<Instance>>>,
+    ) -> Result<SharedInstanceIter, EurekaError> {
+        Ok(Box::new(
+            self.stream_all_instances()?
+                .map(|instance| instance.map(Arc::new)),
+        ))
+    }
+
+    /// Query for all `app_id` instances
+    fn get_instances_by_app(&self, app_id: &str) -> Result<Vec<Instance>, EurekaError>;
+
+    /// Query for a specific `app_id/instance_id`
+    fn get_instance_by_app_and_instance(
+        &self,
+        app_id: &str,
+        instance_id: &str,
+    ) -> Result<Instance, EurekaError>;
+
+    /// Update instance status
+    fn update_status(
+        &self,
+        app_id: &str,
+        instance_id: &str,
+        new_status: StatusType,
+    ) -> Result<(), EurekaError>;
+
+    /// Remove the status override of an instance, setting its status to `new_status` if any
+    fn delete_status_override(
+        &self,
+        app_id: &str,
+        instance_id: &str,
+        new_status: Option<StatusType>,
+    ) -> Result<(), EurekaError>;
+
+    /// Update metadata
+    fn update_metadata(
+        &self,
+        app_id: &str,
+        instance_id: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), EurekaError>;
+
+    /// Enable or disable all instances of an auto scaling group
+    fn update_asg_status(&self, asg_name: &str, enabled: bool) -> Result<(), EurekaError>;
+
+    /// Query for all instances under a particular `vip_address`
+    fn get_instances_by_vip_address(&self, vip_address: &str)
+        -> Result<Vec<Instance>, EurekaError>;
+
+    /// Query for all instances under a particular `svip_address`
+    fn get_instances_by_svip_address(
+        &self,
+        svip_address: &str,
+    ) -> Result<Vec<Instance>, EurekaError>;
+}
+
+/// Eureka servers a request is sent to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pool {
+    /// Registry queries
+    Read,
+    /// Registration, heartbeats and updates
+    Write,
+}
 
 #[derive(Debug)]
 pub struct EurekaRestClient {
     client: Client,
-    base_url: String,
+    resolver: Arc<dyn ClusterResolver>,
+    read_resolver: Option<Arc<dyn ClusterResolver>>,
+    health: Arc<EndpointHealth>,
+    limiter: Option<Arc<RateLimiter>>,
+    codec: Arc<dyn Codec>,
 }
 
 impl EurekaRestClient {
     pub fn new(base_url: String) -> EurekaRestClient {
+        EurekaRestClient::with_rate_limiter(base_url, Arc::new(RateLimiter::default()))
+    }
+
+    pub fn with_rate_limiter(base_url: String, limiter: Arc<RateLimiter>) -> EurekaRestClient {
+        EurekaRestClient::with_resolver(Arc::new(StaticResolver::from_urls(&[base_url])), limiter)
+    }
+
+    /// Sends the requests to the eureka servers of `resolver`, failing over in order
+    pub fn with_resolver(
+        resolver: Arc<dyn ClusterResolver>,
+        limiter: Arc<RateLimiter>,
+    ) -> EurekaRestClient {
         EurekaRestClient {
             client: Client::new(),
-            base_url,
+            resolver,
+            read_resolver: None,
+            health: Arc::new(EndpointHealth::default()),
+            limiter: Some(limiter),
+            codec: Arc::new(XmlCodec),
         }
     }
 
-    /// Register new application instance
-    pub fn register(&self, app_id: &str, data: &Instance) -> Result<(), EurekaError> {
-        let url = format!("{}/apps/{}", self.base_url, path_segment_encode(app_id));
-        debug!("Sending register request to {}", url);
-        let resp = self
-            .client
-            .post(&url)
-            .header(CONTENT_TYPE, "application/xml")
-            .body(data.to_string().unwrap())
-            .send();
-        match resp {
-            Err(e) => Err(EurekaError::Network(e)),
-            Ok(mut resp) => match resp.status() {
-                StatusCode::NO_CONTENT => Ok(()),
-                _ => {
-                    log::error!("{}", resp.text().unwrap_or("".to_string()));
-                    Err(EurekaError::Request(resp.status()))
+    /// Send the registry queries to the eureka servers of `resolver`, e.g. read-only replicas
+    ///
+    /// Registration, heartbeats and status updates still go to the main servers.
+    pub fn with_read_resolver(mut self, resolver: Arc<dyn ClusterResolver>) -> EurekaRestClient {
+        self.read_resolver = Some(resolver);
+        self
+    }
+
+    /// Track the failures of the eureka servers in `health`
+    pub fn with_endpoint_health(mut self, health: Arc<EndpointHealth>) -> EurekaRestClient {
+        self.health = health;
+        self
+    }
+
+    /// Send the requests through `client`, e.g. one shared with the other requests of the
+    /// app to pool their connections
+    pub fn with_http_client(mut self, client: Client) -> EurekaRestClient {
+        self.client = client;
+        self
+    }
+
+    /// Send the requests without rate limiting them, e.g. for a command line tool registering
+    /// or sending heartbeats for many instances at once
+    pub fn without_rate_limit(mut self) -> EurekaRestClient {
+        self.limiter = None;
+        self
+    }
+
+    /// Use `codec` to encode and decode request and response bodies
+    pub fn with_codec(mut self, codec: Arc<dyn Codec>) -> EurekaRestClient {
+        self.codec = codec;
+        self
+    }
+
+    /// Takes a token for the `operation` request, registrations and deregistrations wait for
+    /// one instead of being dropped: a dropped deregistration leaves the instance in the
+    /// registry until its lease expires
+    fn acquire(&self, operation: Operation, url: &str) -> Result<(), EurekaError> {
+        let limiter = match &self.limiter {
+            Some(limiter) => limiter,
+            None => return Ok(()),
+        };
+        if matches!(operation, Operation::Register | Operation::Deregister) {
+            limiter.acquire();
+            Ok(())
+        } else if limiter.try_acquire() {
+            Ok(())
+        } else {
+            warn!("Dropping request to {}, rate limit exceeded", url);
+            Err(EurekaError::RateLimited)
+        }
+    }
+
+    /// Sends the `operation` request at `path` to the eureka servers until one of them answers
+    ///
+    /// The request takes a single rate limit token, whatever the number of servers tried.
+    /// Connection failures and 5xx responses move on to the next server and, but for 503s,
+    /// count towards its quarantine, other error responses are returned as is. Errors carry the
+    /// operation and the url of the last server tried.
+    fn execute<T, F>(
+        &self,
+        operation: Operation,
+        pool: Pool,
+        path: &str,
+        send: F,
+    ) -> Result<T, EurekaError>
+    where
+        F: Fn(&str) -> Result<T, EurekaError>,
+    {
+        let resolver = match (pool, &self.read_resolver) {
+            (Pool::Read, Some(read_resolver)) => read_resolver,
+            _ => &self.resolver,
+        };
+        let endpoints = resolver
+            .get_cluster_endpoints()
+            .map_err(|e| e.context(operation, path))?;
+        self.acquire(operation, path)
+            .map_err(|e| e.context(operation, path))?;
+        let endpoints = self.health.available(endpoints);
+        let mut last_error = None;
+        for endpoint in endpoints {
+            let url = format!("{}{}", endpoint.service_url, path);
+            trace::record_endpoint(&endpoint.service_url);
+            match send(&url) {
+                Err(e) if e.is_connection_failure() => {
+                    warn!("Request to {} failed: {}", url, e);
+                    self.health.record_failure(&endpoint);
+                    last_error = Some(e.context(operation, &url));
+                }
+                Err(e) if is_server_error(&e) => {
+                    // A 503 server is up but shedding load, it isn't quarantined
+                    if e.status() != Some(StatusCode::SERVICE_UNAVAILABLE) {
+                        self.health.record_failure(&endpoint);
+                    }
+                    last_error = Some(e.context(operation, &url));
                 }
-            },
+                result => {
+                    self.health.record_success(&endpoint);
+                    return result.map_err(|e| e.context(operation, &url));
+                }
+            }
         }
+        Err(last_error.unwrap_or_else(|| {
+            EurekaError::UnexpectedState("No eureka server to send the request to".to_string())
+                .context(operation, path)
+        }))
+    }
+
+    /// Sends a GET request, returning the response if it is a 200
+    fn get(&self, path: &str) -> Result<Response, EurekaError> {
+        self.execute(Operation::Fetch, Pool::Read, path, |url| {
+            debug!("Sending get request to {}", url);
+            let resp = self
+                .client
+                .get(url)
+                .header(ACCEPT, self.codec.content_type())
+                .send();
+            match resp {
+                Err(e) => Err(EurekaError::network(e)),
+                Ok(resp) => match trace::record_status(resp.status()) {
+                    StatusCode::OK => Ok(resp),
+                    _ => Err(request_error(url, resp)),
+                },
+            }
+        })
+    }
+
+    fn get_body(&self, path: &str) -> Result<Vec<u8>, EurekaError> {
+        let mut resp = self.get(path)?;
+        let mut body = Vec::new();
+        resp.copy_to(&mut body)
+            .map_err(|e| EurekaError::network(e).context(Operation::Fetch, path))?;
+        Ok(body)
+    }
+
+    /// Fetches the document at `path`, decoded by `decode`
+    fn fetch<T, F>(&self, path: &str, decode: F) -> Result<T, EurekaError>
+    where
+        F: FnOnce(&dyn Codec, &[u8]) -> Result<T, EurekaError>,
+    {
+        let body = self.get_body(path)?;
+        decode(self.codec.as_ref(), &body).map_err(|e| e.context(Operation::Fetch, path))
+    }
+
+    /// Sends a PUT request, expecting a 200
+    fn put(&self, path: &str) -> Result<(), EurekaError> {
+        self.execute(Operation::Update, Pool::Write, path, |url| {
+            debug!("Sending put request to {}", url);
+            let resp = self.client.put(url).send();
+            match resp {
+                Err(e) => Err(EurekaError::network(e)),
+                Ok(resp) => match trace::record_status(resp.status()) {
+                    StatusCode::OK => Ok(()),
+                    _ => Err(request_error(url, resp)),
+                },
+            }
+        })
+    }
+}
+
+impl EurekaApi for EurekaRestClient {
+    /// Register new application instance
+    fn register(&self, app_id: &str, data: &Instance) -> Result<(), EurekaError> {
+        let path = format!("/apps/{}", path_segment_encode(app_id));
+        let body = self
+            .codec
+            .encode_instance(data)
+            .map_err(|e| e.context(Operation::Register, &path))?;
+        self.execute(Operation::Register, Pool::Write, &path, |url| {
+            debug!("Sending register request to {}", url);
+            let resp = self
+                .client
+                .post(url)
+                .header(CONTENT_TYPE, self.codec.content_type())
+                .body(body.clone())
+                .send();
+            match resp {
+                Err(e) => Err(EurekaError::network(e)),
+                Ok(resp) => match trace::record_status(resp.status()) {
+                    StatusCode::NO_CONTENT => Ok(()),
+                    _ => Err(request_error(url, resp)),
+                },
+            }
+        })
     }
 
     /// De-register application instance
-    pub fn deregister(&self, app_id: &str, instance_id: &str) -> Result<(), EurekaError> {
-        let url = format!(
-            "{}/apps/{}/{}",
-            self.base_url,
+    fn deregister(&self, app_id: &str, instance_id: &str) -> Result<(), EurekaError> {
+        let path = format!(
+            "/apps/{}/{}",
             path_segment_encode(app_id),
             path_segment_encode(instance_id)
         );
-        debug!("Sending deregister request to {}", url);
-        let resp = self.client.delete(&url).send();
-        match resp {
-            Err(e) => Err(EurekaError::Network(e)),
-            Ok(resp) => match resp.status() {
-                StatusCode::OK => Ok(()),
-                _ => Err(EurekaError::Request(resp.status())),
-            },
-        }
+        self.execute(Operation::Deregister, Pool::Write, &path, |url| {
+            debug!("Sending deregister request to {}", url);
+            let resp = self.client.delete(url).send();
+            match resp {
+                Err(e) => Err(EurekaError::network(e)),
+                Ok(resp) => match trace::record_status(resp.status()) {
+                    StatusCode::OK => Ok(()),
+                    _ => Err(request_error(url, resp)),
+                },
+            }
+        })
     }
 
     /// Send application instance heartbeat
-    pub fn send_heartbeat(&self, app_id: &str, instance_id: &str) -> Result<(), EurekaError> {
-        let url = format!(
-            "{}/apps/{}/{}",
-            self.base_url,
+    fn send_heartbeat(&self, app_id: &str, instance_id: &str) -> Result<(), EurekaError> {
+        let path = format!(
+            "/apps/{}/{}",
             path_segment_encode(app_id),
             path_segment_encode(instance_id)
         );
-        debug!("Sending heartbeat request to {}", url);
-        let resp = self.client.put(&url).send();
-        match resp {
-            Err(e) => Err(EurekaError::Network(e)),
-            Ok(resp) => match resp.status() {
-                StatusCode::OK => Ok(()),
-                StatusCode::NOT_FOUND => Err(EurekaError::UnexpectedState(
-                    "Instance does not exist".into(),
-                )),
-                _ => Err(EurekaError::Request(resp.status())),
-            },
-        }
+        self.execute(Operation::Heartbeat, Pool::Write, &path, |url| {
+            debug!("Sending heartbeat request to {}", url);
+            let resp = self.client.put(url).send();
+            match resp {
+                Err(e) => Err(EurekaError::network(e)),
+                Ok(resp) => match trace::record_status(resp.status()) {
+                    StatusCode::OK => Ok(()),
+                    // 404 when the instance does not exist, see `EurekaError::is_not_found`
+                    _ => Err(request_error(url, resp)),
+                },
+            }
+        })
     }
 
     /// Query for all instances
-    pub fn get_all_instances(&self) -> Result<Vec<Instance>, EurekaError> {
-        let url = format!("{}/apps", self.base_url);
-        debug!("Sending get all instances request to {}", url);
-        let resp = self.client.get(&url).header(ACCEPT, ACCEPT_XML).send();
-        match resp {
-            Err(e) => Err(EurekaError::Network(e)),
-            Ok(mut resp) => match resp.status() {
-                StatusCode::OK => {
-                    let apps = Applications::from_str(
-                        resp.text()
-                            .map_err(|e| EurekaError::ParseError(format!("{:?}", e)))?
-                            .as_str(),
-                    )
-                    .map_err(|e| EurekaError::ParseError(format!("{:?}", e)))?;
-                    Ok(apps
-                        .applications
-                        .into_iter()
-                        .flat_map(|a| a.instances)
-                        .collect())
-                }
-                _ => Err(EurekaError::Request(resp.status())),
-            },
-        }
+    fn get_all_instances(&self) -> Result<Vec<Instance>, EurekaError> {
+        let apps = self.fetch("/apps", |codec, body| codec.decode_applications(body))?;
+        Ok(apps
+            .applications
+            .into_iter()
+            .flat_map(|a| a.instances)
+            .collect())
+    }
+
+    /// Query for all instances, parsing them incrementally while the body is read
+    fn stream_all_instances(&self) -> Result<InstanceIter, EurekaError> {
+        let resp = self.get("/apps")?;
+        self.codec
+            .stream_instances(Box::new(resp))
+            .map_err(|e| e.context(Operation::Fetch, "/apps"))
+    }
+
+    /// Query for all instances, parsing them incrementally and only copying the changed ones
+    fn stream_all_instances_reusing(
+        &self,
+        known: &HashMap<String, Vec<Arc<Instance>>>,
+    ) -> Result<SharedInstanceIter, EurekaError> {
+        let resp = self.get("/apps")?;
+        self.codec
+            .stream_instances_reusing(Box::new(resp), known)
+            .map_err(|e| e.context(Operation::Fetch, "/apps"))
     }
 
     /// Query for all `app_id` instances
-    pub fn get_instances_by_app(&self, app_id: &str) -> Result<Vec<Instance>, EurekaError> {
-        let url = format!("{}/apps/{}", self.base_url, path_segment_encode(app_id));
-        debug!("Sending get instances by app request to {}", url);
-        let resp = self.client.get(&url).header(ACCEPT, ACCEPT_XML).send();
-        match resp {
-            Err(e) => Err(EurekaError::Network(e)),
-            Ok(mut resp) => match resp.status() {
-                StatusCode::OK => {
-                    let app: Application = Application::from_str(
-                        resp.text()
-                            .map_err(|e| EurekaError::ParseError(e.to_string()))?
-                            .as_str(),
-                    )
-                    .map_err(|e| EurekaError::ParseError(format!("{:?}", e)))?;
-                    Ok(app.instances)
-                }
-                _ => Err(EurekaError::Request(resp.status())),
-            },
-        }
+    fn get_instances_by_app(&self, app_id: &str) -> Result<Vec<Instance>, EurekaError> {
+        let path = format!("/apps/{}", path_segment_encode(app_id));
+        let app = self.fetch(&path, |codec, body| codec.decode_application(body))?;
+        Ok(app.instances)
     }
 
     /// Query for a specific `app_id/instance_id`
-    pub fn get_instance_by_app_and_instance(
+    fn get_instance_by_app_and_instance(
         &self,
         app_id: &str,
         instance_id: &str,
     ) -> Result<Instance, EurekaError> {
-        let url = format!(
-            "{}/apps/{}/{}",
-            self.base_url,
+        let path = format!(
+            "/apps/{}/{}",
             path_segment_encode(app_id),
             path_segment_encode(instance_id)
         );
-        debug!(
-            "Sending get instance by app and instance request to {}",
-            url
-        );
-        let resp = self.client.get(&url).header(ACCEPT, ACCEPT_XML).send();
-        match resp {
-            Err(e) => Err(EurekaError::Network(e)),
-            Ok(mut resp) => match resp.status() {
-                StatusCode::OK => {
-                    let instance: Instance = Instance::from_str(
-                        resp.text()
-                            .map_err(|e| EurekaError::ParseError(e.to_string()))?
-                            .as_str(),
-                    )
-                    .map_err(|e| EurekaError::ParseError(format!("{:?}", e)))?;
-                    Ok(instance)
-                }
-                _ => Err(EurekaError::Request(resp.status())),
-            },
-        }
+        self.fetch(&path, |codec, body| codec.decode_instance(body))
     }
 
     /// Update instance status
-    pub fn update_status(
+    fn update_status(
         &self,
         app_id: &str,
         instance_id: &str,
         new_status: StatusType,
     ) -> Result<(), EurekaError> {
-        let url = format!(
-            "{}/apps/{}/{}/status?value={}",
-            self.base_url,
+        self.put(&format!(
+            "/apps/{}/{}/status?value={}",
             path_segment_encode(app_id),
             path_segment_encode(instance_id),
             new_status
+        ))
+    }
+
+    /// Remove the status override of an instance, setting its status to `new_status` if any
+    fn delete_status_override(
+        &self,
+        app_id: &str,
+        instance_id: &str,
+        new_status: Option<StatusType>,
+    ) -> Result<(), EurekaError> {
+        let mut path = format!(
+            "/apps/{}/{}/status",
+            path_segment_encode(app_id),
+            path_segment_encode(instance_id)
         );
-        debug!("Sending update status request to {}", url);
-        let resp = self.client.put(&url).send();
-        match resp {
-            Err(e) => Err(EurekaError::Network(e)),
-            Ok(resp) => match resp.status() {
-                StatusCode::OK => Ok(()),
-                _ => Err(EurekaError::Request(resp.status())),
-            },
+        if let Some(status) = new_status {
+            path.push_str(&format!("?value={}", status));
         }
+        self.execute(Operation::Update, Pool::Write, &path, |url| {
+            debug!("Sending delete status override request to {}", url);
+            let resp = self.client.delete(url).send();
+            match resp {
+                Err(e) => Err(EurekaError::network(e)),
+                Ok(resp) => match trace::record_status(resp.status()) {
+                    StatusCode::OK => Ok(()),
+                    _ => Err(request_error(url, resp)),
+                },
+            }
+        })
     }
 
     /// Update metadata
-    pub fn update_metadata(
+    fn update_metadata(
         &self,
         app_id: &str,
         instance_id: &str,
         key: &str,
         value: &str,
     ) -> Result<(), EurekaError> {
-        let url = format!(
-            "{}/apps/{}/{}/metadata?{}={}",
-            self.base_url,
+        self.put(&format!(
+            "/apps/{}/{}/metadata?{}={}",
             path_segment_encode(app_id),
             path_segment_encode(instance_id),
             query_encode(key),
             query_encode(value)
-        );
-        debug!("Sending update metadata request to {}", url);
-        let resp = self.client.put(&url).send();
-        match resp {
-            Err(e) => Err(EurekaError::Network(e)),
-            Ok(resp) => match resp.status() {
-                StatusCode::OK => Ok(()),
-                _ => Err(EurekaError::Request(resp.status())),
-            },
-        }
+        ))
+    }
+
+    /// Enable or disable all instances of an auto scaling group
+    fn update_asg_status(&self, asg_name: &str, enabled: bool) -> Result<(), EurekaError> {
+        self.put(&format!(
+            "/asg/{}/status?value={}",
+            path_segment_encode(asg_name),
+            if enabled { "ENABLED" } else { "DISABLED" }
+        ))
     }
 
     /// Query for all instances under a particular `vip_address`
-    pub fn get_instances_by_vip_address(
+    fn get_instances_by_vip_address(
         &self,
         vip_address: &str,
     ) -> Result<Vec<Instance>, EurekaError> {
-        let url = format!(
-            "{}/vips/{}",
-            self.base_url,
-            path_segment_encode(vip_address)
-        );
-        debug!("Sending get instances by vip address request to {}", url);
-        let resp = self.client.get(&url).header(ACCEPT, ACCEPT_XML).send();
-        match resp {
-            Err(e) => Err(EurekaError::Network(e)),
-            Ok(mut resp) => match resp.status() {
-                StatusCode::OK => {
-                    let apps: Applications = Applications::from_str(
-                        resp.text()
-                            .map_err(|e| EurekaError::ParseError(e.to_string()))?
-                            .as_str(),
-                    )
-                    .map_err(|e| EurekaError::ParseError(format!("{:?}", e)))?;
-                    Ok(apps
-                        .applications
-                        .into_iter()
-                        .flat_map(|a| a.instances)
-                        .collect())
-                }
-                _ => Err(EurekaError::Request(resp.status())),
-            },
-        }
+        let path = format!("/vips/{}", path_segment_encode(vip_address));
+        let apps = self.fetch(&path, |codec, body| codec.decode_applications(body))?;
+        Ok(apps
+            .applications
+            .into_iter()
+            .flat_map(|a| a.instances)
+            .collect())
     }
 
     /// Query for all instances under a particular `svip_address`
-    pub fn get_instances_by_svip_address(
+    fn get_instances_by_svip_address(
         &self,
         svip_address: &str,
     ) -> Result<Vec<Instance>, EurekaError> {
-        let url = format!(
-            "{}/svips/{}",
-            self.base_url,
-            path_segment_encode(svip_address)
-        );
-        debug!("Sending get instances by svip address request to {}", url);
-        let resp = self
-            .client
-            .get(&url)
-            .header("Accept", "application/json")
-            .send();
-        match resp {
-            Err(e) => Err(EurekaError::Network(e)),
-            Ok(mut resp) => match resp.status() {
-                StatusCode::OK => {
-                    let apps: Applications = Applications::from_str(
-                        resp.text()
-                            .map_err(|e| EurekaError::ParseError(e.to_string()))?
-                            .as_str(),
-                    )
-                    .map_err(|e| EurekaError::ParseError(format!("{:?}", e)))?;
-                    Ok(apps
-                        .applications
-                        .into_iter()
-                        .flat_map(|a| a.instances)
-                        .collect())
-                }
-                _ => Err(EurekaError::Request(resp.status())),
-            },
-        }
+        let path = format!("/svips/{}", path_segment_encode(svip_address));
+        let apps = self.fetch(&path, |codec, body| codec.decode_applications(body))?;
+        Ok(apps
+            .applications
+            .into_iter()
+            .flat_map(|a| a.instances)
+            .collect())
     }
 }
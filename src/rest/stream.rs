@@ -0,0 +1,303 @@
+//! Incremental parsing of `<instance>` elements out of a registry document
+//!
+//! Only the bytes of the instance currently being parsed are buffered, so the
+//! full `<applications>` document never has to be held in memory. The buffers are
+//! reused from one instance to the next.
+//!
+//! [`InstanceStream`] parses every instance into an owned [`Instance`], a `String` per
+//! text field. [`SharedInstanceStream`] parses them into borrowed structures first and
+//! yields the known instance when it is unchanged, only changed instances are copied.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{ErrorKind, Read};
+use std::str;
+use std::sync::Arc;
+
+use strong_xml::XmlRead;
+
+use super::borrowed::InstanceRef;
+use super::codec::xml_error;
+use super::structures::Instance;
+use crate::{EurekaError, ParseError};
+
+const INSTANCE_START: &[u8] = b"<instance>";
+const INSTANCE_END: &[u8] = b"</instance>";
+const DEFAULT_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Iterator over the instances of a registry document read from `R`
+#[derive(Debug)]
+pub struct InstanceStream<R> {
+    reader: R,
+    buf: Vec<u8>,
+    /// Bytes read from `reader`, before they are appended to `buf`
+    chunk: Vec<u8>,
+    /// Length of `buf` already searched for the end tag of the instance
+    scanned: usize,
+    eof: bool,
+}
+
+impl<R: Read> InstanceStream<R> {
+    pub fn new(reader: R) -> Self {
+        InstanceStream::with_chunk_size(reader, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(reader: R, chunk_size: usize) -> Self {
+        InstanceStream {
+            reader,
+            buf: Vec::with_capacity(chunk_size),
+            chunk: vec![0u8; chunk_size],
+            scanned: 0,
+            eof: false,
+        }
+    }
+
+    /// Pops the next complete `<instance>` element out of the buffer, parsed by `parse`
+    fn take_instance<T, F>(&mut self, parse: F) -> Option<Result<T, EurekaError>>
+    where
+        F: FnOnce(&str) -> Result<T, EurekaError>,
+    {
+        match find(&self.buf, INSTANCE_START) {
+            Some(start) => {
+                // Nothing before the start tag is needed anymore
+                self.buf.drain(..start);
+                // Resume the search where the previous fill stopped it
+                let from = self.scanned.saturating_sub(INSTANCE_END.len() - 1);
+                let end = match find(&self.buf[from..], INSTANCE_END) {
+                    Some(end) => from + end + INSTANCE_END.len(),
+                    None => {
+                        self.scanned = self.buf.len();
+                        return None;
+                    }
+                };
+                self.scanned = 0;
+                // The errors are located in the instance element
+                let result = str::from_utf8(&self.buf[..end])
+                    .map_err(|e| EurekaError::ParseError(ParseError::new(e.to_string())))
+                    .and_then(parse);
+                self.buf.drain(..end);
+                Some(result)
+            }
+            None => {
+                // Keep a tail which may hold the beginning of a start tag
+                let keep = INSTANCE_START.len() - 1;
+                if self.buf.len() > keep {
+                    let len = self.buf.len();
+                    self.buf.drain(..len - keep);
+                }
+                None
+            }
+        }
+    }
+
+    fn fill(&mut self) -> Result<(), EurekaError> {
+        loop {
+            match self.reader.read(&mut self.chunk) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(());
+                }
+                Ok(n) => {
+                    self.buf.extend_from_slice(&self.chunk[..n]);
+                    return Ok(());
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.eof = true;
+                    return Err(EurekaError::Io(e));
+                }
+            }
+        }
+    }
+
+    /// Next instance of the document, parsed by `parse`
+    fn next_with<T, F>(&mut self, mut parse: F) -> Option<Result<T, EurekaError>>
+    where
+        F: FnMut(&str) -> Result<T, EurekaError>,
+    {
+        loop {
+            if let Some(result) = self.take_instance(&mut parse) {
+                return Some(result);
+            }
+            if self.eof {
+                return None;
+            }
+            if let Err(e) = self.fill() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for InstanceStream<R> {
+    type Item = Result<Instance, EurekaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with(|xml| Instance::from_str(xml).map_err(|e| xml_error(xml, e)))
+    }
+}
+
+/// Iterator over the instances of a registry document read from `R`, yielding the
+/// `known` instances whose registration didn't change instead of copies of them
+#[derive(Debug)]
+pub struct SharedInstanceStream<R> {
+    stream: InstanceStream<R>,
+    /// Known instances by the hash of their app and id
+    known: HashMap<u64, Arc<Instance>>,
+}
+
+impl<R: Read> SharedInstanceStream<R> {
+    /// Stream of `reader`, `known` being the cached instances by app
+    pub fn new(reader: R, known: &HashMap<String, Vec<Arc<Instance>>>) -> Self {
+        SharedInstanceStream {
+            stream: InstanceStream::new(reader),
+            known: known
+                .values()
+                .flatten()
+                .map(|instance| {
+                    let id = instance.instance_id.as_deref();
+                    let id = id.unwrap_or(&instance.host_name);
+                    (instance_key(&instance.app, id), Arc::clone(instance))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for SharedInstanceStream<R> {
+    type Item = Result<Arc<Instance>, EurekaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let known = &self.known;
+        self.stream.next_with(|xml| {
+            let instance = InstanceRef::from_str(xml).map_err(|e| xml_error(xml, e))?;
+            // Hash collisions differ in their app or id, which aren't the same registration
+            match known.get(&instance_key(&instance.app, instance.id())) {
+                Some(known) if instance.same_registration(known) => Ok(Arc::clone(known)),
+                _ => Ok(Arc::new(instance.into_owned())),
+            }
+        })
+    }
+}
+
+fn instance_key(app: &str, id: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (app, id).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const XML: &str = r#"<applications>
+  <versions__delta>1</versions__delta>
+  <application>
+    <name>BENCH</name>
+    <instance>
+      <hostName>localhost</hostName>
+      <instanceId>localhost:bench:8080</instanceId>
+      <app>BENCH</app>
+      <ipAddr>127.0.0.1</ipAddr>
+      <status>UP</status>
+      <port enabled="true">8080</port>
+      <securePort enabled="false">443</securePort>
+      <dataCenterInfo class="com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo">
+        <name>MyOwn</name>
+      </dataCenterInfo>
+      <homePageUrl></homePageUrl>
+      <statusPageUrl></statusPageUrl>
+      <healthCheckUrl></healthCheckUrl>
+      <metadata>
+        <zone>us-east-1a</zone>
+        <version><![CDATA[1.0]]></version>
+      </metadata>
+      <vipAddress>bench</vipAddress>
+      <secureVipAddress>bench</secureVipAddress>
+    </instance>
+  </application>
+  <application>
+    <name>OTHER</name>
+    <instance>
+      <hostName>localhost2</hostName>
+      <app>OTHER</app>
+      <ipAddr>127.0.0.2</ipAddr>
+      <status>UP</status>
+      <port enabled="true">8081</port>
+      <securePort enabled="false">443</securePort>
+      <dataCenterInfo class="com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo">
+        <name>MyOwn</name>
+      </dataCenterInfo>
+      <homePageUrl></homePageUrl>
+      <statusPageUrl></statusPageUrl>
+      <healthCheckUrl></healthCheckUrl>
+      <vipAddress>other</vipAddress>
+      <secureVipAddress>other</secureVipAddress>
+    </instance>
+  </application>
+</applications>"#;
+
+    #[test]
+    fn test_stream_instances() {
+        let instances: Vec<Instance> = InstanceStream::with_chunk_size(Cursor::new(XML), 7)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(instances.len(), 2);
+        assert_eq!(
+            instances[0].instance_id,
+            Some("localhost:bench:8080".to_string())
+        );
+        assert_eq!(instances[1].app, "OTHER");
+        assert_eq!(instances[1].port.value, 8081);
+    }
+
+    #[test]
+    fn test_stream_shared_instances() {
+        let instances: Vec<Instance> = InstanceStream::new(Cursor::new(XML))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(instances[0].version(), Some("1.0"));
+
+        // Unknown instances are copied out of the borrowed ones unchanged
+        let copied: Vec<Arc<Instance>> =
+            SharedInstanceStream::new(Cursor::new(XML), &HashMap::new())
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert_eq!(copied.len(), 2);
+        assert_eq!(*copied[0], instances[0]);
+        assert_eq!(*copied[1], instances[1]);
+
+        let mut known = HashMap::new();
+        for instance in &copied {
+            known
+                .entry(instance.app.clone())
+                .or_insert_with(Vec::new)
+                .push(Arc::clone(instance));
+        }
+        let xml = XML.replace("<ipAddr>127.0.0.2</ipAddr>", "<ipAddr>127.0.0.3</ipAddr>");
+        let shared: Vec<Arc<Instance>> = SharedInstanceStream::new(Cursor::new(xml), &known)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(Arc::ptr_eq(&shared[0], &copied[0]));
+        assert!(!Arc::ptr_eq(&shared[1], &copied[1]));
+        assert_eq!(shared[1].ip_addr, "127.0.0.3");
+
+        // The metadata is compared too
+        let xml = XML.replace("us-east-1a", "us-east-1b");
+        let shared: Vec<Arc<Instance>> = SharedInstanceStream::new(Cursor::new(xml), &known)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(!Arc::ptr_eq(&shared[0], &copied[0]));
+        assert_eq!(shared[0].zone(), Some("us-east-1b"));
+        assert!(Arc::ptr_eq(&shared[1], &copied[1]));
+    }
+}
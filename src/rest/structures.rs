@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
@@ -6,62 +7,138 @@ use std::io::Write;
 use std::net::ToSocketAddrs;
 use std::str::FromStr;
 
-use strong_xml::xmlparser::{ElementEnd, Token};
+use serde::ser::SerializeMap;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use strong_xml::{XmlRead, XmlReader, XmlResult, XmlWrite, XmlWriter};
 
-#[derive(XmlWrite, XmlRead, PartialEq, Debug)]
+use super::borrowed::AppMetaDataRef;
+use super::codec::{Codec, JsonCodec, XmlCodec};
+use crate::{EurekaError, ParseError};
+
+#[derive(XmlWrite, XmlRead, Serialize, Deserialize, PartialEq, Debug)]
 #[xml(tag = "applications")]
 pub struct Applications {
     #[xml(flatten_text = "versions__delta")]
+    #[serde(rename = "versions__delta", skip_serializing_if = "Option::is_none")]
     pub versions_delta: Option<String>,
     #[xml(flatten_text = "apps__hashcode")]
+    #[serde(rename = "apps__hashcode", skip_serializing_if = "Option::is_none")]
     pub apps_hashcode: Option<String>,
     #[xml(child = "application")]
+    #[serde(
+        rename = "application",
+        default,
+        deserialize_with = "deserialize_one_or_many"
+    )]
     pub applications: Vec<Application>,
 }
 
-#[derive(XmlWrite, XmlRead, PartialEq, Debug)]
+#[derive(XmlWrite, XmlRead, Serialize, Deserialize, PartialEq, Debug)]
 #[xml(tag = "application")]
 pub struct Application {
     #[xml(flatten_text = "name")]
     pub name: String,
     #[xml(child = "instance")]
+    #[serde(
+        rename = "instance",
+        default,
+        deserialize_with = "deserialize_one_or_many"
+    )]
     pub instances: Vec<Instance>,
 }
 
-#[derive(Clone, XmlWrite, XmlRead, PartialEq, Debug)]
+#[derive(Clone, XmlWrite, XmlRead, Serialize, Deserialize, PartialEq, Debug)]
 #[xml(tag = "instance")]
+#[serde(rename_all = "camelCase")]
 pub struct Instance {
     #[xml(flatten_text = "hostName")]
     pub host_name: String,
     #[xml(flatten_text = "instanceId")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub instance_id: Option<String>,
     #[xml(flatten_text = "app")]
     pub app: String,
+    #[xml(flatten_text = "appGroupName")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_group_name: Option<String>,
+    #[xml(flatten_text = "asgName")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asg_name: Option<String>,
+    #[xml(flatten_text = "sid")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
     #[xml(flatten_text = "ipAddr")]
     pub ip_addr: String,
     #[xml(flatten_text = "vipAddress")]
+    #[serde(default)]
     pub vip_address: String,
     #[xml(flatten_text = "secureVipAddress")]
+    #[serde(default)]
     pub secure_vip_address: String,
     #[xml(flatten_text = "status")]
     pub status: StatusType,
+    #[xml(flatten_text = "overriddenstatus")]
+    #[serde(
+        rename = "overriddenStatus",
+        alias = "overriddenstatus",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub overridden_status: Option<StatusType>,
     #[xml(child = "port")]
+    #[serde(default)]
     pub port: PortData,
     #[xml(child = "securePort")]
+    #[serde(default)]
     pub secure_port: SecurePort,
+    #[xml(flatten_text = "countryId")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub country_id: Option<u32>,
     #[xml(flatten_text = "homePageUrl")]
+    #[serde(default)]
     pub home_page_url: String,
     #[xml(flatten_text = "statusPageUrl")]
+    #[serde(default)]
     pub status_page_url: String,
     #[xml(flatten_text = "healthCheckUrl")]
+    #[serde(default)]
     pub health_check_url: String,
     #[xml(child = "dataCenterInfo")]
+    #[serde(default)]
     pub data_center_info: DataCenterInfo,
     #[xml(child = "leaseInfo")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub lease_info: Option<LeaseInfo>,
     #[xml(child = "metadata")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<AppMetaDataType>,
+    #[xml(flatten_text = "isCoordinatingDiscoveryServer")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub is_coordinating_discovery_server: Option<bool>,
+    #[xml(flatten_text = "lastUpdatedTimestamp")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_updated_timestamp: Option<u64>,
+    #[xml(flatten_text = "lastDirtyTimestamp")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_dirty_timestamp: Option<u64>,
+    #[xml(flatten_text = "actionType")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action_type: Option<ActionType>,
 }
 
 impl Default for Instance {
@@ -70,28 +147,106 @@ impl Default for Instance {
             host_name: "localhost".to_string(),
             instance_id: None,
             app: env!("CARGO_PKG_NAME").to_string(),
+            app_group_name: None,
+            asg_name: None,
+            sid: None,
             ip_addr: "127.0.0.1".to_string(),
             vip_address: env!("CARGO_PKG_NAME").to_string(),
             secure_vip_address: env!("CARGO_PKG_NAME").to_string(),
             status: StatusType::Starting,
+            overridden_status: None,
             port: PortData::default(),
             secure_port: SecurePort::default(),
+            country_id: Some(1),
             home_page_url: "".to_string(),
             status_page_url: "".to_string(),
             health_check_url: "".to_string(),
             data_center_info: DataCenterInfo::default(),
             lease_info: None,
             metadata: None,
+            is_coordinating_discovery_server: Some(false),
+            last_updated_timestamp: None,
+            last_dirty_timestamp: None,
+            action_type: None,
         }
     }
 }
 
-#[derive(Clone, XmlWrite, XmlRead, PartialEq, Debug)]
+impl Instance {
+    /// Value of a metadata key
+    pub fn metadata_value(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(key))
+    }
+
+    /// Sets a metadata key, creating the metadata if needed
+    pub fn set_metadata_value(&mut self, key: &str, value: &str) {
+        self.metadata
+            .get_or_insert_with(AppMetaDataType::default)
+            .map
+            .insert(key.to_string(), value.to_string());
+    }
+
+    /// Port of the management endpoints, falls back to the instance port
+    pub fn management_port(&self) -> u16 {
+        self.metadata
+            .as_ref()
+            .and_then(AppMetaDataType::management_port)
+            .unwrap_or(self.port.value)
+    }
+
+    pub fn management_context_path(&self) -> Option<&str> {
+        self.metadata_value(AppMetaDataType::MANAGEMENT_CONTEXT_PATH)
+    }
+
+    pub fn zone(&self) -> Option<&str> {
+        self.metadata_value(AppMetaDataType::ZONE)
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.metadata_value(AppMetaDataType::VERSION)
+    }
+
+    pub fn rack(&self) -> Option<&str> {
+        self.metadata_value(AppMetaDataType::RACK)
+    }
+
+    pub fn secure_health_check_url(&self) -> Option<&str> {
+        self.metadata_value(AppMetaDataType::SECURE_HEALTH_CHECK_URL)
+    }
+
+    /// XML body registering the instance, e.g. to snapshot-test it
+    ///
+    /// The fields are written in a fixed order and the metadata sorted by key, so that
+    /// unchanged instances are always encoded to the same bytes.
+    pub fn canonical_xml(&self) -> Result<String, EurekaError> {
+        canonical(&XmlCodec, self)
+    }
+
+    /// JSON body registering the instance, canonical as [`canonical_xml`](Self::canonical_xml)
+    pub fn canonical_json(&self) -> Result<String, EurekaError> {
+        canonical(&JsonCodec, self)
+    }
+}
+
+fn canonical(codec: &dyn Codec, instance: &Instance) -> Result<String, EurekaError> {
+    let body = codec.encode_instance(instance)?;
+    String::from_utf8(body).map_err(|e| ParseError::new(e.to_string()).into())
+}
+
+#[derive(Clone, XmlWrite, XmlRead, Serialize, Deserialize, PartialEq, Debug)]
 #[xml(tag = "port")]
 pub struct PortData {
     #[xml(attr = "enabled")]
+    #[serde(
+        rename = "@enabled",
+        serialize_with = "serialize_display",
+        deserialize_with = "deserialize_lenient"
+    )]
     pub enabled: bool,
     #[xml(default, text)]
+    #[serde(rename = "$", deserialize_with = "deserialize_lenient")]
     pub value: u16,
 }
 
@@ -113,12 +268,18 @@ impl PortData {
     }
 }
 
-#[derive(Clone, XmlWrite, XmlRead, PartialEq, Debug)]
+#[derive(Clone, XmlWrite, XmlRead, Serialize, Deserialize, PartialEq, Debug)]
 #[xml(tag = "securePort")]
 pub struct SecurePort {
     #[xml(attr = "enabled")]
+    #[serde(
+        rename = "@enabled",
+        serialize_with = "serialize_display",
+        deserialize_with = "deserialize_lenient"
+    )]
     pub enabled: bool,
     #[xml(default, text)]
+    #[serde(rename = "$", deserialize_with = "deserialize_lenient")]
     pub value: u16,
 }
 
@@ -140,17 +301,38 @@ impl SecurePort {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+/// Serializes a type through its `Display` and `FromStr` impls
+macro_rules! serde_display_from_str {
+    ($ty:ty) => {
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(de::Error::custom)
+            }
+        }
+    };
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub enum DcNameType {
     MyOwn,
     Amazon,
+    /// Data centers registered by custom eureka setups (Cloud Foundry, forks, ...)
+    Other(String),
 }
 
 impl Display for DcNameType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             Self::MyOwn => write!(f, "MyOwn"),
             Self::Amazon => write!(f, "Amazon"),
+            Self::Other(name) => write!(f, "{}", name),
         }
     }
 }
@@ -162,11 +344,13 @@ impl FromStr for DcNameType {
         match s {
             "MyOwn" => Ok(Self::MyOwn),
             "Amazon" => Ok(Self::Amazon),
-            _ => Err("Invalid dcNameType".to_string()),
+            _ => Ok(Self::Other(s.to_string())),
         }
     }
 }
 
+serde_display_from_str!(DcNameType);
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum StatusType {
     Up,
@@ -191,19 +375,60 @@ impl Display for StatusType {
 impl FromStr for StatusType {
     type Err = String;
 
+    /// Unrecognized statuses are mapped to `Unknown` so that a single odd
+    /// instance can't fail the parsing of a whole registry
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "UP" => Ok(Self::Up),
             "DOWN" => Ok(Self::Down),
+            "STARTING" => Ok(Self::Starting),
             "OUT_OF_SERVICE" => Ok(Self::OutOfService),
             "UNKNOWN" => Ok(Self::Unknown),
-            _ => Err("Invalid statusType".to_string()),
+            _ => {
+                warn!("Unrecognized instance status {}, using UNKNOWN", s);
+                Ok(Self::Unknown)
+            }
         }
     }
 }
 
-#[derive(Clone, XmlWrite, XmlRead, PartialEq, Debug)]
+serde_display_from_str!(StatusType);
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ActionType {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl Display for ActionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Added => write!(f, "ADDED"),
+            Self::Modified => write!(f, "MODIFIED"),
+            Self::Deleted => write!(f, "DELETED"),
+        }
+    }
+}
+
+impl FromStr for ActionType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ADDED" => Ok(Self::Added),
+            "MODIFIED" => Ok(Self::Modified),
+            "DELETED" => Ok(Self::Deleted),
+            _ => Err("Invalid actionType".to_string()),
+        }
+    }
+}
+
+serde_display_from_str!(ActionType);
+
+#[derive(Clone, Default, XmlWrite, XmlRead, Serialize, Deserialize, PartialEq, Debug)]
 #[xml(tag = "metadata")]
+#[serde(rename_all = "kebab-case", default)]
 pub struct AmazonMetaDataType {
     #[xml(flatten_text = "ami-launch-index")]
     pub ami_launch_index: String,
@@ -229,14 +454,16 @@ pub struct AmazonMetaDataType {
     pub instance_type: String,
 }
 
-#[derive(Clone, XmlWrite, XmlRead, PartialEq, Debug)]
+#[derive(Clone, XmlWrite, XmlRead, Serialize, Deserialize, PartialEq, Debug)]
 #[xml(tag = "dataCenterInfo")]
 pub struct DataCenterInfo {
     #[xml(attr = "class")]
+    #[serde(rename = "@class", skip_serializing_if = "Option::is_none")]
     pub class: Option<String>,
     #[xml(flatten_text = "name")]
     pub name: DcNameType,
     #[xml(child = "metadata")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<AmazonMetaDataType>,
 }
 
@@ -250,13 +477,81 @@ impl Default for DataCenterInfo {
     }
 }
 
-#[derive(Clone, XmlWrite, XmlRead, PartialEq, Debug)]
+#[derive(Clone, Default, XmlWrite, XmlRead, Serialize, Deserialize, PartialEq, Debug)]
 #[xml(tag = "leaseInfo")]
+#[serde(rename_all = "camelCase")]
 pub struct LeaseInfo {
+    #[xml(flatten_text = "renewalIntervalInSecs")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub renewal_interval_in_secs: Option<u32>,
+    #[xml(flatten_text = "durationInSecs")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub duration_in_secs: Option<u32>,
+    #[xml(flatten_text = "registrationTimestamp")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub registration_timestamp: Option<u64>,
+    #[xml(flatten_text = "lastRenewalTimestamp")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_renewal_timestamp: Option<u64>,
+    #[xml(flatten_text = "evictionTimestamp")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub eviction_timestamp: Option<u64>,
+    #[xml(flatten_text = "serviceUpTimestamp")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub service_up_timestamp: Option<u64>,
     #[xml(flatten_text = "evictionDurationInSecs")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_lenient_option",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub eviction_duration_in_secs: Option<usize>,
 }
 
+impl LeaseInfo {
+    /// Default lease duration of the eureka server
+    pub const DEFAULT_DURATION_IN_SECS: u32 = 90;
+
+    /// Time in milli-seconds since epoch when the lease expires without renewal
+    pub fn expires_at(&self) -> Option<u64> {
+        let duration = self
+            .duration_in_secs
+            .unwrap_or(Self::DEFAULT_DURATION_IN_SECS);
+        self.last_renewal_timestamp
+            .map(|renewed| renewed + u64::from(duration) * 1000)
+    }
+
+    /// Whether the lease has expired at `now`, in milli-seconds since epoch
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.eviction_timestamp.map_or(false, |evicted| evicted > 0)
+            || self.expires_at().map_or(false, |expires| now > expires)
+    }
+}
+
 #[derive(Clone, Default, Debug, PartialEq)]
 pub struct AppMetaDataType {
     pub class: Option<String>,
@@ -265,58 +560,218 @@ pub struct AppMetaDataType {
 
 impl AppMetaDataType {
     pub const TAG: &'static str = "metadata";
-}
 
-impl<'a> XmlRead<'a> for AppMetaDataType {
-    fn from_reader(reader: &mut XmlReader<'a>) -> XmlResult<Self> {
-        let mut metadata = Self::default();
-        reader.read_till_element_start(AppMetaDataType::TAG)?;
+    pub const MANAGEMENT_PORT: &'static str = "management.port";
+    pub const MANAGEMENT_CONTEXT_PATH: &'static str = "management.context-path";
+    pub const ZONE: &'static str = "zone";
+    pub const VERSION: &'static str = "version";
+    pub const RACK: &'static str = "rack";
+    pub const SECURE_HEALTH_CHECK_URL: &'static str = "secure.health.check.url";
 
-        // attr class
-        while let Some((attr_name, attr_val)) = reader.find_attribute()? {
-            if attr_name.eq("class") {
-                metadata.class = Some(attr_val.to_string());
-            }
-        }
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.map.get(key).map(String::as_str)
+    }
 
-        // end?
-        let next = reader.next().unwrap()?;
-        if let Token::ElementEnd {
-            end: ElementEnd::Empty,
-            ..
-        } = next
-        {
-            return Ok(metadata);
-        }
+    /// Parses the value of `key`, values which fail to parse are ignored
+    pub fn parse<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key).and_then(|value| value.trim().parse().ok())
+    }
 
-        // child
-        while let Some(child_key) = reader.find_element_start(Some(AppMetaDataType::TAG))? {
-            reader.next();
-            let child_value = reader.read_text(child_key)?;
-            metadata
-                .map
-                .insert(child_key.to_string(), child_value.to_string());
-        }
+    pub fn management_port(&self) -> Option<u16> {
+        self.parse(Self::MANAGEMENT_PORT)
+    }
+
+    pub fn management_context_path(&self) -> Option<&str> {
+        self.get(Self::MANAGEMENT_CONTEXT_PATH)
+    }
 
-        Ok(metadata)
+    /// Entries sorted by key, the order they are encoded in
+    fn sorted(&self) -> Vec<(&String, &String)> {
+        let mut entries: Vec<_> = self.map.iter().collect();
+        entries.sort();
+        entries
+    }
+
+    pub fn zone(&self) -> Option<&str> {
+        self.get(Self::ZONE)
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.get(Self::VERSION)
+    }
+
+    pub fn secure_health_check_url(&self) -> Option<&str> {
+        self.get(Self::SECURE_HEALTH_CHECK_URL)
+    }
+}
+
+// Parsed as on the registry fetch path, which borrows the values
+impl<'a> XmlRead<'a> for AppMetaDataType {
+    fn from_reader(reader: &mut XmlReader<'a>) -> XmlResult<Self> {
+        AppMetaDataRef::from_reader(reader).map(AppMetaDataRef::into_owned)
     }
 }
 
 impl XmlWrite for AppMetaDataType {
     fn to_writer<W: Write>(&self, writer: &mut XmlWriter<W>) -> XmlResult<()> {
-        writer.write_element_start(AppMetaDataType::TAG);
+        writer.write_element_start(AppMetaDataType::TAG)?;
         if let Some(v) = &self.class {
             writer.write_attribute("class", v.as_str())?;
         }
         writer.write_element_end_open()?;
-        for (k, v) in &self.map {
-            writer.write_flatten_text(k.as_str(), v.as_str(), false);
+        for (k, v) in self.sorted() {
+            write!(writer.inner, "<{}>{}</{}>", k, xml_escape(v), k)?;
         }
-        writer.write_element_end_close(AppMetaDataType::TAG);
+        writer.write_element_end_close(AppMetaDataType::TAG)?;
         Ok(())
     }
 }
 
+fn xml_escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(|c| c == '&' || c == '<' || c == '>') {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Unknown or malformed entities are kept as they are
+pub(crate) fn xml_unescape(text: &str) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let decoded = entity.and_then(|(name, _)| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if name.starts_with("#x") => u32::from_str_radix(&name[2..], 16)
+                .ok()
+                .and_then(std::char::from_u32),
+            _ if name.starts_with('#') => name[1..].parse().ok().and_then(std::char::from_u32),
+            _ => None,
+        });
+        match (decoded, entity) {
+            (Some(c), Some((_, end))) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    Cow::Owned(unescaped)
+}
+
+impl Serialize for AppMetaDataType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = self.map.len() + self.class.iter().count();
+        let mut map = serializer.serialize_map(Some(len))?;
+        if let Some(class) = &self.class {
+            map.serialize_entry("@class", class)?;
+        }
+        for (k, v) in self.sorted() {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for AppMetaDataType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut map = HashMap::<String, String>::deserialize(deserializer)?;
+        let class = map.remove("@class");
+        Ok(AppMetaDataType { class, map })
+    }
+}
+
+/// Eureka encodes most JSON scalars as strings, accept both forms
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Lenient<T> {
+    Value(T),
+    Text(String),
+}
+
+impl<T> Lenient<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    fn into_value<E: de::Error>(self) -> Result<T, E> {
+        match self {
+            Lenient::Value(value) => Ok(value),
+            Lenient::Text(text) => text.parse().map_err(E::custom),
+        }
+    }
+}
+
+fn deserialize_lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+{
+    Lenient::deserialize(deserializer)?.into_value()
+}
+
+fn deserialize_lenient_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+{
+    match Option::<Lenient<T>>::deserialize(deserializer)? {
+        Some(lenient) => lenient.into_value().map(Some),
+        None => Ok(None),
+    }
+}
+
+fn serialize_display<S, T>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Display,
+{
+    serializer.collect_str(value)
+}
+
+/// Single element lists are sometimes encoded as a bare object
+fn deserialize_one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
+    }
+
+    match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::Many(values) => Ok(values),
+        OneOrMany::One(value) => Ok(vec![value]),
+    }
+}
+
 mod tests {
     use std::str::FromStr;
 
@@ -392,6 +847,15 @@ mod tests {
         assert_eq!(instance.host_name, "localhost");
         assert_eq!(instance.app, "BENCH");
         assert_eq!(instance.ip_addr, "127.0.0.1");
+        assert_eq!(instance.overridden_status, Some(StatusType::Up));
+        assert_eq!(instance.country_id, Some(1));
+        assert_eq!(instance.is_coordinating_discovery_server, Some(false));
+        assert_eq!(instance.last_updated_timestamp, Some(1616761261538));
+        assert_eq!(instance.last_dirty_timestamp, Some(1616761261439));
+        assert_eq!(instance.action_type, Some(ActionType::Added));
+
+        let round_trip = Instance::from_str(&instance.to_string()?)?;
+        assert_eq!(round_trip, instance);
 
         Ok(())
     }
@@ -405,6 +869,20 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_status_type() {
+        assert_eq!(StatusType::from_str("STARTING"), Ok(StatusType::Starting));
+        assert_eq!(StatusType::from_str("SOMETHING"), Ok(StatusType::Unknown));
+        assert_eq!(
+            serde_json::to_string(&StatusType::OutOfService).unwrap(),
+            r#""OUT_OF_SERVICE""#
+        );
+        assert_eq!(
+            serde_json::from_str::<StatusType>(r#""UP""#).unwrap(),
+            StatusType::Up
+        );
+    }
+
     #[test]
     fn test_xml_data_center_info() -> XmlResult<()> {
         let xml = r#"<dataCenterInfo class="com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo">
@@ -420,11 +898,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_xml_custom_data_center_info() -> XmlResult<()> {
+        let xml = r#"<dataCenterInfo class="org.example.CloudFoundryDataCenterInfo"><name>CloudFoundry</name></dataCenterInfo>"#;
+        let data_center_info = DataCenterInfo::from_str(xml)?;
+        assert_eq!(
+            data_center_info.name,
+            DcNameType::Other("CloudFoundry".to_string())
+        );
+        assert_eq!(data_center_info.to_string()?, xml);
+        Ok(())
+    }
+
     #[test]
     fn test_xml_lease_info() -> XmlResult<()> {
         let xml = r#"<leaseInfo></leaseInfo>"#;
         let lease_info = LeaseInfo::from_str(xml)?;
         assert_eq!(lease_info.eviction_duration_in_secs, None);
+
+        let xml = r#"<leaseInfo>
+        <renewalIntervalInSecs>30</renewalIntervalInSecs>
+        <durationInSecs>90</durationInSecs>
+        <registrationTimestamp>1616761261538</registrationTimestamp>
+        <lastRenewalTimestamp>1616761921820</lastRenewalTimestamp>
+        <evictionTimestamp>0</evictionTimestamp>
+        <serviceUpTimestamp>1616761261439</serviceUpTimestamp>
+      </leaseInfo>"#;
+        let lease_info = LeaseInfo::from_str(xml)?;
+        assert_eq!(lease_info.renewal_interval_in_secs, Some(30));
+        assert_eq!(lease_info.duration_in_secs, Some(90));
+        assert_eq!(lease_info.registration_timestamp, Some(1616761261538));
+        assert_eq!(lease_info.last_renewal_timestamp, Some(1616761921820));
+        assert_eq!(lease_info.eviction_timestamp, Some(0));
+        assert_eq!(lease_info.service_up_timestamp, Some(1616761261439));
+        assert_eq!(lease_info.expires_at(), Some(1616762011820));
+        assert!(!lease_info.is_expired(1616762011820));
+        assert!(lease_info.is_expired(1616762011821));
         Ok(())
     }
 
@@ -444,6 +953,114 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_json_applications() {
+        let json = r#"{
+  "versions__delta": "1",
+  "apps__hashcode": "UP_1_",
+  "application": {
+    "name": "BENCH",
+    "instance": [{
+      "instanceId": "localhost:bench:8080",
+      "hostName": "localhost",
+      "app": "BENCH",
+      "ipAddr": "127.0.0.1",
+      "status": "UP",
+      "overriddenStatus": "UNKNOWN",
+      "port": {"$": 8080, "@enabled": "true"},
+      "securePort": {"$": 443, "@enabled": "false"},
+      "countryId": 1,
+      "dataCenterInfo": {
+        "@class": "com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo",
+        "name": "MyOwn"
+      },
+      "leaseInfo": {"renewalIntervalInSecs": 30, "durationInSecs": "90"},
+      "metadata": {"@class": "java.util.Collections$EmptyMap", "zone": "a"},
+      "homePageUrl": "http://localhost:8080/",
+      "statusPageUrl": "http://localhost:8080/info",
+      "healthCheckUrl": "http://localhost:8080/health",
+      "vipAddress": "bench",
+      "secureVipAddress": "bench",
+      "isCoordinatingDiscoveryServer": "false",
+      "lastUpdatedTimestamp": "1616761261538",
+      "lastDirtyTimestamp": 1616761261439,
+      "actionType": "ADDED"
+    }]
+  }
+}"#;
+        let apps: Applications = serde_json::from_str(json).unwrap();
+        assert_eq!(apps.applications.len(), 1);
+        let instance = &apps.applications[0].instances[0];
+        assert_eq!(instance.port, PortData::new(8080, true));
+        assert_eq!(instance.secure_port, SecurePort::new(443, false));
+        assert_eq!(instance.overridden_status, Some(StatusType::Unknown));
+        assert_eq!(instance.is_coordinating_discovery_server, Some(false));
+        assert_eq!(instance.last_updated_timestamp, Some(1616761261538));
+        assert_eq!(instance.last_dirty_timestamp, Some(1616761261439));
+        assert_eq!(
+            instance.lease_info.as_ref().unwrap().duration_in_secs,
+            Some(90)
+        );
+        let metadata = instance.metadata.as_ref().unwrap();
+        assert_eq!(
+            metadata.class,
+            Some("java.util.Collections$EmptyMap".to_string())
+        );
+        assert_eq!(metadata.map.get("zone").unwrap(), "a");
+
+        let port = serde_json::to_string(&instance.port).unwrap();
+        assert_eq!(port, r#"{"@enabled":"true","$":8080}"#);
+
+        let round_trip: Applications =
+            serde_json::from_str(&serde_json::to_string(&apps).unwrap()).unwrap();
+        assert_eq!(round_trip, apps);
+    }
+
+    #[test]
+    fn test_metadata_accessors() {
+        let mut instance = Instance::default();
+        instance.port = PortData::new(8080, true);
+        assert_eq!(instance.management_port(), 8080);
+        assert_eq!(instance.zone(), None);
+
+        let mut metadata = AppMetaDataType::default();
+        for (k, v) in &[
+            ("management.port", "9090"),
+            ("management.context-path", "/actuator"),
+            ("zone", "us-east-1a"),
+            ("version", "2.0"),
+        ] {
+            metadata.map.insert(k.to_string(), v.to_string());
+        }
+        instance.metadata = Some(metadata);
+        assert_eq!(instance.management_port(), 9090);
+        assert_eq!(instance.management_context_path(), Some("/actuator"));
+        assert_eq!(instance.zone(), Some("us-east-1a"));
+        assert_eq!(instance.version(), Some("2.0"));
+        assert_eq!(instance.secure_health_check_url(), None);
+    }
+
+    #[test]
+    fn test_xml_app_meta_data_escaping() -> XmlResult<()> {
+        let mut metadata = AppMetaDataType::default();
+        metadata
+            .map
+            .insert("a".to_string(), "x < y && \"ü\" > z".to_string());
+        let s = metadata.to_string()?;
+        assert_eq!(
+            s,
+            r#"<metadata><a>x &lt; y &amp;&amp; "ü" &gt; z</a></metadata>"#
+        );
+        assert_eq!(AppMetaDataType::from_str(&s)?, metadata);
+
+        let xml = r#"<metadata><a><![CDATA[<b>&amp;</b>]]></a><c>&#x41;&#66;&unknown;</c><d/></metadata>"#;
+        let metadata = AppMetaDataType::from_str(xml)?;
+        assert_eq!(metadata.map.get("a").unwrap(), "<b>&amp;</b>");
+        assert_eq!(metadata.map.get("c").unwrap(), "AB&unknown;");
+        assert_eq!(metadata.map.get("d").unwrap(), "");
+        Ok(())
+    }
+
     #[test]
     fn test_xml_full() -> XmlResult<()> {
         let xml = r#"<applications>
@@ -519,4 +1136,21 @@ mod tests {
         let application = Applications::from_str(xml)?;
         Ok(())
     }
+
+    #[test]
+    fn test_canonical_xml() {
+        let mut instance = Instance::default();
+        for key in &["zone", "version", "management.port", "a"] {
+            instance.set_metadata_value(key, "1");
+        }
+        let xml = instance.canonical_xml().unwrap();
+        assert!(xml.contains(
+            "<metadata><a>1</a><management.port>1</management.port><version>1</version><zone>1</zone></metadata>"
+        ));
+        assert_eq!(instance.clone().canonical_xml().unwrap(), xml);
+        let json = instance.canonical_json().unwrap();
+        assert!(
+            json.contains(r#""metadata":{"a":"1","management.port":"1","version":"1","zone":"1"}"#)
+        );
+    }
 }
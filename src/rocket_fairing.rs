@@ -0,0 +1,69 @@
+//! Registration of a `rocket` server while it runs, behind the `rocket` feature
+
+use std::sync::Arc;
+
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::tokio::task::spawn_blocking;
+use rocket::{Build, Orbit, Rocket};
+
+use crate::{BaseConfig, EurekaClient};
+
+/// Fairing registering the instance on liftoff and deregistering it on shutdown
+///
+/// The client is created on ignite with the configured rocket port, and managed so that
+/// handlers get it with `&State<Arc<EurekaClient>>`, e.g. to read the registry with
+/// [`EurekaClient::dump_registry`].
+#[derive(Debug, Clone)]
+pub struct EurekaFairing {
+    config: BaseConfig,
+}
+
+impl EurekaFairing {
+    pub fn new(config: BaseConfig) -> Self {
+        EurekaFairing { config }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for EurekaFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Eureka",
+            kind: Kind::Ignite | Kind::Liftoff | Kind::Shutdown,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let mut config = self.config.clone();
+        config.instance.port.value = match rocket.figment().extract_inner::<u16>("port") {
+            Ok(port) => port,
+            Err(e) => {
+                error!("Failed to read the rocket port: {}", e);
+                return Err(rocket);
+            }
+        };
+        config.instance.port.enabled = true;
+        match spawn_blocking(move || Arc::new(EurekaClient::new(config))).await {
+            Ok(client) => Ok(rocket.manage(client)),
+            Err(e) => {
+                error!("Failed to create the eureka client: {}", e);
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        if let Some(client) = rocket.state::<Arc<EurekaClient>>() {
+            let client = Arc::clone(client);
+            // Registration blocks until eureka answers
+            let _ = spawn_blocking(move || client.start()).await;
+        }
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        if let Some(client) = rocket.state::<Arc<EurekaClient>>() {
+            let client = Arc::clone(client);
+            let _ = spawn_blocking(move || client.deregister()).await;
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Canary and blue/green routing of the requests to other apps, see
+//! [`crate::EurekaClient::route`] and [`crate::EurekaClient::label_selector`]
+
+use rand::random;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::registry::{read, write};
+use crate::rest::structures::Instance;
+
+/// Share of the requests to an app sent to the instances whose metadata match a selector
+#[derive(Debug, Clone, PartialEq)]
+struct WeightRule {
+    key: String,
+    /// `None` for the selectors matching any value of `key`
+    value: Option<String>,
+    percent: u32,
+}
+
+impl WeightRule {
+    /// Rule of a `key=value` selector, or of a bare `key`
+    fn new(selector: &str, percent: u32) -> Self {
+        let (key, value) = match selector.find('=') {
+            Some(i) => (&selector[..i], Some(selector[i + 1..].trim().to_string())),
+            None => (selector, None),
+        };
+        WeightRule {
+            key: key.trim().to_string(),
+            value,
+            percent: percent.min(100),
+        }
+    }
+
+    fn selector(&self) -> String {
+        match self.value {
+            Some(ref value) => format!("{}={}", self.key, value),
+            None => self.key.clone(),
+        }
+    }
+
+    fn matches(&self, instance: &Instance) -> bool {
+        let value = instance
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.map.get(&self.key));
+        match (&self.value, value) {
+            (Some(expected), Some(value)) => expected == value,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        }
+    }
+}
+
+/// Weight rules and label selectors of the apps
+#[derive(Debug, Default)]
+pub(crate) struct Routes {
+    /// Rules by upper case app name, in the order they were added
+    apps: RwLock<HashMap<String, Vec<WeightRule>>>,
+    /// Metadata label the instances must carry, by upper case app name
+    labels: RwLock<HashMap<String, (String, String)>>,
+}
+
+impl Routes {
+    fn set_weight(&self, app: &str, selector: &str, percent: u32) {
+        let rule = WeightRule::new(selector, percent);
+        let mut apps = write(&self.apps);
+        let rules = apps.entry(app.to_uppercase()).or_insert_with(Vec::new);
+        match rules.iter_mut().find(|r| r.selector() == rule.selector()) {
+            Some(existing) => *existing = rule,
+            None => rules.push(rule),
+        }
+    }
+
+    fn clear(&self, app: &str) {
+        write(&self.apps).remove(&app.to_uppercase());
+    }
+
+    pub(crate) fn set_label(&self, app: &str, key: &str, value: &str) {
+        let label = (key.to_string(), value.to_string());
+        write(&self.labels).insert(app.to_uppercase(), label);
+    }
+
+    pub(crate) fn clear_label(&self, app: &str) {
+        write(&self.labels).remove(&app.to_uppercase());
+    }
+
+    /// Whether `instance` carries the label selected for `app`, if any
+    pub(crate) fn has_label(&self, app: &str, instance: &Instance) -> bool {
+        match read(&self.labels).get(&app.to_uppercase()) {
+            Some((key, value)) => instance.metadata_value(key) == Some(value.as_str()),
+            None => true,
+        }
+    }
+
+    /// The `candidates` indices of `instances` a request to `app` picks from, the ones
+    /// carrying the selected label then the ones the weight rules pick
+    pub(crate) fn select(
+        &self,
+        app: &str,
+        instances: &[Arc<Instance>],
+        mut candidates: Vec<usize>,
+    ) -> Vec<usize> {
+        if let Some((key, value)) = read(&self.labels).get(&app.to_uppercase()) {
+            candidates.retain(|&i| instances[i].metadata_value(key) == Some(value.as_str()));
+        }
+        match read(&self.apps).get(&app.to_uppercase()) {
+            Some(rules) if !rules.is_empty() => {
+                select(rules, random::<u32>() % 100, instances, candidates)
+            }
+            _ => candidates,
+        }
+    }
+}
+
+/// The candidates matching the rule whose band of percents holds `draw`, or the ones
+/// matching no rule when `draw` is past the bands, all of them when that pool is empty
+fn select(
+    rules: &[WeightRule],
+    draw: u32,
+    instances: &[Arc<Instance>],
+    candidates: Vec<usize>,
+) -> Vec<usize> {
+    let pool = |matches: &dyn Fn(&Instance) -> bool| -> Vec<usize> {
+        candidates
+            .iter()
+            .cloned()
+            .filter(|&i| matches(&instances[i]))
+            .collect()
+    };
+    let mut bound = 0;
+    for rule in rules {
+        bound += rule.percent;
+        if draw < bound {
+            let matching = pool(&|instance| rule.matches(instance));
+            if !matching.is_empty() {
+                return matching;
+            }
+            break;
+        }
+    }
+    let baseline = pool(&|instance| !rules.iter().any(|rule| rule.matches(instance)));
+    if baseline.is_empty() {
+        candidates
+    } else {
+        baseline
+    }
+}
+
+/// Routing rules of an app, e.g. `client.route("ORDERS").weight("version=2.0", 10)` to send
+/// 10 % of the requests to the instances whose `version` metadata is `2.0`
+///
+/// The rules apply to [`make_request`](crate::EurekaClient::make_request), `call` and
+/// [`resolve`](crate::EurekaClient::resolve). The requests no rule takes go to the instances
+/// matching no rule.
+#[derive(Debug)]
+pub struct Route<'a> {
+    routes: &'a Routes,
+    app: String,
+}
+
+impl<'a> Route<'a> {
+    pub(crate) fn new(routes: &'a Routes, app: &str) -> Self {
+        Route {
+            routes,
+            app: app.to_string(),
+        }
+    }
+
+    /// Sends `percent` of the requests to the instances whose metadata match `selector`,
+    /// `key=value` or a bare `key`, replacing the percent of the same selector
+    ///
+    /// The rules take their percents in the order they were added, up to 100 in total.
+    /// Requests falling to a rule no UP instance matches go to the others.
+    pub fn weight(self, selector: &str, percent: u32) -> Self {
+        self.routes.set_weight(&self.app, selector, percent);
+        self
+    }
+
+    /// Removes the rules of the app, sending its requests to any instance
+    pub fn clear(self) -> Self {
+        self.routes.clear(&self.app);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::instance;
+
+    #[test]
+    fn test_select() {
+        let instances = [
+            Arc::new(instance("ORDERS").version("1.0").build()),
+            Arc::new(instance("ORDERS").version("2.0").build()),
+            Arc::new(instance("ORDERS").build()),
+            Arc::new(instance("ORDERS").version("3.0").build()),
+        ];
+        let rules = [
+            WeightRule::new("version=2.0", 10),
+            WeightRule::new(" version = 3.0 ", 5),
+        ];
+        let all = vec![0, 1, 2, 3];
+        assert_eq!(select(&rules, 0, &instances, all.clone()), vec![1]);
+        assert_eq!(select(&rules, 12, &instances, all.clone()), vec![3]);
+        assert_eq!(select(&rules, 15, &instances, all.clone()), vec![0, 2]);
+        // No UP canary left
+        assert_eq!(select(&rules, 0, &instances, vec![0, 2]), vec![0, 2]);
+        assert_eq!(select(&rules, 50, &instances, vec![1]), vec![1]);
+
+        let routes = Routes::default();
+        Route::new(&routes, "orders")
+            .weight("version=2.0", 100)
+            .weight("version=2.0", 150);
+        for _ in 0..10 {
+            assert_eq!(routes.select("ORDERS", &instances, all.clone()), vec![1]);
+        }
+        assert_eq!(read(&routes.apps)["ORDERS"][0].percent, 100);
+        Route::new(&routes, "Orders").clear();
+        assert_eq!(routes.select("ORDERS", &instances, all.clone()), all);
+
+        routes.set_label("orders", "version", "3.0");
+        assert_eq!(routes.select("ORDERS", &instances, all.clone()), vec![3]);
+        assert!(!routes.has_label("ORDERS", &instances[2]));
+        routes.set_label("orders", "version", "4.0");
+        assert!(routes.select("ORDERS", &instances, all.clone()).is_empty());
+        routes.clear_label("ORDERS");
+        assert_eq!(routes.select("ORDERS", &instances, all.clone()), all);
+    }
+}
@@ -0,0 +1,151 @@
+//! Typed clients of apps, see [`eureka_service!`](crate::eureka_service)
+
+use std::fmt::Display;
+
+use crate::path_segment_encode;
+
+/// Declares a client of an app, with a method per endpoint calling it with
+/// [`EurekaClient::call`](crate::EurekaClient::call)
+///
+/// The parameters of a method fill the `{name}` segments of its path, percent encoded. A
+/// parameter after a `;` is sent as the JSON body.
+///
+/// ```ignore
+/// eureka_service! {
+///     /// Client of the ORDERS app
+///     pub struct OrdersClient("ORDERS") {
+///         /// Order of `id`
+///         fn order(id: u64) -> Order = GET "/orders/{id}";
+///         fn create_order(customer: String; order: &NewOrder) -> Order =
+///             POST "/customers/{customer}/orders";
+///     }
+/// }
+///
+/// let orders = OrdersClient::new(Arc::clone(&client));
+/// let order = orders.order(42)?;
+/// ```
+#[macro_export]
+macro_rules! eureka_service {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident($app:expr) {
+            $(
+                $(#[$fn_meta:meta])*
+                fn $fn:ident(
+                    $($arg:ident: $ty:ty),* $(,)? $(; $body:ident: $body_ty:ty)?
+                ) -> $ret:ty = $method:ident $path:literal;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone)]
+        $vis struct $name {
+            client: ::std::sync::Arc<$crate::EurekaClient>,
+        }
+
+        impl $name {
+            /// App the client calls
+            pub const APP: &'static str = $app;
+
+            pub fn new(client: ::std::sync::Arc<$crate::EurekaClient>) -> Self {
+                $name { client }
+            }
+
+            $(
+                $(#[$fn_meta])*
+                pub fn $fn(
+                    &self,
+                    $($arg: $ty,)*
+                    $($body: $body_ty)?
+                ) -> ::std::result::Result<$ret, $crate::EurekaError> {
+                    let path = $crate::service::expand_path(
+                        $path,
+                        &[$((stringify!($arg), &$arg as &dyn ::std::fmt::Display)),*],
+                    );
+                    $crate::eureka_service!(
+                        @call self.client, Self::APP, path, $method $(, $body)?
+                    )
+                }
+            )*
+        }
+    };
+    (@call $client:expr, $app:expr, $path:ident, $method:ident) => {
+        $client.call_with(
+            $app,
+            &$path,
+            $crate::Method::$method,
+            $crate::HeaderMap::new(),
+            |request| request,
+        )
+    };
+    (@call $client:expr, $app:expr, $path:ident, $method:ident, $body:ident) => {
+        $client.call(
+            $app,
+            &$path,
+            $crate::Method::$method,
+            &$body,
+            $crate::HeaderMap::new(),
+        )
+    };
+}
+
+/// `template` with its `{name}` segments replaced by the percent encoded value of `name`
+#[doc(hidden)]
+pub fn expand_path(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut path = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        path.push_str(&rest[..start]);
+        let name = &rest[start + 1..end];
+        match args.iter().find(|(arg, _)| *arg == name) {
+            Some((_, value)) => path.push_str(&path_segment_encode(&value.to_string())),
+            None => path.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    path.push_str(rest);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{BaseConfig, EurekaClient, EurekaError};
+
+    eureka_service! {
+        /// Client of the ORDERS app
+        struct OrdersClient("ORDERS") {
+            fn order(id: u64) -> String = GET "/orders/{id}";
+            fn create_order(customer: &str; order: &[u64]) -> u64 =
+                POST "/customers/{customer}/orders";
+        }
+    }
+
+    #[test]
+    fn test_expand_path() {
+        let args: [(&str, &dyn Display); 2] = [("customer", &"acme corp"), ("id", &42)];
+        assert_eq!(
+            expand_path("/customers/{customer}/orders/{id}", &args),
+            "/customers/acme%20corp/orders/42"
+        );
+        assert_eq!(expand_path("/orders/{unknown}", &args), "/orders/{unknown}");
+        assert_eq!(expand_path("/orders/{id", &args), "/orders/{id");
+    }
+
+    #[test]
+    fn test_service() {
+        let mut config = BaseConfig::default();
+        config.eureka.register_with_eureka = false;
+        config.eureka.fetch_registry = false;
+        let orders = OrdersClient::new(Arc::new(EurekaClient::new(config)));
+        assert_eq!(OrdersClient::APP, "ORDERS");
+        let error = orders.create_order("acme", &[1, 2]).unwrap_err();
+        assert!(matches!(error, EurekaError::AppNotFound(ref app) if app == "ORDERS"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Registration of a co-process, e.g. an app not written in Rust, as Spring Cloud Netflix
+//! Sidecar does
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+
+use crate::rest::structures::{PortData, StatusType};
+use crate::{BaseConfig, EurekaClient};
+
+/// Co-process registered by a [`Sidecar`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SidecarConfig {
+    /// Port the co-process serves on, registered as the instance port
+    pub port: u16,
+    /// Health url of the co-process, e.g. `http://localhost:3000/health`
+    pub health_uri: String,
+    /// Health check interval in milli-seconds, default 30,000
+    pub health_check_interval: usize,
+}
+
+impl Default for SidecarConfig {
+    fn default() -> Self {
+        SidecarConfig {
+            port: 80,
+            health_uri: String::new(),
+            health_check_interval: 30_000,
+        }
+    }
+}
+
+/// Registers a co-process listening on the sidecar port, and sets its status from its
+/// health url
+///
+/// The co-process is UP when its health url answers a success, unless the answer is a JSON
+/// object whose `status` says otherwise, as Spring Boot actuator health endpoints do. It is
+/// DOWN when the health url fails or can't be reached.
+#[derive(Debug)]
+pub struct Sidecar {
+    client: Arc<EurekaClient>,
+    config: SidecarConfig,
+    is_running: Arc<AtomicBool>,
+}
+
+impl Sidecar {
+    pub fn new(mut config: BaseConfig, sidecar: SidecarConfig) -> Self {
+        config.instance.port = PortData::new(sidecar.port, true);
+        if config.instance.health_check_url.is_empty() {
+            config.instance.health_check_url = sidecar.health_uri.clone();
+        }
+        Sidecar {
+            client: Arc::new(EurekaClient::new(config)),
+            config: sidecar,
+            is_running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Client registering the co-process, e.g. to call other apps on its behalf
+    pub fn client(&self) -> &Arc<EurekaClient> {
+        &self.client
+    }
+
+    /// Registers the co-process, then checks its health until the sidecar is dropped
+    pub fn start(&self) {
+        self.client.start();
+        self.is_running.store(true, Ordering::Relaxed);
+
+        let is_running = Arc::clone(&self.is_running);
+        let client = Arc::clone(&self.client);
+        let health_uri = self.config.health_uri.clone();
+        let interval = Duration::from_millis(self.config.health_check_interval as u64);
+        thread::spawn(move || {
+            let http = client.clients.shared().clone();
+            let mut status = StatusType::Up;
+            while is_running.load(Ordering::Relaxed) {
+                let health = check_health(&http, &health_uri);
+                if health != status {
+                    info!("Co-process is {}, was {}", health, status);
+                    match client.set_status(health) {
+                        Ok(_) => status = health,
+                        Err(e) => error!("Failed to set the co-process status: {}", e),
+                    }
+                }
+                thread::sleep(interval);
+            }
+        });
+    }
+}
+
+impl Drop for Sidecar {
+    fn drop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn check_health(http: &Client, health_uri: &str) -> StatusType {
+    match http.get(health_uri).send() {
+        Ok(mut response) => {
+            let body = response.json::<Value>().ok();
+            health_status(response.status(), body.as_ref())
+        }
+        Err(e) => {
+            warn!(
+                "Failed to check the co-process health at {}: {}",
+                health_uri, e
+            );
+            StatusType::Down
+        }
+    }
+}
+
+fn health_status(status: StatusCode, body: Option<&Value>) -> StatusType {
+    if !status.is_success() {
+        return StatusType::Down;
+    }
+    match body.and_then(|body| body["status"].as_str()) {
+        Some(status) => status.parse().unwrap_or(StatusType::Unknown),
+        None => StatusType::Up,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_status() {
+        assert_eq!(health_status(StatusCode::OK, None), StatusType::Up);
+        assert_eq!(
+            health_status(
+                StatusCode::OK,
+                Some(&serde_json::json!({ "status": "DOWN" }))
+            ),
+            StatusType::Down
+        );
+        assert_eq!(
+            health_status(StatusCode::OK, Some(&serde_json::json!(["UP"]))),
+            StatusType::Up
+        );
+        assert_eq!(
+            health_status(
+                StatusCode::SERVICE_UNAVAILABLE,
+                Some(&serde_json::json!({ "status": "UP" }))
+            ),
+            StatusType::Down
+        );
+    }
+}
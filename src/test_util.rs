@@ -0,0 +1,1277 @@
+//! Helpers to test eureka clients without a eureka server, behind the `test-util` feature
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use percent_encoding::percent_decode;
+use strong_xml::XmlWrite;
+
+use crate::rest::codec::{Codec, JsonCodec, XmlCodec};
+use crate::rest::structures::{
+    AppMetaDataType, Application, Applications, Instance, PortData, SecurePort, StatusType,
+};
+use crate::rest::{EurekaApi, SharedInstanceIter};
+use crate::{EurekaConfig, EurekaError, ParseError, StatusCode};
+
+/// Instances registered with a [`MockEurekaServer`], by upper-cased app name
+type Registry = Arc<Mutex<BTreeMap<String, Vec<Instance>>>>;
+
+/// Eureka degradations injected by a [`MockEurekaServer`] or a [`FaultyApi`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Faults {
+    /// Delay added to each request
+    pub latency: Duration,
+    /// Number of the next requests failing with a 503
+    pub server_errors: usize,
+    /// Drop the heartbeats, the connection is closed without an answer
+    pub drop_heartbeats: bool,
+    /// Number of the next instance status updates failing with a 503
+    pub status_errors: usize,
+    /// Answer the registry queries with the registry as it was when this was set
+    pub stale_registry: bool,
+}
+
+/// Faults injected, with the registry snapshot of `stale_registry`
+#[derive(Debug, Default)]
+struct FaultState {
+    faults: Faults,
+    snapshot: Option<BTreeMap<String, Vec<Instance>>>,
+}
+
+/// Request some of the faults are limited to
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    Heartbeat,
+    StatusUpdate,
+    Other,
+}
+
+/// What to do with a request
+enum Injected {
+    Answer,
+    Fail,
+    Drop,
+}
+
+impl FaultState {
+    /// Waits for the latency, then consumes a server error if any
+    fn inject(state: &Mutex<FaultState>, target: Target) -> Injected {
+        let latency = state.lock().unwrap().faults.latency;
+        if latency > Duration::from_secs(0) {
+            thread::sleep(latency);
+        }
+        let mut state = state.lock().unwrap();
+        if state.faults.server_errors > 0 {
+            state.faults.server_errors -= 1;
+            Injected::Fail
+        } else if target == Target::StatusUpdate && state.faults.status_errors > 0 {
+            state.faults.status_errors -= 1;
+            Injected::Fail
+        } else if target == Target::Heartbeat && state.faults.drop_heartbeats {
+            Injected::Drop
+        } else {
+            Injected::Answer
+        }
+    }
+}
+
+/// In-process eureka server keeping its registry in memory, e.g. for integration tests
+///
+/// It serves the registration, heartbeat, deregistration, status, metadata and ASG status
+/// updates and the `/apps` queries under `/eureka`, in XML or in JSON when asked with
+/// `Accept`. Heartbeats don't renew any lease, instances stay registered until they
+/// deregister.
+#[derive(Debug)]
+pub struct MockEurekaServer {
+    addr: SocketAddr,
+    registry: Registry,
+    faults: Arc<Mutex<FaultState>>,
+    is_running: Arc<AtomicBool>,
+}
+
+impl MockEurekaServer {
+    pub const SERVICE_PATH: &'static str = "/eureka";
+
+    /// Starts a server on a free local port, serving until it is dropped
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let server = MockEurekaServer {
+            addr: listener.local_addr()?,
+            registry: Arc::new(Mutex::new(BTreeMap::new())),
+            faults: Arc::new(Mutex::new(FaultState::default())),
+            is_running: Arc::new(AtomicBool::new(true)),
+        };
+
+        let registry = Arc::clone(&server.registry);
+        let faults = Arc::clone(&server.faults);
+        let is_running = Arc::clone(&server.is_running);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !is_running.load(Ordering::Relaxed) {
+                    break;
+                }
+                match stream {
+                    Ok(stream) => {
+                        let registry = Arc::clone(&registry);
+                        let faults = Arc::clone(&faults);
+                        thread::spawn(move || {
+                            if let Err(e) = handle_connection(&registry, &faults, stream) {
+                                warn!("Mock eureka server failed to answer: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => warn!("Mock eureka server failed to accept: {}", e),
+                }
+            }
+        });
+        Ok(server)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Service url of the server, e.g. for `service_urls`
+    pub fn service_url(&self) -> String {
+        format!("http://{}{}", self.addr, MockEurekaServer::SERVICE_PATH)
+    }
+
+    /// Eureka config pointing at the server
+    pub fn config(&self) -> EurekaConfig {
+        EurekaConfig {
+            host: self.addr.ip().to_string(),
+            port: self.addr.port(),
+            service_path: MockEurekaServer::SERVICE_PATH.to_string(),
+            ..EurekaConfig::default()
+        }
+    }
+
+    /// Registers `instance` as if it registered itself
+    pub fn register(&self, instance: Instance) {
+        register(&mut self.registry.lock().unwrap(), instance);
+    }
+
+    /// Instances of `app`, in registration order
+    pub fn instances(&self, app: &str) -> Vec<Instance> {
+        self.registry
+            .lock()
+            .unwrap()
+            .get(&app.to_uppercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Copy of the registry
+    pub fn applications(&self) -> Applications {
+        applications_of(&self.registry.lock().unwrap())
+    }
+
+    /// Injects `faults` in the next requests, [`Faults::default`] to stop
+    pub fn set_faults(&self, faults: Faults) {
+        let mut state = self.faults.lock().unwrap();
+        state.snapshot = if faults.stale_registry {
+            state
+                .snapshot
+                .take()
+                .or_else(|| Some(self.registry.lock().unwrap().clone()))
+        } else {
+            None
+        };
+        state.faults = faults;
+    }
+
+    /// Faults still injected, e.g. the server errors left
+    pub fn faults(&self) -> Faults {
+        self.faults.lock().unwrap().faults
+    }
+}
+
+impl Drop for MockEurekaServer {
+    fn drop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+        // Wakes the listener up so that it sees it is stopped
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+struct Request {
+    method: String,
+    path: Vec<String>,
+    query: Vec<(String, String)>,
+    json: bool,
+    body: Vec<u8>,
+}
+
+struct Answer {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Answer {
+    fn empty(status: &'static str) -> Self {
+        Answer {
+            status,
+            content_type: "text/plain",
+            body: Vec::new(),
+        }
+    }
+}
+
+fn handle_connection(
+    registry: &Registry,
+    faults: &Mutex<FaultState>,
+    mut stream: TcpStream,
+) -> io::Result<()> {
+    let request = read_request(&mut stream)?;
+    let target = match (request.method.as_str(), request.path.len()) {
+        ("PUT", 4) => Target::Heartbeat,
+        ("PUT", 5) if request.path[4] == "status" => Target::StatusUpdate,
+        _ => Target::Other,
+    };
+    let answer = match FaultState::inject(faults, target) {
+        Injected::Answer => match (request.method.as_str(), &faults.lock().unwrap().snapshot) {
+            ("GET", Some(snapshot)) => answer(&Mutex::new(snapshot.clone()), &request),
+            _ => answer(registry, &request),
+        },
+        Injected::Fail => Answer::empty("503 Service Unavailable"),
+        Injected::Drop => return Ok(()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        answer.status,
+        answer.content_type,
+        answer.body.len()
+    )?;
+    stream.write_all(&answer.body)?;
+    stream.flush()
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut json = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("accept")
+                || name.eq_ignore_ascii_case("content-type")
+            {
+                json |= value.contains("json");
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+    Ok(Request {
+        method,
+        path: path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(decode)
+            .collect(),
+        query: query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (decode(key), decode(value)))
+            .collect(),
+        json,
+        body,
+    })
+}
+
+fn decode(value: &str) -> String {
+    percent_decode(value.as_bytes())
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+fn answer(registry: &Mutex<BTreeMap<String, Vec<Instance>>>, request: &Request) -> Answer {
+    let codec: &dyn Codec = if request.json { &JsonCodec } else { &XmlCodec };
+    let path: Vec<&str> = request.path.iter().map(String::as_str).collect();
+    let mut registry = registry.lock().unwrap();
+    match (request.method.as_str(), path.as_slice()) {
+        ("GET", ["eureka", "apps"]) => encoded(
+            codec,
+            codec.encode_applications(&applications_of(&registry)),
+        ),
+        ("GET", ["eureka", "apps", app]) => match registry.get(&app.to_uppercase()) {
+            Some(instances) => encoded(codec, encode_application(request.json, app, instances)),
+            None => Answer::empty("404 Not Found"),
+        },
+        ("GET", ["eureka", "apps", app, id]) => match find(&mut registry, app, id) {
+            Some(instance) => encoded(codec, codec.encode_instance(instance)),
+            None => Answer::empty("404 Not Found"),
+        },
+        ("POST", ["eureka", "apps", _]) => match codec.decode_instance(&request.body) {
+            Ok(instance) => {
+                register(&mut registry, instance);
+                Answer::empty("204 No Content")
+            }
+            Err(e) => Answer {
+                body: e.to_string().into_bytes(),
+                ..Answer::empty("400 Bad Request")
+            },
+        },
+        ("PUT", ["eureka", "apps", app, id]) => match find(&mut registry, app, id) {
+            Some(_) => Answer::empty("200 OK"),
+            None => Answer::empty("404 Not Found"),
+        },
+        ("DELETE", ["eureka", "apps", app, id]) => match registry.get_mut(&app.to_uppercase()) {
+            Some(instances) if instances.iter().any(|i| instance_id(i) == *id) => {
+                instances.retain(|i| instance_id(i) != *id);
+                Answer::empty("200 OK")
+            }
+            _ => Answer::empty("404 Not Found"),
+        },
+        ("PUT", ["eureka", "apps", app, id, "status"]) => {
+            let status = request
+                .query
+                .iter()
+                .find(|(key, _)| key == "value")
+                .and_then(|(_, value)| value.parse::<StatusType>().ok());
+            match (find(&mut registry, app, id), status) {
+                (Some(instance), Some(status)) => {
+                    instance.status = status;
+                    Answer::empty("200 OK")
+                }
+                (None, _) => Answer::empty("404 Not Found"),
+                (_, None) => Answer::empty("400 Bad Request"),
+            }
+        }
+        ("PUT", ["eureka", "apps", app, id, "metadata"]) => match find(&mut registry, app, id) {
+            Some(instance) => {
+                for (key, value) in &request.query {
+                    instance.set_metadata_value(key, value);
+                }
+                Answer::empty("200 OK")
+            }
+            None => Answer::empty("404 Not Found"),
+        },
+        ("PUT", ["eureka", "asg", asg, "status"]) => {
+            // Instances of a disabled ASG are taken out of service, as eureka does
+            let status = match request.query.iter().find(|(key, _)| key == "value") {
+                Some((_, value)) if value == "ENABLED" => StatusType::Up,
+                Some((_, value)) if value == "DISABLED" => StatusType::OutOfService,
+                _ => return Answer::empty("400 Bad Request"),
+            };
+            registry
+                .values_mut()
+                .flatten()
+                .filter(|instance| instance.asg_name.as_deref() == Some(*asg))
+                .for_each(|instance| instance.status = status);
+            Answer::empty("200 OK")
+        }
+        _ => Answer::empty("404 Not Found"),
+    }
+}
+
+fn encoded(codec: &dyn Codec, body: Result<Vec<u8>, EurekaError>) -> Answer {
+    match body {
+        Ok(body) => Answer {
+            status: "200 OK",
+            content_type: codec.content_type(),
+            body,
+        },
+        Err(e) => Answer {
+            body: e.to_string().into_bytes(),
+            ..Answer::empty("500 Internal Server Error")
+        },
+    }
+}
+
+/// `Codec` has no application encoding, clients never send this document
+fn encode_application(
+    json: bool,
+    app: &str,
+    instances: &[Instance],
+) -> Result<Vec<u8>, EurekaError> {
+    let application = Application {
+        name: app.to_uppercase(),
+        instances: instances.to_vec(),
+    };
+    if json {
+        serde_json::to_vec(&serde_json::json!({ "application": application }))
+            .map_err(|e| EurekaError::ParseError(ParseError::new(e.to_string())))
+    } else {
+        application
+            .to_string()
+            .map(String::into_bytes)
+            .map_err(|e| EurekaError::ParseError(ParseError::new(format!("{:?}", e))))
+    }
+}
+
+fn instance_id(instance: &Instance) -> &str {
+    instance
+        .instance_id
+        .as_deref()
+        .unwrap_or(&instance.host_name)
+}
+
+fn find<'a>(
+    registry: &'a mut BTreeMap<String, Vec<Instance>>,
+    app: &str,
+    id: &str,
+) -> Option<&'a mut Instance> {
+    registry
+        .get_mut(&app.to_uppercase())?
+        .iter_mut()
+        .find(|instance| instance_id(instance) == id)
+}
+
+/// Adds `instance` to the registry, replacing a previous registration of the same id
+fn register(registry: &mut BTreeMap<String, Vec<Instance>>, instance: Instance) {
+    let instances = registry.entry(instance.app.to_uppercase()).or_default();
+    instances.retain(|registered| instance_id(registered) != instance_id(&instance));
+    instances.push(instance);
+}
+
+fn applications_of(registry: &BTreeMap<String, Vec<Instance>>) -> Applications {
+    Applications {
+        versions_delta: Some("1".to_string()),
+        apps_hashcode: None,
+        applications: registry
+            .iter()
+            .filter(|(_, instances)| !instances.is_empty())
+            .map(|(name, instances)| Application {
+                name: name.clone(),
+                instances: instances.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// [`EurekaApi`] injecting faults in front of another one, e.g. to degrade a real server
+///
+/// Dropped heartbeats fail with a timed out I/O error. The stale registry is the one of the
+/// first registry query after `stale_registry` was set.
+#[derive(Debug)]
+pub struct FaultyApi {
+    inner: Arc<dyn EurekaApi>,
+    faults: Mutex<FaultState>,
+    stale_instances: Mutex<Option<Vec<Instance>>>,
+}
+
+impl FaultyApi {
+    pub fn new(inner: Arc<dyn EurekaApi>) -> Self {
+        FaultyApi {
+            inner,
+            faults: Mutex::new(FaultState::default()),
+            stale_instances: Mutex::new(None),
+        }
+    }
+
+    /// Injects `faults` in the next requests, [`Faults::default`] to stop
+    pub fn set_faults(&self, faults: Faults) {
+        if !faults.stale_registry {
+            *self.stale_instances.lock().unwrap() = None;
+        }
+        self.faults.lock().unwrap().faults = faults;
+    }
+
+    /// Faults still injected, e.g. the server errors left
+    pub fn faults(&self) -> Faults {
+        self.faults.lock().unwrap().faults
+    }
+
+    fn inject(&self, target: Target) -> Result<(), EurekaError> {
+        match FaultState::inject(&self.faults, target) {
+            Injected::Answer => Ok(()),
+            Injected::Fail => Err(EurekaError::Request(StatusCode::SERVICE_UNAVAILABLE)),
+            Injected::Drop => Err(EurekaError::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "heartbeat dropped",
+            ))),
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        self.faults.lock().unwrap().faults.stale_registry
+    }
+}
+
+impl EurekaApi for FaultyApi {
+    fn register(&self, app_id: &str, data: &Instance) -> Result<(), EurekaError> {
+        self.inject(Target::Other)?;
+        self.inner.register(app_id, data)
+    }
+
+    fn deregister(&self, app_id: &str, instance_id: &str) -> Result<(), EurekaError> {
+        self.inject(Target::Other)?;
+        self.inner.deregister(app_id, instance_id)
+    }
+
+    fn send_heartbeat(&self, app_id: &str, instance_id: &str) -> Result<(), EurekaError> {
+        self.inject(Target::Heartbeat)?;
+        self.inner.send_heartbeat(app_id, instance_id)
+    }
+
+    fn get_all_instances(&self) -> Result<Vec<Instance>, EurekaError> {
+        self.inject(Target::Other)?;
+        if !self.is_stale() {
+            return self.inner.get_all_instances();
+        }
+        let mut stale_instances = self.stale_instances.lock().unwrap();
+        if stale_instances.is_none() {
+            *stale_instances = Some(self.inner.get_all_instances()?);
+        }
+        Ok(stale_instances.clone().unwrap_or_default())
+    }
+
+    fn get_instances_by_app(&self, app_id: &str) -> Result<Vec<Instance>, EurekaError> {
+        self.inject(Target::Other)?;
+        self.inner.get_instances_by_app(app_id)
+    }
+
+    fn get_instance_by_app_and_instance(
+        &self,
+        app_id: &str,
+        instance_id: &str,
+    ) -> Result<Instance, EurekaError> {
+        self.inject(Target::Other)?;
+        self.inner
+            .get_instance_by_app_and_instance(app_id, instance_id)
+    }
+
+    fn update_status(
+        &self,
+        app_id: &str,
+        instance_id: &str,
+        new_status: StatusType,
+    ) -> Result<(), EurekaError> {
+        self.inject(Target::StatusUpdate)?;
+        self.inner.update_status(app_id, instance_id, new_status)
+    }
+
+    fn delete_status_override(
+        &self,
+        app_id: &str,
+        instance_id: &str,
+        new_status: Option<StatusType>,
+    ) -> Result<(), EurekaError> {
+        self.inject(Target::Other)?;
+        self.inner
+            .delete_status_override(app_id, instance_id, new_status)
+    }
+
+    fn update_metadata(
+        &self,
+        app_id: &str,
+        instance_id: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), EurekaError> {
+        self.inject(Target::Other)?;
+        self.inner.update_metadata(app_id, instance_id, key, value)
+    }
+
+    fn update_asg_status(&self, asg_name: &str, enabled: bool) -> Result<(), EurekaError> {
+        self.inject(Target::Other)?;
+        self.inner.update_asg_status(asg_name, enabled)
+    }
+
+    fn get_instances_by_vip_address(
+        &self,
+        vip_address: &str,
+    ) -> Result<Vec<Instance>, EurekaError> {
+        self.inject(Target::Other)?;
+        self.inner.get_instances_by_vip_address(vip_address)
+    }
+
+    fn get_instances_by_svip_address(
+        &self,
+        svip_address: &str,
+    ) -> Result<Vec<Instance>, EurekaError> {
+        self.inject(Target::Other)?;
+        self.inner.get_instances_by_svip_address(svip_address)
+    }
+}
+
+/// [`EurekaApi`] answering the registry queries with responses recorded in a directory,
+/// e.g. by [`capture`]
+///
+/// The response to a query path is read from the file of the same path with the extension
+/// of the codec, `apps.xml` for `/apps` or `apps/ORDERS.xml` for `/apps/ORDERS`. Successive
+/// queries of a path are answered with `apps.xml`, `apps.2.xml`, `apps.3.xml` and so on,
+/// then with the last file, so that cache updates can be replayed. A query without a
+/// recorded response fails with a 404. Registrations, heartbeats and updates succeed
+/// without effect.
+#[derive(Debug)]
+pub struct ReplayApi {
+    dir: PathBuf,
+    codec: Arc<dyn Codec>,
+    replayed: Mutex<HashMap<String, usize>>,
+}
+
+impl ReplayApi {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        ReplayApi {
+            dir: dir.as_ref().to_path_buf(),
+            codec: Arc::new(XmlCodec),
+            replayed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Decode the recorded responses with `codec`, e.g. JSON ones
+    pub fn with_codec(mut self, codec: Arc<dyn Codec>) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Next recorded response of `path`
+    fn replay(&self, path: &str) -> Result<Vec<u8>, EurekaError> {
+        let mut replayed = self.replayed.lock().unwrap();
+        let count = replayed.entry(path.to_string()).or_insert(0);
+        let next = replay_file(&self.dir, path, self.codec.as_ref(), *count + 1);
+        let file = if next.exists() {
+            *count += 1;
+            next
+        } else {
+            replay_file(&self.dir, path, self.codec.as_ref(), (*count).max(1))
+        };
+        fs::read(&file).map_err(|e| {
+            debug!("No recorded response {}: {}", file.display(), e);
+            EurekaError::Request(StatusCode::NOT_FOUND)
+        })
+    }
+
+    fn replay_instances(&self, path: &str) -> Result<Vec<Instance>, EurekaError> {
+        let apps = self.codec.decode_applications(&self.replay(path)?)?;
+        Ok(apps
+            .applications
+            .into_iter()
+            .flat_map(|app| app.instances)
+            .collect())
+    }
+}
+
+impl EurekaApi for ReplayApi {
+    fn register(&self, _: &str, _: &Instance) -> Result<(), EurekaError> {
+        Ok(())
+    }
+
+    fn deregister(&self, _: &str, _: &str) -> Result<(), EurekaError> {
+        Ok(())
+    }
+
+    fn send_heartbeat(&self, _: &str, _: &str) -> Result<(), EurekaError> {
+        Ok(())
+    }
+
+    fn get_all_instances(&self) -> Result<Vec<Instance>, EurekaError> {
+        self.replay_instances("/apps")
+    }
+
+    fn stream_all_instances_reusing(
+        &self,
+        known: &HashMap<String, Vec<Arc<Instance>>>,
+    ) -> Result<SharedInstanceIter, EurekaError> {
+        let body = self.replay("/apps")?;
+        self.codec
+            .stream_instances_reusing(Box::new(io::Cursor::new(body)), known)
+    }
+
+    fn get_instances_by_app(&self, app_id: &str) -> Result<Vec<Instance>, EurekaError> {
+        let body = self.replay(&format!("/apps/{}", app_id))?;
+        Ok(self.codec.decode_application(&body)?.instances)
+    }
+
+    fn get_instance_by_app_and_instance(
+        &self,
+        app_id: &str,
+        instance_id: &str,
+    ) -> Result<Instance, EurekaError> {
+        let body = self.replay(&format!("/apps/{}/{}", app_id, instance_id))?;
+        self.codec.decode_instance(&body)
+    }
+
+    fn update_status(&self, _: &str, _: &str, _: StatusType) -> Result<(), EurekaError> {
+        Ok(())
+    }
+
+    fn delete_status_override(
+        &self,
+        _: &str,
+        _: &str,
+        _: Option<StatusType>,
+    ) -> Result<(), EurekaError> {
+        Ok(())
+    }
+
+    fn update_metadata(&self, _: &str, _: &str, _: &str, _: &str) -> Result<(), EurekaError> {
+        Ok(())
+    }
+
+    fn update_asg_status(&self, _: &str, _: bool) -> Result<(), EurekaError> {
+        Ok(())
+    }
+
+    fn get_instances_by_vip_address(
+        &self,
+        vip_address: &str,
+    ) -> Result<Vec<Instance>, EurekaError> {
+        self.replay_instances(&format!("/vips/{}", vip_address))
+    }
+
+    fn get_instances_by_svip_address(
+        &self,
+        svip_address: &str,
+    ) -> Result<Vec<Instance>, EurekaError> {
+        self.replay_instances(&format!("/svips/{}", svip_address))
+    }
+}
+
+/// Records the response of a eureka server to the query `path`, e.g. `/apps`, for a
+/// [`ReplayApi`] reading `dir`
+///
+/// Each capture of a path is recorded after the previous ones, returns the recorded file.
+pub fn capture<P: AsRef<Path>>(
+    service_url: &str,
+    path: &str,
+    codec: &dyn Codec,
+    dir: P,
+) -> Result<PathBuf, EurekaError> {
+    let url = format!("{}{}", service_url.trim_end_matches('/'), path);
+    let mut resp = reqwest::Client::new()
+        .get(&url)
+        .header(reqwest::header::ACCEPT, codec.content_type())
+        .send()
+        .map_err(EurekaError::network)?;
+    if resp.status() != StatusCode::OK {
+        return Err(EurekaError::Request(resp.status()));
+    }
+    let mut body = Vec::new();
+    resp.copy_to(&mut body).map_err(EurekaError::network)?;
+
+    let file = (1..)
+        .map(|count| replay_file(dir.as_ref(), path, codec, count))
+        .find(|file| !file.exists())
+        .unwrap_or_default();
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent).map_err(EurekaError::Io)?;
+    }
+    fs::write(&file, body).map_err(EurekaError::Io)?;
+    Ok(file)
+}
+
+/// `dir/apps.xml` for the first response to `/apps`, `dir/apps.2.xml` for the second one
+fn replay_file(dir: &Path, path: &str, codec: &dyn Codec, count: usize) -> PathBuf {
+    let extension = if codec.content_type().contains("json") {
+        "json"
+    } else {
+        "xml"
+    };
+    let name = path.trim_matches('/');
+    if count <= 1 {
+        dir.join(format!("{}.{}", name, extension))
+    } else {
+        dir.join(format!("{}.{}.{}", name, count, extension))
+    }
+}
+
+/// Starts an [`InstanceFixture`] of `app`, e.g.
+/// `instance("APP").port(8080).up().zone("us-east-1a").build()`
+pub fn instance(app: &str) -> InstanceFixture {
+    InstanceFixture {
+        instance: Instance {
+            app: app.to_string(),
+            vip_address: app.to_lowercase(),
+            secure_vip_address: app.to_lowercase(),
+            port: PortData::new(8080, true),
+            ..Instance::default()
+        },
+    }
+}
+
+/// Instance of a test registry, unlike [`InstanceBuilder`](crate::InstanceBuilder) any
+/// state can be built, e.g. to test the handling of broken instances
+///
+/// The instance id defaults to `host:app:port`.
+#[derive(Debug, Clone)]
+pub struct InstanceFixture {
+    instance: Instance,
+}
+
+impl InstanceFixture {
+    pub fn id(mut self, instance_id: &str) -> Self {
+        self.instance.instance_id = Some(instance_id.to_string());
+        self
+    }
+
+    pub fn host_name(mut self, host_name: &str) -> Self {
+        self.instance.host_name = host_name.to_string();
+        self
+    }
+
+    pub fn ip_addr(mut self, ip_addr: &str) -> Self {
+        self.instance.ip_addr = ip_addr.to_string();
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.instance.port = PortData::new(port, true);
+        self
+    }
+
+    /// Enables the secure port
+    pub fn secure_port(mut self, port: u16) -> Self {
+        self.instance.secure_port = SecurePort::new(port, true);
+        self
+    }
+
+    pub fn status(mut self, status: StatusType) -> Self {
+        self.instance.status = status;
+        self
+    }
+
+    pub fn up(self) -> Self {
+        self.status(StatusType::Up)
+    }
+
+    pub fn down(self) -> Self {
+        self.status(StatusType::Down)
+    }
+
+    pub fn out_of_service(self) -> Self {
+        self.status(StatusType::OutOfService)
+    }
+
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.instance.set_metadata_value(key, value);
+        self
+    }
+
+    pub fn zone(self, zone: &str) -> Self {
+        self.metadata(AppMetaDataType::ZONE, zone)
+    }
+
+    pub fn version(self, version: &str) -> Self {
+        self.metadata(AppMetaDataType::VERSION, version)
+    }
+
+    pub fn build(mut self) -> Instance {
+        if self.instance.instance_id.is_none() {
+            self.instance.instance_id = Some(format!(
+                "{}:{}:{}",
+                self.instance.host_name, self.instance.app, self.instance.port.value
+            ));
+        }
+        self.instance
+    }
+}
+
+/// Registry of `instances`, grouped by app
+pub fn applications<I: IntoIterator<Item = Instance>>(instances: I) -> Applications {
+    let mut registry = BTreeMap::new();
+    for instance in instances {
+        registry
+            .entry(instance.app.clone())
+            .or_insert_with(Vec::new)
+            .push(instance);
+    }
+    applications_of(&registry)
+}
+
+/// Registry of `apps` apps named `APP-0000`, `APP-0001`... of `instances` instances each,
+/// e.g. to measure the parsing and caching of large registries
+///
+/// Instances are spread over three zones, one in ten is DOWN.
+pub fn synthetic_applications(apps: usize, instances: usize) -> Applications {
+    applications((0..apps).flat_map(|app| {
+        (0..instances).map(move |index| {
+            let host_name = format!("app-{:04}-{:04}", app, index);
+            let fixture = instance(&format!("APP-{:04}", app))
+                .id(&format!("{}:{}", host_name, 8080))
+                .host_name(&host_name)
+                .ip_addr(&format!(
+                    "10.{}.{}.{}",
+                    app / 256 % 256,
+                    app % 256,
+                    index % 256
+                ))
+                .zone(["us-east-1a", "us-east-1b", "us-east-1c"][index % 3])
+                .version("1.0.0");
+            if index % 10 == 9 {
+                fixture.down().build()
+            } else {
+                fixture.up().build()
+            }
+        })
+    }))
+}
+
+/// Registry answered by a eureka server to `GET /eureka/apps` in XML: `ORDERS` with an UP
+/// and a DOWN instance in two zones, `PAYMENTS` with an UP instance on its secure port
+pub const APPLICATIONS_XML: &str = r#"<applications>
+  <versions__delta>1</versions__delta>
+  <apps__hashcode>DOWN_1_UP_2_</apps__hashcode>
+  <application>
+    <name>ORDERS</name>
+    <instance>
+      <instanceId>orders-1:orders:8080</instanceId>
+      <hostName>orders-1</hostName>
+      <app>ORDERS</app>
+      <ipAddr>10.0.1.10</ipAddr>
+      <status>UP</status>
+      <overriddenstatus>UNKNOWN</overriddenstatus>
+      <port enabled="true">8080</port>
+      <securePort enabled="false">443</securePort>
+      <countryId>1</countryId>
+      <dataCenterInfo class="com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo">
+        <name>MyOwn</name>
+      </dataCenterInfo>
+      <leaseInfo>
+        <renewalIntervalInSecs>30</renewalIntervalInSecs>
+        <durationInSecs>90</durationInSecs>
+      </leaseInfo>
+      <metadata>
+        <zone>us-east-1a</zone>
+        <version>1.2.0</version>
+      </metadata>
+      <homePageUrl>http://orders-1:8080/</homePageUrl>
+      <statusPageUrl>http://orders-1:8080/actuator/info</statusPageUrl>
+      <healthCheckUrl>http://orders-1:8080/actuator/health</healthCheckUrl>
+      <vipAddress>orders</vipAddress>
+      <secureVipAddress>orders</secureVipAddress>
+      <isCoordinatingDiscoveryServer>false</isCoordinatingDiscoveryServer>
+      <lastUpdatedTimestamp>1616761261538</lastUpdatedTimestamp>
+      <lastDirtyTimestamp>1616761261439</lastDirtyTimestamp>
+      <actionType>ADDED</actionType>
+    </instance>
+    <instance>
+      <instanceId>orders-2:orders:8080</instanceId>
+      <hostName>orders-2</hostName>
+      <app>ORDERS</app>
+      <ipAddr>10.0.2.10</ipAddr>
+      <status>DOWN</status>
+      <overriddenstatus>UNKNOWN</overriddenstatus>
+      <port enabled="true">8080</port>
+      <securePort enabled="false">443</securePort>
+      <countryId>1</countryId>
+      <dataCenterInfo class="com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo">
+        <name>MyOwn</name>
+      </dataCenterInfo>
+      <metadata>
+        <zone>us-east-1b</zone>
+        <version>1.2.0</version>
+      </metadata>
+      <homePageUrl>http://orders-2:8080/</homePageUrl>
+      <statusPageUrl>http://orders-2:8080/actuator/info</statusPageUrl>
+      <healthCheckUrl>http://orders-2:8080/actuator/health</healthCheckUrl>
+      <vipAddress>orders</vipAddress>
+      <secureVipAddress>orders</secureVipAddress>
+      <actionType>MODIFIED</actionType>
+    </instance>
+  </application>
+  <application>
+    <name>PAYMENTS</name>
+    <instance>
+      <instanceId>payments-1:payments:8443</instanceId>
+      <hostName>payments-1</hostName>
+      <app>PAYMENTS</app>
+      <ipAddr>10.0.1.20</ipAddr>
+      <status>UP</status>
+      <port enabled="false">8080</port>
+      <securePort enabled="true">8443</securePort>
+      <countryId>1</countryId>
+      <dataCenterInfo class="com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo">
+        <name>MyOwn</name>
+      </dataCenterInfo>
+      <metadata class="java.util.Collections$EmptyMap"/>
+      <homePageUrl>https://payments-1:8443/</homePageUrl>
+      <statusPageUrl>https://payments-1:8443/actuator/info</statusPageUrl>
+      <healthCheckUrl>https://payments-1:8443/actuator/health</healthCheckUrl>
+      <vipAddress>payments</vipAddress>
+      <secureVipAddress>payments</secureVipAddress>
+      <actionType>ADDED</actionType>
+    </instance>
+  </application>
+</applications>"#;
+
+/// Same registry as [`APPLICATIONS_XML`] in the JSON encoding of eureka
+pub const APPLICATIONS_JSON: &str = r#"{
+  "applications": {
+    "versions__delta": "1",
+    "apps__hashcode": "DOWN_1_UP_2_",
+    "application": [{
+      "name": "ORDERS",
+      "instance": [{
+        "instanceId": "orders-1:orders:8080",
+        "hostName": "orders-1",
+        "app": "ORDERS",
+        "ipAddr": "10.0.1.10",
+        "status": "UP",
+        "overriddenStatus": "UNKNOWN",
+        "port": {"$": 8080, "@enabled": "true"},
+        "securePort": {"$": 443, "@enabled": "false"},
+        "countryId": 1,
+        "dataCenterInfo": {
+          "@class": "com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo",
+          "name": "MyOwn"
+        },
+        "leaseInfo": {"renewalIntervalInSecs": 30, "durationInSecs": 90},
+        "metadata": {"zone": "us-east-1a", "version": "1.2.0"},
+        "homePageUrl": "http://orders-1:8080/",
+        "statusPageUrl": "http://orders-1:8080/actuator/info",
+        "healthCheckUrl": "http://orders-1:8080/actuator/health",
+        "vipAddress": "orders",
+        "secureVipAddress": "orders",
+        "isCoordinatingDiscoveryServer": "false",
+        "lastUpdatedTimestamp": "1616761261538",
+        "lastDirtyTimestamp": "1616761261439",
+        "actionType": "ADDED"
+      }, {
+        "instanceId": "orders-2:orders:8080",
+        "hostName": "orders-2",
+        "app": "ORDERS",
+        "ipAddr": "10.0.2.10",
+        "status": "DOWN",
+        "overriddenStatus": "UNKNOWN",
+        "port": {"$": 8080, "@enabled": "true"},
+        "securePort": {"$": 443, "@enabled": "false"},
+        "countryId": 1,
+        "dataCenterInfo": {
+          "@class": "com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo",
+          "name": "MyOwn"
+        },
+        "metadata": {"zone": "us-east-1b", "version": "1.2.0"},
+        "homePageUrl": "http://orders-2:8080/",
+        "statusPageUrl": "http://orders-2:8080/actuator/info",
+        "healthCheckUrl": "http://orders-2:8080/actuator/health",
+        "vipAddress": "orders",
+        "secureVipAddress": "orders",
+        "actionType": "MODIFIED"
+      }]
+    }, {
+      "name": "PAYMENTS",
+      "instance": {
+        "instanceId": "payments-1:payments:8443",
+        "hostName": "payments-1",
+        "app": "PAYMENTS",
+        "ipAddr": "10.0.1.20",
+        "status": "UP",
+        "port": {"$": 8080, "@enabled": "false"},
+        "securePort": {"$": 8443, "@enabled": "true"},
+        "countryId": 1,
+        "dataCenterInfo": {
+          "@class": "com.netflix.appinfo.InstanceInfo$DefaultDataCenterInfo",
+          "name": "MyOwn"
+        },
+        "metadata": {"@class": "java.util.Collections$EmptyMap"},
+        "homePageUrl": "https://payments-1:8443/",
+        "statusPageUrl": "https://payments-1:8443/actuator/info",
+        "healthCheckUrl": "https://payments-1:8443/actuator/health",
+        "vipAddress": "payments",
+        "secureVipAddress": "payments",
+        "actionType": "ADDED"
+      }
+    }]
+  }
+}"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::EurekaRestClient;
+
+    #[test]
+    fn test_mock_server() {
+        let server = MockEurekaServer::start().unwrap();
+        let mut instance = Instance::default();
+        instance.app = "my-app".to_string();
+        instance.instance_id = Some("my-app:1".to_string());
+
+        for codec in [
+            Arc::new(XmlCodec) as Arc<dyn Codec>,
+            Arc::new(JsonCodec) as Arc<dyn Codec>,
+        ] {
+            let client = EurekaRestClient::new(server.service_url()).with_codec(codec);
+            client.register(&instance.app, &instance).unwrap();
+            client.send_heartbeat("my-app", "my-app:1").unwrap();
+            client
+                .update_status("my-app", "my-app:1", StatusType::Up)
+                .unwrap();
+            client
+                .update_metadata("my-app", "my-app:1", "zone", "zone-a")
+                .unwrap();
+
+            let instances = client.get_all_instances().unwrap();
+            assert_eq!(instances.len(), 1);
+            assert_eq!(instances[0].status, StatusType::Up);
+            assert_eq!(instances[0].zone(), Some("zone-a"));
+            assert_eq!(client.get_instances_by_app("MY-APP").unwrap().len(), 1);
+
+            client.deregister("my-app", "my-app:1").unwrap();
+            assert!(server.instances("my-app").is_empty());
+            assert!(client.send_heartbeat("my-app", "my-app:1").is_err());
+        }
+    }
+
+    #[test]
+    fn test_mock_server_asg_status() {
+        let server = MockEurekaServer::start().unwrap();
+        let client = EurekaRestClient::new(server.service_url());
+        let mut member = instance("ORDERS").id("orders-1").up().build();
+        member.asg_name = Some("orders-v1".to_string());
+        server.register(member);
+        server.register(instance("ORDERS").id("orders-2").up().build());
+
+        client.update_asg_status("orders-v1", false).unwrap();
+        let statuses: Vec<_> = server
+            .instances("ORDERS")
+            .iter()
+            .map(|i| i.status)
+            .collect();
+        assert_eq!(statuses, vec![StatusType::OutOfService, StatusType::Up]);
+        client.update_asg_status("orders-v1", true).unwrap();
+        assert_eq!(server.instances("ORDERS")[0].status, StatusType::Up);
+    }
+
+    #[test]
+    fn test_mock_server_faults() {
+        let server = MockEurekaServer::start().unwrap();
+        let client = EurekaRestClient::new(server.service_url());
+        let mut instance = Instance::default();
+        instance.app = "APP".to_string();
+        client.register("APP", &instance).unwrap();
+
+        server.set_faults(Faults {
+            server_errors: 1,
+            drop_heartbeats: true,
+            stale_registry: true,
+            ..Faults::default()
+        });
+        assert!(client.get_all_instances().is_err());
+        assert_eq!(server.faults().server_errors, 0);
+        assert!(client.send_heartbeat("APP", "localhost").is_err());
+
+        let mut other = instance.clone();
+        other.host_name = "other".to_string();
+        server.register(other);
+        assert_eq!(client.get_all_instances().unwrap().len(), 1);
+        server.set_faults(Faults::default());
+        assert_eq!(client.get_all_instances().unwrap().len(), 2);
+        client.send_heartbeat("APP", "localhost").unwrap();
+    }
+
+    #[test]
+    fn test_faulty_api() {
+        let server = MockEurekaServer::start().unwrap();
+        let api = FaultyApi::new(Arc::new(EurekaRestClient::new(server.service_url())));
+        api.set_faults(Faults {
+            server_errors: 1,
+            drop_heartbeats: true,
+            ..Faults::default()
+        });
+        assert!(api.register("APP", &Instance::default()).is_err());
+        api.register("APP", &Instance::default()).unwrap();
+        assert!(api.send_heartbeat("APP", "localhost").is_err());
+        api.set_faults(Faults::default());
+        api.send_heartbeat("APP", "localhost").unwrap();
+    }
+
+    #[test]
+    fn test_instance_fixture() {
+        let instance = instance("APP").port(9090).up().zone("us-east-1a").build();
+        assert_eq!(instance.instance_id.as_deref(), Some("localhost:APP:9090"));
+        assert_eq!(instance.port, PortData::new(9090, true));
+        assert_eq!(instance.status, StatusType::Up);
+        assert_eq!(instance.zone(), Some("us-east-1a"));
+
+        let apps = applications(vec![
+            instance.clone(),
+            super::instance("OTHER").build(),
+            super::instance("APP").id("APP:2").down().build(),
+        ]);
+        assert_eq!(apps.applications.len(), 2);
+        assert_eq!(apps.applications[0].instances.len(), 2);
+    }
+
+    #[test]
+    fn test_applications_fixtures() {
+        let xml = XmlCodec
+            .decode_applications(APPLICATIONS_XML.as_bytes())
+            .unwrap();
+        let json = JsonCodec
+            .decode_applications(APPLICATIONS_JSON.as_bytes())
+            .unwrap();
+        assert_eq!(xml.applications.len(), 2);
+        assert_eq!(xml.applications[0].instances.len(), 2);
+        assert_eq!(xml.applications[0].instances[1].zone(), Some("us-east-1b"));
+        assert_eq!(xml.applications[1].instances[0].secure_port.value, 8443);
+        assert_eq!(json.applications.len(), xml.applications.len());
+        for (json, xml) in json.applications.iter().zip(&xml.applications) {
+            assert_eq!(json.instances.len(), xml.instances.len());
+            for (json, xml) in json.instances.iter().zip(&xml.instances) {
+                assert_eq!(json.instance_id, xml.instance_id);
+                assert_eq!(json.status, xml.status);
+                assert_eq!(json.port, xml.port);
+                assert_eq!(json.secure_port, xml.secure_port);
+                assert_eq!(json.zone(), xml.zone());
+            }
+        }
+    }
+
+    #[test]
+    fn test_capture_and_replay() {
+        let dir = std::env::temp_dir().join(format!("eureka-replay-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let server = MockEurekaServer::start().unwrap();
+        server.register(super::instance("ORDERS").up().build());
+        let first = capture(&server.service_url(), "/apps", &XmlCodec, &dir).unwrap();
+        assert_eq!(first, dir.join("apps.xml"));
+        server.register(super::instance("ORDERS").id("ORDERS:2").up().build());
+        let second = capture(&server.service_url(), "/apps", &XmlCodec, &dir).unwrap();
+        assert_eq!(second, dir.join("apps.2.xml"));
+        capture(&server.service_url(), "/apps/ORDERS", &XmlCodec, &dir).unwrap();
+
+        let api = ReplayApi::new(&dir);
+        assert_eq!(api.get_all_instances().unwrap().len(), 1);
+        assert_eq!(api.get_all_instances().unwrap().len(), 2);
+        // The last response is replayed once all have been
+        assert_eq!(api.get_all_instances().unwrap().len(), 2);
+        assert_eq!(api.get_instances_by_app("ORDERS").unwrap().len(), 2);
+        assert!(api.get_instances_by_app("PAYMENTS").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_synthetic_applications() {
+        let apps = synthetic_applications(3, 20);
+        assert_eq!(apps.applications.len(), 3);
+        assert_eq!(apps.applications[2].name, "APP-0002");
+        let instances = &apps.applications[0].instances;
+        assert_eq!(instances.len(), 20);
+        assert_eq!(
+            instances
+                .iter()
+                .filter(|instance| instance.status == StatusType::Down)
+                .count(),
+            2
+        );
+        let xml = XmlCodec.encode_applications(&apps).unwrap();
+        assert_eq!(XmlCodec.decode_applications(&xml).unwrap(), apps);
+    }
+}
@@ -0,0 +1,151 @@
+//! Spans of the eureka operations, recorded with `tracing` when the feature is enabled
+//!
+//! Without the feature these helpers only run the operations.
+
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::StatusCode;
+
+/// Runs the eureka `operation` in a span carrying its app, instance, endpoint, status
+/// and latency, empty `app` and `instance_id` are left out
+pub(crate) fn instrument<T, E, F>(
+    operation: &'static str,
+    app: &str,
+    instance_id: &str,
+    f: F,
+) -> Result<T, E>
+where
+    F: FnOnce() -> Result<T, E>,
+{
+    #[cfg(feature = "tracing")]
+    {
+        use std::time::Instant;
+        use tracing::field::Empty;
+
+        let span = tracing::info_span!(
+            "eureka",
+            operation,
+            app = Empty,
+            instance_id = Empty,
+            endpoint = Empty,
+            status = Empty,
+            latency_ms = Empty,
+            error = Empty,
+        );
+        if !app.is_empty() {
+            span.record("app", app);
+        }
+        if !instance_id.is_empty() {
+            span.record("instance_id", instance_id);
+        }
+        let start = Instant::now();
+        let result = span.in_scope(f);
+        span.record("latency_ms", start.elapsed().as_millis() as u64);
+        if result.is_err() {
+            span.record("error", true);
+        }
+        result
+    }
+    #[cfg(not(feature = "tracing"))]
+    f()
+}
+
+/// Records the eureka server or app instance the current operation is sent to
+pub(crate) fn record_endpoint(endpoint: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("endpoint", endpoint);
+}
+
+/// Records the response status of the current operation
+pub(crate) fn record_status(status: StatusCode) -> StatusCode {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("status", status.as_u16());
+    status
+}
+
+/// Adds the W3C `traceparent` and `tracestate` headers, and the B3 single header when
+/// `b3` is set, of the current OpenTelemetry span. Headers already in `headers` are kept.
+///
+/// The span is the one of the current `tracing` span, or the current OpenTelemetry context.
+pub(crate) fn inject_trace_context(headers: &mut HeaderMap, b3: bool) {
+    #[cfg(feature = "trace-context")]
+    {
+        use opentelemetry::trace::TraceContextExt;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let mut cx = tracing::Span::current().context();
+        if !cx.span().span_context().is_valid() {
+            cx = opentelemetry::Context::current();
+        }
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+        let mut insert = |name: &'static str, value: String| {
+            if headers.contains_key(name) {
+                return;
+            }
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        };
+        insert(
+            "traceparent",
+            format!(
+                "00-{}-{}-{:02x}",
+                span_context.trace_id(),
+                span_context.span_id(),
+                span_context.trace_flags().to_u8()
+            ),
+        );
+        let trace_state = span_context.trace_state().header();
+        if !trace_state.is_empty() {
+            insert("tracestate", trace_state);
+        }
+        if b3 {
+            insert(
+                "b3",
+                format!(
+                    "{}-{}-{}",
+                    span_context.trace_id(),
+                    span_context.span_id(),
+                    if span_context.is_sampled() { 1 } else { 0 }
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "trace-context"))]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+    use opentelemetry::Context;
+
+    #[test]
+    fn test_inject_trace_context() {
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let _guard = Context::new()
+            .with_remote_span_context(span_context)
+            .attach();
+        let mut headers = HeaderMap::new();
+        inject_trace_context(&mut headers, true);
+        assert_eq!(
+            headers["traceparent"],
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        assert_eq!(
+            headers["b3"],
+            "4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1"
+        );
+        assert!(!headers.contains_key("tracestate"));
+    }
+}
@@ -0,0 +1,122 @@
+//! Checks of a [`BaseConfig`] before the client talks to eureka
+
+use crate::{BaseConfig, EurekaError};
+
+/// Lease duration of the eureka servers when the instance doesn't set one
+const DEFAULT_LEASE_DURATION_IN_SECS: u32 = 90;
+
+fn invalid(field: &'static str, message: String) -> Result<(), EurekaError> {
+    Err(EurekaError::InvalidConfig(field, message))
+}
+
+impl BaseConfig {
+    /// Checks the eureka settings and, when the instance registers, the instance, e.g. to
+    /// fail at startup rather than retrying registrations eureka keeps rejecting
+    pub fn validate(&self) -> Result<(), EurekaError> {
+        let eureka = &self.eureka;
+        eureka.validate()?;
+        if eureka.heartbeat_interval == 0 {
+            return invalid("heartbeatInterval", "must not be 0".to_string());
+        }
+        if eureka.fetch_registry && eureka.registry_fetch_interval == 0 {
+            return invalid("registryFetchInterval", "must not be 0".to_string());
+        }
+        for (app, config) in &eureka.apps {
+            config.default_headers(app)?;
+            config.parse_endpoints(app)?;
+        }
+        if !eureka.register_with_eureka {
+            return Ok(());
+        }
+
+        let instance = &self.instance;
+        if instance.app.is_empty() {
+            return invalid("app", "must not be empty".to_string());
+        }
+        if instance.host_name.is_empty() {
+            return invalid("hostName", "must not be empty".to_string());
+        }
+        if !instance.port.enabled && !instance.secure_port.enabled {
+            return invalid(
+                "port",
+                "either port or securePort must be enabled".to_string(),
+            );
+        }
+        if instance.port.enabled && instance.port.value == 0 {
+            return invalid("port", "enabled port must not be 0".to_string());
+        }
+        if instance.secure_port.enabled && instance.secure_port.value == 0 {
+            return invalid(
+                "securePort",
+                "enabled secure port must not be 0".to_string(),
+            );
+        }
+        if eureka.ssl && !instance.secure_port.enabled {
+            return invalid("securePort", "must be enabled with ssl".to_string());
+        }
+
+        let lease_info = instance.lease_info.as_ref();
+        let duration = lease_info
+            .and_then(|lease_info| lease_info.duration_in_secs)
+            .unwrap_or(DEFAULT_LEASE_DURATION_IN_SECS);
+        if eureka.heartbeat_interval as u64 >= u64::from(duration) * 1000 {
+            return invalid(
+                "heartbeatInterval",
+                format!(
+                    "{} ms must be shorter than the {} s lease duration",
+                    eureka.heartbeat_interval, duration
+                ),
+            );
+        }
+        let renewal = lease_info.and_then(|lease_info| lease_info.renewal_interval_in_secs);
+        if let Some(renewal) = renewal {
+            if renewal >= duration {
+                return invalid(
+                    "leaseInfo",
+                    format!(
+                        "renewal interval of {} s must be shorter than the {} s lease duration",
+                        renewal, duration
+                    ),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::structures::LeaseInfo;
+
+    fn field(config: &BaseConfig) -> Option<&'static str> {
+        match config.validate() {
+            Err(EurekaError::InvalidConfig(field, _)) => Some(field),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = BaseConfig::default();
+        assert!(config.validate().is_ok());
+
+        config.eureka.ssl = true;
+        assert_eq!(field(&config), Some("securePort"));
+        config.instance.secure_port.enabled = true;
+        assert!(config.validate().is_ok());
+
+        config.instance.lease_info = Some(LeaseInfo {
+            duration_in_secs: Some(20),
+            ..LeaseInfo::default()
+        });
+        assert_eq!(field(&config), Some("heartbeatInterval"));
+        config.eureka.heartbeat_interval = 10_000;
+        assert!(config.validate().is_ok());
+
+        config.instance.app.clear();
+        assert_eq!(field(&config), Some("app"));
+        config.eureka.register_with_eureka = false;
+        assert!(config.validate().is_ok());
+    }
+}
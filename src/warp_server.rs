@@ -0,0 +1,57 @@
+//! Registration of a `warp` server while it serves, behind the `warp` feature
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+
+use tokio::net::TcpListener;
+use tokio::task::spawn_blocking;
+use tokio_stream::wrappers::TcpListenerStream;
+use warp::{Filter, Rejection, Reply};
+
+use crate::{BaseConfig, EurekaClient};
+
+/// Filter extracting the client, e.g. for handlers reading the registry with
+/// [`EurekaClient::dump_registry`]
+pub fn with_eureka(
+    client: Arc<EurekaClient>,
+) -> impl Filter<Extract = (Arc<EurekaClient>,), Error = Infallible> + Clone {
+    warp::any().map(move || Arc::clone(&client))
+}
+
+/// Serves the filter built by `app` on `listener`, registered with eureka while it serves
+///
+/// The instance registers with the port of `listener` before the server starts, `app` gets
+/// the client to pass to [`with_eureka`]. The instance is deregistered once the server shut
+/// down gracefully on `shutdown`.
+pub async fn serve_warp<A, F, R, S>(
+    mut config: BaseConfig,
+    listener: TcpListener,
+    app: A,
+    shutdown: S,
+) -> io::Result<()>
+where
+    A: FnOnce(Arc<EurekaClient>) -> F,
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+    S: Future<Output = ()> + Send + 'static,
+{
+    config.instance.port.value = listener.local_addr()?.port();
+    config.instance.port.enabled = true;
+    // Registration blocks until eureka answers
+    let client = spawn_blocking(move || {
+        let client = Arc::new(EurekaClient::new(config));
+        client.start();
+        client
+    })
+    .await
+    .map_err(io::Error::other)?;
+
+    warp::serve(app(Arc::clone(&client)))
+        .serve_incoming_with_graceful_shutdown(TcpListenerStream::new(listener), shutdown)
+        .await;
+    spawn_blocking(move || client.deregister())
+        .await
+        .map_err(io::Error::other)
+}
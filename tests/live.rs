@@ -0,0 +1,122 @@
+//! Contract tests against a live eureka server, e.g. Netflix Eureka or Spring Cloud Netflix
+//! Eureka, run with
+//! `EUREKA_URL=http://localhost:8761/eureka cargo test --features live-tests`
+//!
+//! Each test registers its own app and deregisters it, in XML and in JSON.
+#![cfg(feature = "live-tests")]
+
+extern crate eureka_client;
+extern crate reqwest;
+
+use std::env;
+use std::process;
+use std::sync::Arc;
+
+use eureka_client::{
+    Codec, EurekaApi, EurekaRestClient, Instance, JsonCodec, StatusType, XmlCodec,
+};
+
+fn service_url() -> String {
+    env::var("EUREKA_URL").expect("EUREKA_URL must be set for the live tests")
+}
+
+fn codecs() -> Vec<Arc<dyn Codec>> {
+    vec![Arc::new(XmlCodec), Arc::new(JsonCodec)]
+}
+
+fn client(codec: &Arc<dyn Codec>) -> EurekaRestClient {
+    EurekaRestClient::new(service_url()).with_codec(Arc::clone(codec))
+}
+
+/// Instance of an app unique to the test, codec and process
+fn instance(test: &str, codec: &Arc<dyn Codec>) -> Instance {
+    let format = if codec.content_type().contains("json") {
+        "json"
+    } else {
+        "xml"
+    };
+    let app = format!("EUREKA-RS-{}-{}-{}", test, format, process::id()).to_uppercase();
+    Instance::builder()
+        .app(&app)
+        .host_name("localhost")
+        .ip_addr("127.0.0.1")
+        .port(8080, true)
+        .status(StatusType::Up)
+        .metadata("zone", "live-tests")
+        .build()
+        .unwrap()
+}
+
+fn instance_id(instance: &Instance) -> &str {
+    instance.instance_id.as_deref().unwrap()
+}
+
+#[test]
+fn test_register_heartbeat_deregister() {
+    for codec in codecs() {
+        let client = client(&codec);
+        let instance = instance("lifecycle", &codec);
+        let id = instance_id(&instance);
+        client.register(&instance.app, &instance).unwrap();
+        client.send_heartbeat(&instance.app, id).unwrap();
+
+        let registered = client
+            .get_instance_by_app_and_instance(&instance.app, id)
+            .unwrap();
+        assert_eq!(registered.app, instance.app);
+        assert_eq!(registered.port, instance.port);
+        assert_eq!(registered.zone(), Some("live-tests"));
+
+        client.deregister(&instance.app, id).unwrap();
+        assert!(client.send_heartbeat(&instance.app, id).is_err());
+    }
+}
+
+#[test]
+fn test_status_and_metadata() {
+    for codec in codecs() {
+        let client = client(&codec);
+        let instance = instance("status", &codec);
+        let id = instance_id(&instance);
+        client.register(&instance.app, &instance).unwrap();
+
+        client
+            .update_status(&instance.app, id, StatusType::OutOfService)
+            .unwrap();
+        client
+            .update_metadata(&instance.app, id, "version", "1.0.0")
+            .unwrap();
+        let updated = client
+            .get_instance_by_app_and_instance(&instance.app, id)
+            .unwrap();
+        assert_eq!(updated.status, StatusType::OutOfService);
+        assert_eq!(updated.version(), Some("1.0.0"));
+
+        client.deregister(&instance.app, id).unwrap();
+    }
+}
+
+#[test]
+fn test_registry_and_delta() {
+    for codec in codecs() {
+        let client = client(&codec);
+        client.get_all_instances().unwrap();
+        for instance in client.stream_all_instances().unwrap() {
+            instance.unwrap();
+        }
+
+        // The client has no delta api yet, the document must parse all the same
+        let mut resp = reqwest::Client::new()
+            .get(&format!(
+                "{}/apps/delta",
+                service_url().trim_end_matches('/')
+            ))
+            .header(reqwest::header::ACCEPT, codec.content_type())
+            .send()
+            .unwrap();
+        assert!(resp.status().is_success());
+        let mut body = Vec::new();
+        resp.copy_to(&mut body).unwrap();
+        codec.decode_applications(&body).unwrap();
+    }
+}